@@ -6,7 +6,8 @@ use uuid::Uuid;
 use webthing::property::ValueForwarder;
 use webthing::server::ActionGenerator;
 use webthing::{
-    Action, BaseAction, BaseEvent, BaseProperty, BaseThing, Thing, ThingsType, WebThingServer,
+    Action, BaseAction, BaseEvent, BaseProperty, BaseThing, Clock, Thing, ThingsType,
+    WebThingServer,
 };
 
 pub struct FadeAction(BaseAction);
@@ -30,6 +31,10 @@ impl Action for FadeAction {
         self.0.set_href_prefix(prefix)
     }
 
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.0.set_clock(clock)
+    }
+
     fn get_id(&self) -> String {
         self.0.get_id()
     }
@@ -282,7 +287,7 @@ async fn main() -> std::io::Result<()> {
 
             {
                 let mut t = t.write().unwrap();
-                let prop = t.find_property(&"level".to_owned()).unwrap();
+                let prop = t.find_property("level").unwrap();
                 let _ = prop.set_cached_value(new_value.clone());
             }
 
@@ -303,5 +308,5 @@ async fn main() -> std::io::Result<()> {
         None,
         None,
     );
-    server.start(None).await
+    server.start(None)?.await
 }
@@ -3,7 +3,8 @@ use std::sync::{Arc, RwLock, Weak};
 use std::{thread, time};
 use uuid::Uuid;
 use webthing::{
-    Action, BaseAction, BaseEvent, BaseProperty, BaseThing, Thing, ThingsType, WebThingServer,
+    Action, BaseAction, BaseEvent, BaseProperty, BaseThing, Clock, Thing, ThingsType,
+    WebThingServer,
 };
 
 use webthing::server::ActionGenerator;
@@ -29,6 +30,10 @@ impl Action for FadeAction {
         self.0.set_href_prefix(prefix)
     }
 
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.0.set_clock(clock)
+    }
+
     fn get_id(&self) -> String {
         self.0.get_id()
     }
@@ -224,5 +229,5 @@ async fn main() -> std::io::Result<()> {
         None,
         None,
     );
-    server.start(None).await
+    server.start(None)?.await
 }
@@ -3,8 +3,8 @@ use serde_json::json;
 use std::marker::{Send, Sync};
 use std::sync::{Arc, RwLock, Weak};
 
+use super::clock::{Clock, SystemClock};
 use super::thing::Thing;
-use super::utils::timestamp;
 
 /// High-level Action trait.
 pub trait Action: Send + Sync {
@@ -33,6 +33,12 @@ pub trait Action: Send + Sync {
     /// Set the prefix of any hrefs associated with this action.
     fn set_href_prefix(&mut self, prefix: String);
 
+    /// Set the clock timestamping this action.
+    ///
+    /// Called with the thing's clock when the action is added to a thing that has one. Actions
+    /// wrapping a `BaseAction` should forward this to it.
+    fn set_clock(&mut self, _clock: Arc<dyn Clock>) {}
+
     /// Get this action's ID.
     fn get_id(&self) -> String;
 
@@ -64,6 +70,11 @@ pub trait Action: Send + Sync {
     fn start(&mut self);
 
     /// Override this with the code necessary to perform the action.
+    ///
+    /// The server calls this with only the action locked, so requests to the rest of the thing
+    /// are served meanwhile. It must not wait for the thing's lock, which is taken before the
+    /// action's elsewhere, and it holds up the request, so work that is long-running or needs
+    /// the thing should be moved to another thread.
     fn perform_action(&mut self);
 
     /// Override this with the code necessary to cancel the action.
@@ -88,6 +99,7 @@ pub struct BaseAction {
     time_requested: String,
     time_completed: Option<String>,
     thing: Weak<RwLock<Box<dyn Thing>>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl BaseAction {
@@ -99,6 +111,7 @@ impl BaseAction {
         thing: Weak<RwLock<Box<dyn Thing>>>,
     ) -> Self {
         let href = format!("/actions/{}/{}", name, id);
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock::new());
 
         Self {
             id,
@@ -107,9 +120,10 @@ impl BaseAction {
             href_prefix: "".to_owned(),
             href,
             status: "created".to_owned(),
-            time_requested: timestamp(),
+            time_requested: clock.timestamp(),
             time_completed: None,
             thing,
+            clock,
         }
     }
 }
@@ -121,6 +135,12 @@ impl Action for BaseAction {
         self.href_prefix = prefix;
     }
 
+    /// Set the clock timestamping this action, restamping the time it was requested.
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.time_requested = clock.timestamp();
+        self.clock = clock;
+    }
+
     /// Get this action's ID.
     fn get_id(&self) -> String {
         self.id.clone()
@@ -180,6 +200,6 @@ impl Action for BaseAction {
     /// Finish performing the action.
     fn finish(&mut self) {
         self.set_status("completed".to_owned());
-        self.time_completed = Some(timestamp());
+        self.time_completed = Some(self.clock.timestamp());
     }
 }
@@ -0,0 +1,418 @@
+//! Serve a simulated thing from a Thing Description file.
+//!
+//! Usage: `webthing-sim <description.json> [--config <config.json>] [--port <port>]`
+//!
+//! The optional configuration file scripts the thing's behaviour:
+//!
+//! ```json
+//! {
+//!     "properties": {
+//!         "temperature": {"randomWalk": {"step": 0.5, "interval": 2000, "minimum": 15}}
+//!     },
+//!     "events": {
+//!         "overheated": {"interval": 30000, "data": 102}
+//!     },
+//!     "actions": {
+//!         "fade": {"delay": 1000, "set": {"brightness": {"input": "/brightness"}, "on": true}}
+//!     }
+//! }
+//! ```
+//!
+//! Random walks default to the property's `minimum` and `maximum`, events are raised every
+//! `interval` milliseconds, and actions complete after `delay` milliseconds, setting properties
+//! to fixed values or to members of their input, given as JSON pointers.
+
+use rand::Rng;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use webthing::server::ActionGenerator;
+use webthing::utils::{read_lock, write_lock};
+use webthing::{
+    Action, BaseAction, BaseEvent, BaseThing, Clock, Thing, ThingsType, WebThingServer,
+};
+
+const USAGE: &str =
+    "Usage: webthing-sim <description.json> [--config <config.json>] [--port <port>]";
+
+/// Default interval between random walk steps and periodic events.
+const DEFAULT_INTERVAL: u64 = 1000;
+
+/// Command-line arguments.
+struct Args {
+    description: String,
+    config: Option<String>,
+    port: u16,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut description = None;
+        let mut config = None;
+        let mut port = 8888;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => config = Some(args.next().ok_or("Missing config file")?),
+                "--port" => {
+                    port = args
+                        .next()
+                        .and_then(|p| p.parse().ok())
+                        .ok_or("Invalid port")?
+                }
+                "-h" | "--help" => return Err(USAGE.to_owned()),
+                _ if description.is_none() && !arg.starts_with('-') => description = Some(arg),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        Ok(Args {
+            description: description.ok_or(USAGE)?,
+            config,
+            port,
+        })
+    }
+}
+
+/// Read a JSON file.
+fn read_json(path: &str) -> io::Result<serde_json::Value> {
+    let file = File::open(path)?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Get a member of a configuration entry as a number of milliseconds.
+fn millis(config: &serde_json::Value, key: &str) -> Duration {
+    Duration::from_millis(
+        config
+            .get(key)
+            .and_then(|i| i.as_u64())
+            .unwrap_or(DEFAULT_INTERVAL),
+    )
+}
+
+/// Get a member of a configuration entry as a map.
+fn members<'a>(
+    config: &'a serde_json::Value,
+    key: &str,
+) -> impl Iterator<Item = (&'a String, &'a serde_json::Value)> {
+    config
+        .get(key)
+        .and_then(|m| m.as_object())
+        .into_iter()
+        .flatten()
+}
+
+/// Set a property of a simulated thing, regardless of whether it is read-only.
+fn update_property(thing: &mut dyn Thing, name: &str, value: serde_json::Value) {
+    match thing.find_property(name) {
+        Some(property) => {
+            let _ = property.set_cached_value(value.clone());
+        }
+        None => {
+            eprintln!("Unknown property: {}", name);
+            return;
+        }
+    }
+
+    thing.property_notify(name.to_owned(), value);
+}
+
+/// Move a numeric property by a random step within its bounds, periodically.
+///
+/// Fails if the step is negative or the bounds are reversed, which would make the walk panic.
+fn random_walk(
+    thing: Arc<RwLock<Box<dyn Thing>>>,
+    name: String,
+    config: &serde_json::Value,
+) -> Result<(), String> {
+    let metadata = {
+        let mut thing = write_lock(&thing);
+        match thing.find_property(&name) {
+            Some(property) => property.get_metadata(),
+            None => {
+                eprintln!("Unknown property: {}", name);
+                return Ok(());
+            }
+        }
+    };
+
+    let bound = |key: &str| {
+        config
+            .get(key)
+            .or_else(|| metadata.get(key))
+            .and_then(|b| b.as_f64())
+    };
+    let minimum = bound("minimum").unwrap_or(f64::MIN);
+    let maximum = bound("maximum").unwrap_or(f64::MAX);
+    let step = config.get("step").and_then(|s| s.as_f64()).unwrap_or(
+        if minimum > f64::MIN && maximum < f64::MAX {
+            (maximum - minimum) / 20.0
+        } else {
+            1.0
+        },
+    );
+    let integer = metadata.get("type").and_then(|t| t.as_str()) == Some("integer");
+    let interval = millis(config, "interval");
+
+    if minimum > maximum {
+        return Err(format!(
+            "Invalid random walk bounds for {}: minimum {} is above maximum {}",
+            name, minimum, maximum
+        ));
+    }
+    if !(step >= 0.0 && step.is_finite()) {
+        return Err(format!("Invalid random walk step for {}: {}", name, step));
+    }
+
+    thread::spawn(move || {
+        let mut rng = rand::thread_rng();
+
+        loop {
+            thread::sleep(interval);
+
+            let mut thing = write_lock(&thing);
+            let current = thing
+                .get_property(&name)
+                .and_then(|v| v.as_f64())
+                .unwrap_or(minimum.max(0.0).min(maximum));
+            let mut value = (current + rng.gen_range(-step..=step)).clamp(minimum, maximum);
+            if integer {
+                value = value.round();
+            }
+
+            let value = if integer {
+                json!(value as i64)
+            } else {
+                json!(value)
+            };
+            update_property(&mut **thing, &name, value);
+        }
+    });
+
+    Ok(())
+}
+
+/// Raise an event periodically.
+fn periodic_event(thing: Arc<RwLock<Box<dyn Thing>>>, name: String, config: &serde_json::Value) {
+    let data = config.get("data").cloned();
+    let interval = millis(config, "interval");
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        write_lock(&thing).add_event(Box::new(BaseEvent::new(name.clone(), data.clone())));
+    });
+}
+
+/// Scripted behaviour of an action.
+#[derive(Clone, Default)]
+struct ActionBehaviour {
+    delay: Duration,
+    set: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ActionBehaviour {
+    fn new(config: &serde_json::Value) -> Self {
+        ActionBehaviour {
+            delay: Duration::from_millis(config.get("delay").and_then(|d| d.as_u64()).unwrap_or(0)),
+            set: config
+                .get("set")
+                .and_then(|s| s.as_object())
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Resolve the value to set a property to, taking it from the action's input if requested.
+    fn resolve(
+        value: &serde_json::Value,
+        input: &Option<serde_json::Map<String, serde_json::Value>>,
+    ) -> Option<serde_json::Value> {
+        match value.get("input").and_then(|p| p.as_str()) {
+            Some(pointer) => input
+                .as_ref()
+                .and_then(|input| json!(input).pointer(pointer).cloned()),
+            None => Some(value.clone()),
+        }
+    }
+}
+
+/// An action which completes after a delay, setting properties.
+struct SimAction {
+    action: BaseAction,
+    behaviour: ActionBehaviour,
+}
+
+impl Action for SimAction {
+    fn set_href_prefix(&mut self, prefix: String) {
+        self.action.set_href_prefix(prefix)
+    }
+
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.action.set_clock(clock)
+    }
+
+    fn get_id(&self) -> String {
+        self.action.get_id()
+    }
+
+    fn get_name(&self) -> String {
+        self.action.get_name()
+    }
+
+    fn get_href(&self) -> String {
+        self.action.get_href()
+    }
+
+    fn get_status(&self) -> String {
+        self.action.get_status()
+    }
+
+    fn get_time_requested(&self) -> String {
+        self.action.get_time_requested()
+    }
+
+    fn get_time_completed(&self) -> Option<String> {
+        self.action.get_time_completed()
+    }
+
+    fn get_input(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        self.action.get_input()
+    }
+
+    fn get_thing(&self) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.action.get_thing()
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.action.set_status(status)
+    }
+
+    fn start(&mut self) {
+        self.action.start()
+    }
+
+    fn perform_action(&mut self) {
+        let thing = match self.get_thing() {
+            Some(thing) => thing,
+            None => return,
+        };
+
+        let input = self.get_input();
+        let name = self.get_name();
+        let id = self.get_id();
+        let behaviour = self.behaviour.clone();
+
+        thread::spawn(move || {
+            thread::sleep(behaviour.delay);
+
+            // Actions deleted or cancelled while waiting must leave the thing alone.
+            let mut thing = write_lock(&thing);
+            let cancelled = match thing.get_action(name.clone(), id.clone()) {
+                Some(action) => read_lock(&action).get_status() == "cancelled",
+                None => true,
+            };
+            if cancelled {
+                return;
+            }
+
+            for (property, value) in &behaviour.set {
+                if let Some(value) = ActionBehaviour::resolve(value, &input) {
+                    update_property(&mut **thing, property, value);
+                }
+            }
+
+            thing.finish_action(name, id);
+        });
+    }
+
+    fn cancel(&mut self) {
+        self.action.cancel()
+    }
+
+    fn finish(&mut self) {
+        self.action.finish()
+    }
+}
+
+/// Generates actions according to their configured behaviour.
+struct Generator(HashMap<String, ActionBehaviour>);
+
+impl ActionGenerator for Generator {
+    fn generate(
+        &self,
+        thing: Weak<RwLock<Box<dyn Thing>>>,
+        name: String,
+        input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+        let input = match input {
+            Some(serde_json::Value::Object(input)) => Some(input.clone()),
+            Some(_) => return None,
+            None => None,
+        };
+
+        let behaviour = self.0.get(&name).cloned().unwrap_or_default();
+        Some(Box::new(SimAction {
+            action: BaseAction::new(Uuid::new_v4().to_string(), name, input, thing),
+            behaviour,
+        }))
+    }
+}
+
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    env_logger::init();
+
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    let description = read_json(&args.description)?;
+    let config = match args.config {
+        Some(ref path) => read_json(path)?,
+        None => json!({}),
+    };
+
+    let thing = BaseThing::from_description(&description)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let thing: Arc<RwLock<Box<dyn Thing>>> = Arc::new(RwLock::new(Box::new(thing)));
+
+    for (name, property) in members(&config, "properties") {
+        if let Some(walk) = property.get("randomWalk") {
+            if let Err(message) = random_walk(thing.clone(), name.to_owned(), walk) {
+                eprintln!("{}", message);
+                process::exit(2);
+            }
+        }
+    }
+
+    for (name, event) in members(&config, "events") {
+        periodic_event(thing.clone(), name.to_owned(), event);
+    }
+
+    let behaviours = members(&config, "actions")
+        .map(|(name, action)| (name.to_owned(), ActionBehaviour::new(action)))
+        .collect();
+
+    let mut server = WebThingServer::new(
+        ThingsType::Single(thing),
+        Some(args.port),
+        None,
+        None,
+        Box::new(Generator(behaviours)),
+        None,
+        None,
+    );
+    server.start(None)?.await
+}
@@ -0,0 +1,418 @@
+use serde_json;
+
+use super::description::ValidationError;
+
+/// Vocabularies of the WebThings capability schemas, current and legacy.
+const VOCABULARIES: &[&str] = &[
+    "https://webthings.io/schemas",
+    "https://iot.mozilla.org/schemas",
+];
+
+/// JSON types accepted for numeric property types.
+const NUMBER: &[&str] = &["number", "integer"];
+
+/// A thing capability, i.e. `OnOffSwitch`.
+#[derive(Debug)]
+pub struct Capability {
+    /// Name of the capability.
+    pub name: &'static str,
+    /// Property types the thing must have; each entry is satisfied by any one of its types.
+    pub required_properties: &'static [&'static [&'static str]],
+}
+
+/// A property type, i.e. `OnOffProperty`.
+#[derive(Debug)]
+pub struct PropertyType {
+    /// Name of the property type.
+    pub name: &'static str,
+    /// JSON types a property of this type may have.
+    pub types: &'static [&'static str],
+    /// Lowest value allowed, if any.
+    pub minimum: Option<f64>,
+    /// Highest value allowed, if any.
+    pub maximum: Option<f64>,
+    /// Values allowed, if restricted.
+    pub values: Option<&'static [&'static str]>,
+}
+
+macro_rules! capability {
+    ($name:expr, [$($required:expr),*]) => {
+        Capability {
+            name: $name,
+            required_properties: &[$($required),*],
+        }
+    };
+}
+
+macro_rules! property_type {
+    ($name:expr, $types:expr) => {
+        property_type!($name, $types, None, None, None)
+    };
+    ($name:expr, $types:expr, $minimum:expr, $maximum:expr, $values:expr) => {
+        PropertyType {
+            name: $name,
+            types: $types,
+            minimum: $minimum,
+            maximum: $maximum,
+            values: $values,
+        }
+    };
+}
+
+/// Thing capabilities of the WebThings schemas.
+pub const CAPABILITIES: &[Capability] = &[
+    capability!("Alarm", [&["AlarmProperty"]]),
+    capability!(
+        "AirQualitySensor",
+        [&["ConcentrationProperty", "DensityProperty"]]
+    ),
+    capability!(
+        "BarometricPressureSensor",
+        [&["BarometricPressureProperty"]]
+    ),
+    capability!("BinarySensor", [&["BooleanProperty"]]),
+    capability!("Camera", [&["ImageProperty"]]),
+    capability!(
+        "ColorControl",
+        [&["ColorProperty", "ColorTemperatureProperty"]]
+    ),
+    capability!("ColorSensor", [&["ColorProperty"]]),
+    capability!("DoorSensor", [&["OpenProperty"]]),
+    capability!("EnergyMonitor", [&["InstantaneousPowerProperty"]]),
+    capability!("HumiditySensor", [&["HumidityProperty"]]),
+    capability!("LeakSensor", [&["LeakProperty"]]),
+    capability!("Light", [&["OnOffProperty"]]),
+    capability!("Lock", [&["LockedProperty"]]),
+    capability!("MotionSensor", [&["MotionProperty"]]),
+    capability!("MultiLevelSensor", [&["LevelProperty"]]),
+    capability!("MultiLevelSwitch", [&["LevelProperty"]]),
+    capability!("OnOffSwitch", [&["OnOffProperty"]]),
+    capability!("PushButton", [&["PushedProperty"]]),
+    capability!("SmartPlug", [&["OnOffProperty"]]),
+    capability!("SmokeSensor", [&["SmokeProperty"]]),
+    capability!("TemperatureSensor", [&["TemperatureProperty"]]),
+    capability!(
+        "Thermostat",
+        [&["TemperatureProperty"], &["TargetTemperatureProperty"]]
+    ),
+    capability!("VideoCamera", [&["VideoProperty"]]),
+];
+
+/// Property types of the WebThings schemas.
+pub const PROPERTY_TYPES: &[PropertyType] = &[
+    property_type!("AlarmProperty", &["boolean"]),
+    property_type!("BarometricPressureProperty", NUMBER),
+    property_type!("BooleanProperty", &["boolean"]),
+    property_type!(
+        "BrightnessProperty",
+        &["integer"],
+        Some(0.0),
+        Some(100.0),
+        None
+    ),
+    property_type!(
+        "ColorModeProperty",
+        &["string"],
+        None,
+        None,
+        Some(&["color", "temperature"])
+    ),
+    property_type!("ColorProperty", &["string"]),
+    property_type!("ColorTemperatureProperty", NUMBER, Some(0.0), None, None),
+    property_type!("ConcentrationProperty", NUMBER, Some(0.0), None, None),
+    property_type!("CurrentProperty", NUMBER),
+    property_type!("DensityProperty", NUMBER, Some(0.0), None, None),
+    property_type!("FrequencyProperty", NUMBER, Some(0.0), None, None),
+    property_type!(
+        "HeatingCoolingProperty",
+        &["string"],
+        None,
+        None,
+        Some(&["off", "heating", "cooling"])
+    ),
+    property_type!("HumidityProperty", NUMBER, Some(0.0), Some(100.0), None),
+    property_type!("ImageProperty", &["null"]),
+    property_type!(
+        "InstantaneousPowerFactorProperty",
+        NUMBER,
+        Some(-1.0),
+        Some(1.0),
+        None
+    ),
+    property_type!("InstantaneousPowerProperty", NUMBER),
+    property_type!("LeakProperty", &["boolean"]),
+    property_type!("LevelProperty", NUMBER),
+    property_type!(
+        "LockedProperty",
+        &["string"],
+        None,
+        None,
+        Some(&["locked", "unlocked", "jammed", "unknown"])
+    ),
+    property_type!("MotionProperty", &["boolean"]),
+    property_type!("OnOffProperty", &["boolean"]),
+    property_type!("OpenProperty", &["boolean"]),
+    property_type!("PushedProperty", &["boolean"]),
+    property_type!("SmokeProperty", &["boolean"]),
+    property_type!("TargetTemperatureProperty", NUMBER),
+    property_type!("TemperatureProperty", NUMBER),
+    property_type!(
+        "ThermostatModeProperty",
+        &["string"],
+        None,
+        None,
+        Some(&["off", "heat", "cool", "auto", "dry", "wind"])
+    ),
+    property_type!("VideoProperty", &["null"]),
+    property_type!("VoltageProperty", NUMBER),
+];
+
+/// Action types of the WebThings schemas.
+pub const ACTION_TYPES: &[&str] = &["FadeAction", "LockAction", "ToggleAction", "UnlockAction"];
+
+/// Event types of the WebThings schemas.
+pub const EVENT_TYPES: &[&str] = &[
+    "AlarmEvent",
+    "DoublePressedEvent",
+    "LongPressedEvent",
+    "OverheatedEvent",
+    "PressedEvent",
+    "ReleasedEvent",
+];
+
+/// Find a thing capability by name.
+pub fn find_capability(name: &str) -> Option<&'static Capability> {
+    CAPABILITIES.iter().find(|c| c.name == name)
+}
+
+/// Find a property type by name.
+pub fn find_property_type(name: &str) -> Option<&'static PropertyType> {
+    PROPERTY_TYPES.iter().find(|p| p.name == name)
+}
+
+/// Terms of a description which refer to the WebThings schemas.
+struct Vocabulary {
+    plain: bool,
+    prefixes: Vec<String>,
+}
+
+impl Vocabulary {
+    /// Find out how a description's `@context` refers to the WebThings schemas.
+    fn new(context: Option<&serde_json::Value>) -> Self {
+        fn is_webthings(value: &serde_json::Value) -> bool {
+            value.as_str().is_some_and(|uri| {
+                VOCABULARIES
+                    .iter()
+                    .any(|vocabulary| uri.trim_end_matches(['/', '#']) == *vocabulary)
+            })
+        }
+
+        let mut vocabulary = Vocabulary {
+            plain: false,
+            prefixes: Vec::new(),
+        };
+
+        let entries = match context {
+            Some(serde_json::Value::Array(entries)) => entries.iter().collect(),
+            Some(entry) => vec![entry],
+            None => Vec::new(),
+        };
+
+        for entry in entries {
+            if is_webthings(entry) {
+                vocabulary.plain = true;
+            } else if let Some(namespaces) = entry.as_object() {
+                for (prefix, uri) in namespaces {
+                    if is_webthings(uri) {
+                        vocabulary.prefixes.push(prefix.to_owned());
+                    }
+                }
+            }
+        }
+
+        vocabulary
+    }
+
+    /// Get the WebThings term a semantic type refers to, if any.
+    fn term<'a>(&self, type_: &'a str) -> Option<&'a str> {
+        match type_.split_once(':') {
+            Some((prefix, term)) if self.prefixes.iter().any(|p| p == prefix) => Some(term),
+            Some(_) => None,
+            None if self.plain => Some(type_),
+            None => None,
+        }
+    }
+
+    /// Get the WebThings terms of a `@type` member.
+    fn terms<'a>(&self, types: Option<&'a serde_json::Value>) -> Vec<&'a str> {
+        let types = match types {
+            Some(serde_json::Value::Array(types)) => {
+                types.iter().filter_map(|t| t.as_str()).collect()
+            }
+            Some(serde_json::Value::String(type_)) => vec![type_.as_str()],
+            _ => Vec::new(),
+        };
+
+        types.into_iter().filter_map(|t| self.term(t)).collect()
+    }
+}
+
+/// Check a property against the constraints of its property type.
+fn validate_property(
+    path: &str,
+    property_type: &PropertyType,
+    property: &serde_json::Map<String, serde_json::Value>,
+    errors: &mut Vec<ValidationError>,
+) {
+    let mut error = |message: String| {
+        errors.push(ValidationError {
+            path: path.to_owned(),
+            message,
+        })
+    };
+
+    match property.get("type").and_then(|t| t.as_str()) {
+        Some(type_) if property_type.types.contains(&type_) => (),
+        _ => error(format!(
+            "{} must be of type {}",
+            property_type.name,
+            property_type.types.join(" or ")
+        )),
+    }
+
+    // A missing bound lets any value through, so it can't be within the type's range either.
+    if let Some(minimum) = property_type.minimum {
+        match property.get("minimum").and_then(|m| m.as_f64()) {
+            Some(value) if value >= minimum => (),
+            _ => error(format!(
+                "{} must have a minimum of at least {}",
+                property_type.name, minimum
+            )),
+        }
+    }
+
+    if let Some(maximum) = property_type.maximum {
+        match property.get("maximum").and_then(|m| m.as_f64()) {
+            Some(value) if value <= maximum => (),
+            _ => error(format!(
+                "{} must have a maximum of at most {}",
+                property_type.name, maximum
+            )),
+        }
+    }
+
+    if let Some(values) = property_type.values {
+        if let Some(enum_) = property.get("enum").and_then(|e| e.as_array()) {
+            for value in enum_ {
+                if !value.as_str().is_some_and(|v| values.contains(&v)) {
+                    error(format!(
+                        "{} does not allow the value {}, expected one of {}",
+                        property_type.name,
+                        value,
+                        values.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Validate the capabilities declared by a WebThings Thing Description.
+///
+/// Checks that the thing's capabilities are satisfied by its properties, and that properties,
+/// actions and events match their semantic types. Only types from the WebThings schemas are
+/// checked; descriptions which do not use that vocabulary always pass.
+///
+/// # Arguments
+///
+/// * `description` - description as returned by `Thing::as_thing_description()`
+pub fn validate(
+    description: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), Vec<ValidationError>> {
+    let vocabulary = Vocabulary::new(description.get("@context"));
+    let mut errors = Vec::new();
+
+    let empty = serde_json::Map::new();
+    let members = |key: &str| {
+        description
+            .get(key)
+            .and_then(|m| m.as_object())
+            .unwrap_or(&empty)
+    };
+
+    let mut property_types = Vec::new();
+    for (name, property) in members("properties") {
+        let path = format!("/properties/{}", name);
+        let property = match property.as_object() {
+            Some(property) => property,
+            None => continue,
+        };
+
+        for term in vocabulary.terms(property.get("@type")) {
+            match find_property_type(term) {
+                Some(property_type) => {
+                    validate_property(&path, property_type, property, &mut errors);
+                    property_types.push(term);
+                }
+                None => errors.push(ValidationError {
+                    path: format!("{}/@type", path),
+                    message: format!("Unknown property type {}", term),
+                }),
+            }
+        }
+    }
+
+    for (name, action) in members("actions") {
+        for term in vocabulary.terms(action.get("@type")) {
+            if !ACTION_TYPES.contains(&term) {
+                errors.push(ValidationError {
+                    path: format!("/actions/{}/@type", name),
+                    message: format!("Unknown action type {}", term),
+                });
+            }
+        }
+    }
+
+    for (name, event) in members("events") {
+        for term in vocabulary.terms(event.get("@type")) {
+            if !EVENT_TYPES.contains(&term) {
+                errors.push(ValidationError {
+                    path: format!("/events/{}/@type", name),
+                    message: format!("Unknown event type {}", term),
+                });
+            }
+        }
+    }
+
+    for term in vocabulary.terms(description.get("@type")) {
+        let capability = match find_capability(term) {
+            Some(capability) => capability,
+            None => {
+                errors.push(ValidationError {
+                    path: "/@type".to_owned(),
+                    message: format!("Unknown capability {}", term),
+                });
+                continue;
+            }
+        };
+
+        for required in capability.required_properties {
+            if !required.iter().any(|r| property_types.contains(r)) {
+                errors.push(ValidationError {
+                    path: "/properties".to_owned(),
+                    message: format!(
+                        "{} requires a property with @type {}",
+                        capability.name,
+                        required.join(" or ")
+                    ),
+                });
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
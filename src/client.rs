@@ -0,0 +1,704 @@
+use futures::stream::{self, BoxStream};
+use futures::{SinkExt, Stream, StreamExt};
+use serde_json;
+use serde_json::json;
+use std::error::Error;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+/// Default time to wait before reconnecting a WebSocket.
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest time to wait before reconnecting a WebSocket.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Errors returned by a `ThingClient`.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The HTTP request failed.
+    Http(reqwest::Error),
+    /// The server answered with an unexpected status code.
+    Status(u16),
+    /// The WebSocket connection failed.
+    WebSocket(tungstenite::Error),
+    /// The URL could not be parsed.
+    Url(url::ParseError),
+    /// The thing has no such property, action or event.
+    Unknown(String),
+    /// The server's response could not be understood.
+    InvalidResponse(&'static str),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "HTTP request failed: {}", err),
+            ClientError::Status(status) => write!(f, "Unexpected status code: {}", status),
+            ClientError::WebSocket(err) => write!(f, "WebSocket connection failed: {}", err),
+            ClientError::Url(err) => write!(f, "Invalid URL: {}", err),
+            ClientError::Unknown(name) => write!(f, "Unknown interaction: {}", name),
+            ClientError::InvalidResponse(message) => write!(f, "Invalid response: {}", message),
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::Http(err) => Some(err),
+            ClientError::WebSocket(err) => Some(err),
+            ClientError::Url(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        ClientError::Http(err)
+    }
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(err: tungstenite::Error) -> Self {
+        ClientError::WebSocket(err)
+    }
+}
+
+impl From<url::ParseError> for ClientError {
+    fn from(err: url::ParseError) -> Self {
+        ClientError::Url(err)
+    }
+}
+
+/// Get the single name and value of a `{name: {...}}` description.
+fn named_entry(
+    description: &serde_json::Value,
+) -> Result<(&String, &serde_json::Map<String, serde_json::Value>), ClientError> {
+    description
+        .as_object()
+        .filter(|description| description.len() == 1)
+        .and_then(|description| description.iter().next())
+        .and_then(|(name, inner)| inner.as_object().map(|inner| (name, inner)))
+        .ok_or(ClientError::InvalidResponse(
+            "Expected a single named entry",
+        ))
+}
+
+/// Get a list of descriptions from a response.
+fn entries(value: serde_json::Value) -> Result<Vec<serde_json::Value>, ClientError> {
+    match value {
+        serde_json::Value::Array(entries) => Ok(entries),
+        _ => Err(ClientError::InvalidResponse("Expected an array")),
+    }
+}
+
+/// An action request, as reported by the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionRequest {
+    /// Name of the action.
+    pub name: String,
+    /// ID of the request.
+    pub id: String,
+    /// Href of the request.
+    pub href: String,
+    /// Status of the request, i.e. `pending` or `completed`.
+    pub status: String,
+    /// Input of the request, if any.
+    pub input: Option<serde_json::Value>,
+    /// Time the action was requested.
+    pub time_requested: Option<String>,
+    /// Time the action was completed, if it has been.
+    pub time_completed: Option<String>,
+}
+
+impl ActionRequest {
+    /// Parse an action description, i.e. `{"fade": {"href": ..., "status": ...}}`.
+    pub fn from_description(description: &serde_json::Value) -> Result<Self, ClientError> {
+        let (name, inner) = named_entry(description)?;
+        let string = |key: &str| {
+            inner
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_owned())
+        };
+
+        let href = string("href").ok_or(ClientError::InvalidResponse("Action has no href"))?;
+        Ok(ActionRequest {
+            name: name.to_owned(),
+            id: href.rsplit('/').next().unwrap_or_default().to_owned(),
+            status: string("status").unwrap_or_default(),
+            input: inner.get("input").cloned(),
+            time_requested: string("timeRequested"),
+            time_completed: string("timeCompleted"),
+            href,
+        })
+    }
+}
+
+/// An event, as reported by the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventRecord {
+    /// Name of the event.
+    pub name: String,
+    /// Data of the event, if any.
+    pub data: Option<serde_json::Value>,
+    /// Time the event occurred.
+    pub timestamp: Option<String>,
+}
+
+impl EventRecord {
+    /// Parse an event description, i.e. `{"overheated": {"data": 102, "timestamp": ...}}`.
+    pub fn from_description(description: &serde_json::Value) -> Result<Self, ClientError> {
+        let (name, inner) = named_entry(description)?;
+        Ok(EventRecord {
+            name: name.to_owned(),
+            data: inner.get("data").cloned(),
+            timestamp: inner
+                .get("timestamp")
+                .and_then(|t| t.as_str())
+                .map(|t| t.to_owned()),
+        })
+    }
+}
+
+/// A notification received over a thing's WebSocket.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Notification {
+    /// The WebSocket was connected or reconnected, and event subscriptions were sent.
+    ///
+    /// Changes which happened while disconnected are not replayed.
+    Connected,
+    /// Properties changed to the given values.
+    PropertyStatus(serde_json::Map<String, serde_json::Value>),
+    /// The status of an action request changed.
+    ActionStatus(ActionRequest),
+    /// A subscribed event occurred.
+    Event(EventRecord),
+    /// The server reported an error, i.e. for an invalid message.
+    Error(serde_json::Value),
+    /// The WebSocket handshake failed in a way reconnecting won't fix, i.e. the server doesn't
+    /// speak the requested subprotocol or the TLS certificate isn't trusted.
+    ///
+    /// This is the last notification of the observation.
+    Rejected(String),
+}
+
+impl Notification {
+    /// Parse a WebSocket message.
+    fn from_message(text: &str) -> Option<Self> {
+        let message: serde_json::Value = serde_json::from_str(text).ok()?;
+        let data = message.get("data")?;
+
+        match message.get("messageType")?.as_str()? {
+            "propertyStatus" => Some(Notification::PropertyStatus(data.as_object()?.clone())),
+            "actionStatus" => ActionRequest::from_description(data)
+                .ok()
+                .map(Notification::ActionStatus),
+            "event" => EventRecord::from_description(data)
+                .ok()
+                .map(Notification::Event),
+            "error" => Some(Notification::Error(data.clone())),
+            _ => None,
+        }
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Determine whether a WebSocket connection error would happen again on reconnecting.
+///
+/// Unreachable servers and error responses, i.e. for a thing that is being replaced, are
+/// retried.
+fn is_permanent(err: &ClientError) -> bool {
+    matches!(
+        err,
+        ClientError::WebSocket(
+            tungstenite::Error::Protocol(_)
+                | tungstenite::Error::Tls(_)
+                | tungstenite::Error::Url(_)
+        ) | ClientError::InvalidResponse(_)
+    )
+}
+
+/// State of an observation's WebSocket.
+struct ObservationState {
+    url: Url,
+    subprotocol: Option<String>,
+    events: Vec<String>,
+    socket: Option<Socket>,
+    delay: Duration,
+    reconnect_delay: Duration,
+    attempted: bool,
+    rejected: bool,
+}
+
+impl ObservationState {
+    /// Connect the WebSocket and subscribe to the events.
+    async fn connect(&self) -> Result<Socket, ClientError> {
+        let mut request = self.url.as_str().into_client_request()?;
+        if let Some(ref subprotocol) = self.subprotocol {
+            let value = HeaderValue::from_str(subprotocol)
+                .map_err(|_| ClientError::InvalidResponse("Invalid WebSocket subprotocol"))?;
+            request
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", value);
+        }
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+
+        if !self.events.is_empty() {
+            let mut data = serde_json::Map::new();
+            for event in &self.events {
+                data.insert(event.to_owned(), json!({}));
+            }
+
+            let message = json!({"messageType": "addEventSubscription", "data": data});
+            socket.send(Message::text(message.to_string())).await?;
+        }
+
+        Ok(socket)
+    }
+
+    /// Wait for the next notification, reconnecting as needed.
+    async fn next(mut self) -> Option<(Notification, Self)> {
+        if self.rejected {
+            return None;
+        }
+
+        loop {
+            let socket = match self.socket {
+                Some(ref mut socket) => socket,
+                None => {
+                    if self.attempted {
+                        tokio::time::sleep(self.delay).await;
+                        self.delay = (self.delay * 2).min(MAX_RECONNECT_DELAY);
+                    }
+                    self.attempted = true;
+
+                    match self.connect().await {
+                        Ok(socket) => {
+                            self.socket = Some(socket);
+                            self.delay = self.reconnect_delay;
+                            return Some((Notification::Connected, self));
+                        }
+                        Err(err) if is_permanent(&err) => {
+                            self.rejected = true;
+                            return Some((Notification::Rejected(err.to_string()), self));
+                        }
+                        Err(_) => continue,
+                    }
+                }
+            };
+
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    if let Some(notification) = Notification::from_message(text.as_str()) {
+                        return Some((notification, self));
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => self.socket = None,
+                Some(Ok(_)) => (),
+            }
+        }
+    }
+}
+
+/// Stream of notifications from a thing's WebSocket.
+///
+/// The WebSocket is reconnected with an increasing delay whenever it is closed or fails, and
+/// `Notification::Connected` is yielded each time the connection is established. If the
+/// handshake fails for good, `Notification::Rejected` is yielded and the observation ends. Drop
+/// the observation to close the WebSocket.
+pub struct Observation {
+    notifications: BoxStream<'static, Notification>,
+}
+
+impl Stream for Observation {
+    type Item = Notification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.notifications.poll_next_unpin(cx)
+    }
+}
+
+/// Client for a web thing served by another server.
+#[derive(Clone)]
+pub struct ThingClient {
+    http: reqwest::Client,
+    description: serde_json::Map<String, serde_json::Value>,
+    base: Url,
+    reconnect_delay: Duration,
+}
+
+impl ThingClient {
+    /// Fetch a Thing Description and create a client for the thing.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of the Thing Description, i.e. `http://mything.local:8888/`
+    pub async fn connect(url: &str) -> Result<Self, ClientError> {
+        let mut clients = Self::connect_all(url).await?;
+        if clients.len() != 1 {
+            return Err(ClientError::InvalidResponse("Expected a single thing"));
+        }
+
+        Ok(clients.remove(0))
+    }
+
+    /// Fetch the Thing Descriptions of all things served at a URL and create a client for each.
+    ///
+    /// Servers managing multiple things list them at their root.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of a Thing Description, or of a list of them
+    pub async fn connect_all(url: &str) -> Result<Vec<Self>, ClientError> {
+        let http = reqwest::Client::new();
+        let response = http.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status().as_u16()));
+        }
+
+        let descriptions = match response.json().await? {
+            serde_json::Value::Array(descriptions) => descriptions,
+            description => vec![description],
+        };
+
+        descriptions
+            .into_iter()
+            .map(|description| match description {
+                serde_json::Value::Object(description) => {
+                    let mut client = Self::from_description(url, description)?;
+                    client.http = http.clone();
+                    Ok(client)
+                }
+                _ => Err(ClientError::InvalidResponse("Expected a Thing Description")),
+            })
+            .collect()
+    }
+
+    /// Create a client from a Thing Description which was already fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL the description was fetched from, used if it has no `base`
+    /// * `description` - the Thing Description, in the WebThings or W3C format
+    pub fn from_description(
+        url: &str,
+        description: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Self, ClientError> {
+        let url = Url::parse(url)?;
+        let base = match description.get("base").and_then(|b| b.as_str()) {
+            Some(base) => url.join(base)?,
+            None => match description.get("href").and_then(|h| h.as_str()) {
+                Some(href) => url.join(href)?,
+                None => url,
+            },
+        };
+
+        Ok(ThingClient {
+            http: reqwest::Client::new(),
+            description,
+            base,
+            reconnect_delay: DEFAULT_RECONNECT_DELAY,
+        })
+    }
+
+    /// Set how long to wait before reconnecting a closed WebSocket.
+    ///
+    /// The delay doubles with each failed attempt, up to 30 seconds. Defaults to 1 second.
+    pub fn with_reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Get the Thing Description.
+    pub fn description(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.description
+    }
+
+    /// Get the thing's ID, if it has one.
+    pub fn id(&self) -> Option<&str> {
+        self.description.get("id").and_then(|id| id.as_str())
+    }
+
+    /// Get the thing's title.
+    pub fn title(&self) -> Option<&str> {
+        self.description.get("title").and_then(|t| t.as_str())
+    }
+
+    /// Get the URL of a path below the thing's base URL.
+    fn below_base(&self, path: &str) -> Url {
+        let mut url = self.base.clone();
+        url.set_path(&format!(
+            "{}/{}",
+            self.base.path().trim_end_matches('/'),
+            path
+        ));
+        url
+    }
+
+    /// Find the href of an HTTP link or form in an interaction or thing description.
+    fn find_href(
+        &self,
+        description: &serde_json::Value,
+        rel: &str,
+        op: &str,
+    ) -> Result<Option<Url>, ClientError> {
+        let links = description.get("links").and_then(|l| l.as_array());
+        let link = links
+            .into_iter()
+            .flatten()
+            .find(|link| link.get("rel").and_then(|r| r.as_str()) == Some(rel));
+
+        let forms = description.get("forms").and_then(|f| f.as_array());
+        let form = forms.into_iter().flatten().find(|form| {
+            form.get("subprotocol").is_none()
+                && match form.get("op") {
+                    Some(serde_json::Value::String(o)) => o == op,
+                    Some(serde_json::Value::Array(ops)) => ops.iter().any(|o| o == op),
+                    _ => false,
+                }
+        });
+
+        match link
+            .or(form)
+            .and_then(|l| l.get("href"))
+            .and_then(|h| h.as_str())
+        {
+            Some(href) => Ok(Some(self.base.join(href)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the URL of a property, action or event.
+    fn interaction_url(
+        &self,
+        kind: &str,
+        name: &str,
+        rel: &str,
+        op: &str,
+    ) -> Result<Url, ClientError> {
+        let interaction = self
+            .description
+            .get(kind)
+            .and_then(|interactions| interactions.get(name))
+            .ok_or_else(|| ClientError::Unknown(name.to_owned()))?;
+
+        Ok(self
+            .find_href(interaction, rel, op)?
+            .unwrap_or_else(|| self.below_base(&format!("{}/{}", kind, name))))
+    }
+
+    /// Get the URL of the thing's properties, actions or events.
+    fn collection_url(&self, kind: &str, op: &str) -> Result<Url, ClientError> {
+        Ok(self
+            .find_href(&json!(self.description), kind, op)?
+            .unwrap_or_else(|| self.below_base(kind)))
+    }
+
+    /// Get the URL of the thing's WebSocket.
+    pub fn websocket_url(&self) -> Result<Url, ClientError> {
+        Ok(self.websocket_endpoint()?.0)
+    }
+
+    /// Get the URL of the thing's WebSocket, and the subprotocol to request, if any.
+    ///
+    /// W3C descriptions name the subprotocol in their WebSocket forms, which are preferred,
+    /// while WebThings descriptions link the WebSocket without one.
+    fn websocket_endpoint(&self) -> Result<(Url, Option<String>), ClientError> {
+        let is_websocket = |entry: &&serde_json::Value| {
+            entry
+                .get("href")
+                .and_then(|h| h.as_str())
+                .is_some_and(|href| href.starts_with("ws://") || href.starts_with("wss://"))
+        };
+
+        let links = self.description.get("links").and_then(|l| l.as_array());
+        let forms = self.description.get("forms").and_then(|f| f.as_array());
+        let entry = forms
+            .into_iter()
+            .flatten()
+            .chain(
+                links
+                    .into_iter()
+                    .flatten()
+                    .filter(|link| link.get("rel").and_then(|r| r.as_str()) == Some("alternate")),
+            )
+            .find(is_websocket);
+
+        if let Some(entry) = entry {
+            let href = entry["href"].as_str().unwrap_or_default();
+            let subprotocol = entry
+                .get("subprotocol")
+                .and_then(|s| s.as_str())
+                .map(|s| s.to_owned());
+            return Ok((Url::parse(href)?, subprotocol));
+        }
+
+        let mut url = self.base.clone();
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|_| ClientError::InvalidResponse("Cannot derive WebSocket URL"))?;
+        Ok((url, None))
+    }
+
+    /// Send a request and parse its JSON response.
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<serde_json::Value, ClientError> {
+        let response = request.header("Accept", "application/json").send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status().as_u16()));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Read a property's value.
+    pub async fn read_property(&self, name: &str) -> Result<serde_json::Value, ClientError> {
+        let url = self.interaction_url("properties", name, "property", "readproperty")?;
+        let response = self.send(self.http.get(url)).await?;
+
+        response
+            .get(name)
+            .cloned()
+            .ok_or(ClientError::InvalidResponse("Property value missing"))
+    }
+
+    /// Read the values of all properties.
+    pub async fn read_properties(
+        &self,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, ClientError> {
+        let url = self.collection_url("properties", "readallproperties")?;
+        match self.send(self.http.get(url)).await? {
+            serde_json::Value::Object(properties) => Ok(properties),
+            _ => Err(ClientError::InvalidResponse("Expected an object")),
+        }
+    }
+
+    /// Write a property's value.
+    ///
+    /// Returns the value applied by the thing.
+    pub async fn write_property(
+        &self,
+        name: &str,
+        value: serde_json::Value,
+    ) -> Result<serde_json::Value, ClientError> {
+        let url = self.interaction_url("properties", name, "property", "writeproperty")?;
+        let mut body = serde_json::Map::new();
+        body.insert(name.to_owned(), value);
+        let response = self.send(self.http.put(url).json(&body)).await?;
+
+        response
+            .get(name)
+            .cloned()
+            .ok_or(ClientError::InvalidResponse("Property value missing"))
+    }
+
+    /// Request an action.
+    pub async fn request_action(
+        &self,
+        name: &str,
+        input: Option<serde_json::Value>,
+    ) -> Result<ActionRequest, ClientError> {
+        let url = self.interaction_url("actions", name, "action", "invokeaction")?;
+        let mut params = serde_json::Map::new();
+        if let Some(input) = input {
+            params.insert("input".to_owned(), input);
+        }
+
+        let mut body = serde_json::Map::new();
+        body.insert(name.to_owned(), json!(params));
+        let response = self.send(self.http.post(url).json(&body)).await?;
+        ActionRequest::from_description(&response)
+    }
+
+    /// Get the URL of an action request.
+    fn action_request_url(&self, name: &str, id: &str) -> Result<Url, ClientError> {
+        let mut url = self.interaction_url("actions", name, "action", "invokeaction")?;
+        url.set_path(&format!("{}/{}", url.path().trim_end_matches('/'), id));
+        Ok(url)
+    }
+
+    /// Get the current state of an action request.
+    pub async fn get_action(&self, name: &str, id: &str) -> Result<ActionRequest, ClientError> {
+        let url = self.action_request_url(name, id)?;
+        let response = self.send(self.http.get(url)).await?;
+        ActionRequest::from_description(&response)
+    }
+
+    /// List the action requests, either of one action or of all actions.
+    pub async fn list_actions(
+        &self,
+        name: Option<&str>,
+    ) -> Result<Vec<ActionRequest>, ClientError> {
+        let url = match name {
+            Some(name) => self.interaction_url("actions", name, "action", "invokeaction")?,
+            None => self.collection_url("actions", "queryallactions")?,
+        };
+
+        entries(self.send(self.http.get(url)).await?)?
+            .iter()
+            .map(ActionRequest::from_description)
+            .collect()
+    }
+
+    /// Cancel an action request.
+    pub async fn cancel_action(&self, name: &str, id: &str) -> Result<(), ClientError> {
+        let url = self.action_request_url(name, id)?;
+        let response = self.http.delete(url).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError::Status(response.status().as_u16()));
+        }
+
+        Ok(())
+    }
+
+    /// List the events which occurred, either of one event or of all events.
+    pub async fn list_events(&self, name: Option<&str>) -> Result<Vec<EventRecord>, ClientError> {
+        let url = match name {
+            Some(name) => self.interaction_url("events", name, "event", "subscribeevent")?,
+            None => self.collection_url("events", "subscribeallevents")?,
+        };
+
+        entries(self.send(self.http.get(url)).await?)?
+            .iter()
+            .map(EventRecord::from_description)
+            .collect()
+    }
+
+    /// Observe property changes and action status over the thing's WebSocket, and subscribe to
+    /// events.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - names of the events to subscribe to
+    pub fn observe(&self, events: &[&str]) -> Result<Observation, ClientError> {
+        let (url, subprotocol) = self.websocket_endpoint()?;
+        let state = ObservationState {
+            url,
+            subprotocol,
+            events: events.iter().map(|e| e.to_string()).collect(),
+            socket: None,
+            delay: self.reconnect_delay,
+            reconnect_delay: self.reconnect_delay,
+            attempted: false,
+            rejected: false,
+        };
+
+        Ok(Observation {
+            notifications: stream::unfold(state, ObservationState::next).boxed(),
+        })
+    }
+}
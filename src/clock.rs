@@ -0,0 +1,119 @@
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use std::marker::{Send, Sync};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::utils::lock;
+
+/// Precision of RFC 3339 timestamps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    /// Whole seconds, i.e. `2022-03-07T12:00:00+00:00`.
+    #[default]
+    Seconds,
+    /// Milliseconds, i.e. `2022-03-07T12:00:00.250+00:00`, so events within the same second can
+    /// be ordered.
+    Milliseconds,
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC.
+pub fn format_timestamp(time: DateTime<Utc>, precision: TimestampPrecision) -> String {
+    let format = match precision {
+        TimestampPrecision::Seconds => SecondsFormat::Secs,
+        TimestampPrecision::Milliseconds => SecondsFormat::Millis,
+    };
+
+    time.to_rfc3339_opts(format, false)
+}
+
+/// Source of the timestamps of actions and events.
+pub trait Clock: Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Get the precision of the timestamps.
+    fn precision(&self) -> TimestampPrecision {
+        TimestampPrecision::Seconds
+    }
+
+    /// Get the current time as an RFC 3339 timestamp.
+    fn timestamp(&self) -> String {
+        format_timestamp(self.now(), self.precision())
+    }
+}
+
+/// Clock reading the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock {
+    precision: TimestampPrecision,
+}
+
+impl SystemClock {
+    /// Create a new SystemClock, with timestamps in whole seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the precision of the timestamps.
+    pub fn with_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn precision(&self) -> TimestampPrecision {
+        self.precision
+    }
+}
+
+/// Clock which only moves when told to, for tests.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+    precision: TimestampPrecision,
+}
+
+impl ManualClock {
+    /// Create a new ManualClock, stopped at `start`.
+    pub fn new(start: DateTime<Utc>) -> Self {
+        ManualClock {
+            now: Mutex::new(start),
+            precision: TimestampPrecision::default(),
+        }
+    }
+
+    /// Set the precision of the timestamps.
+    pub fn with_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set the current time.
+    pub fn set(&self, time: DateTime<Utc>) {
+        *lock(&self.now) = time;
+    }
+
+    /// Move the current time forward.
+    pub fn advance(&self, by: Duration) {
+        let by = TimeDelta::from_std(by).unwrap_or(TimeDelta::MAX);
+        let mut now = lock(&self.now);
+        *now = now
+            .checked_add_signed(by)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *lock(&self.now)
+    }
+
+    fn precision(&self) -> TimestampPrecision {
+        self.precision
+    }
+}
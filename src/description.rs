@@ -0,0 +1,349 @@
+use serde_json;
+use serde_json::json;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use valico::json_schema;
+
+use super::capability;
+use super::utils::lock;
+
+/// Context of W3C WoT Thing Description 1.1 documents.
+pub const TD_11_CONTEXT: &str = "https://www.w3.org/2022/wot/td/v1.1";
+
+/// Context of W3C WoT Thing Description 1.0 documents.
+const TD_10_CONTEXT: &str = "https://www.w3.org/2019/wot/td/v1";
+
+/// WebSocket subprotocol spoken on a thing's WebSocket endpoint.
+pub const WEBSOCKET_SUBPROTOCOL: &str = "webthing";
+
+/// Hand-written JSON Schema for the W3C WoT Thing Description 1.1 members this crate produces.
+///
+/// This is only a partial check, not the official TD 1.1 schema: documents it accepts may still
+/// be rejected by the official schema, e.g. for vocabulary this crate never emits.
+const TD_SUBSET_SCHEMA: &str = include_str!("schemas/td-subset.json");
+
+/// JSON Schema for WebThings Thing Descriptions, as returned by `Thing::as_thing_description()`.
+const WEBTHINGS_SCHEMA: &str = include_str!("schemas/webthings.json");
+
+/// A compiled JSON Schema, validating values against it.
+///
+/// Compiled schemas are not `Sync`, so they are shared behind a mutex.
+type SchemaValidator =
+    Mutex<Box<dyn Fn(&serde_json::Value) -> json_schema::ValidationState + Send>>;
+
+static TD_SUBSET_VALIDATOR: OnceLock<SchemaValidator> = OnceLock::new();
+static WEBTHINGS_VALIDATOR: OnceLock<SchemaValidator> = OnceLock::new();
+
+/// Compile one of the bundled JSON Schemas.
+fn compile(schema: &str) -> SchemaValidator {
+    let schema: serde_json::Value =
+        serde_json::from_str(schema).expect("bundled schemas are valid JSON");
+    let mut scope = json_schema::Scope::new();
+    let id = scope
+        .compile(schema, false)
+        .expect("bundled schemas are valid JSON Schemas");
+
+    Mutex::new(Box::new(move |value| {
+        scope
+            .resolve(&id)
+            .expect("compiled schemas can be resolved")
+            .validate(value)
+    }))
+}
+
+/// Content type of the HTTP interactions.
+const CONTENT_TYPE: &str = "application/json";
+
+/// Keywords of a WebThings event description which describe the event data.
+const DATA_SCHEMA_KEYWORDS: &[&str] = &[
+    "type",
+    "unit",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "enum",
+    "const",
+    "format",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "items",
+    "minItems",
+    "maxItems",
+    "properties",
+    "required",
+    "oneOf",
+];
+
+/// Format of the Thing Descriptions served for each thing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DescriptionFormat {
+    /// WebThings Thing Description, with `links` for each interaction.
+    #[default]
+    WebThings,
+    /// W3C WoT Thing Description 1.1, with `forms` for each interaction.
+    Td11,
+}
+
+/// A problem found while validating a Thing Description.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    /// JSON pointer to the offending member, i.e. `/properties/level`.
+    pub path: String,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Validate a JSON value against a bundled JSON Schema, compiled on first use, appending any
+/// problems to `errors`.
+fn validate_against(
+    validator: &OnceLock<SchemaValidator>,
+    schema: &str,
+    value: &serde_json::Value,
+    errors: &mut Vec<ValidationError>,
+) {
+    let validator = validator.get_or_init(|| compile(schema));
+    let state = lock(validator)(value);
+
+    for error in state.errors {
+        let message = match error.get_detail() {
+            Some(detail) => format!("{} ({})", error.get_title(), detail),
+            None => error.get_title().to_owned(),
+        };
+        let error = ValidationError {
+            path: error.get_path().to_owned(),
+            message,
+        };
+
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+}
+
+/// Validate a WebThings Thing Description.
+///
+/// The description is checked against the WebThings schema, which catches unknown or misspelled
+/// metadata members, and its W3C WoT Thing Description 1.1 form against a hand-written schema
+/// covering the members this crate produces, which is only a partial check of TD 1.1. Its capabilities are then checked with
+/// `capability::validate()`.
+///
+/// # Arguments
+///
+/// * `description` - description as returned by `Thing::as_thing_description()`
+pub fn validate(
+    description: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    validate_against(
+        &WEBTHINGS_VALIDATOR,
+        WEBTHINGS_SCHEMA,
+        &json!(description),
+        &mut errors,
+    );
+
+    // The WebSocket URL depends on the request, any absolute URL will do for validation.
+    let td = to_td11(description.clone(), "ws://localhost/");
+    validate_against(
+        &TD_SUBSET_VALIDATOR,
+        TD_SUBSET_SCHEMA,
+        &json!(td),
+        &mut errors,
+    );
+
+    if let Err(capability_errors) = capability::validate(description) {
+        errors.extend(capability_errors);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Check whether a context URI is one of the W3C WoT Thing Description contexts.
+pub(crate) fn is_td_context(uri: &str) -> bool {
+    uri == TD_11_CONTEXT || uri == TD_10_CONTEXT
+}
+
+/// Remove the link with the given relation from a JSON map's `links`.
+///
+/// Returns the href of the removed link.
+fn take_link(
+    description: &mut serde_json::Map<String, serde_json::Value>,
+    rel: &str,
+) -> Option<String> {
+    let links = description.get_mut("links")?.as_array_mut()?;
+    let position = links
+        .iter()
+        .position(|link| link.get("rel").and_then(|r| r.as_str()) == Some(rel))?;
+    let link = links.remove(position);
+    link.get("href")
+        .and_then(|href| href.as_str())
+        .map(|href| href.to_owned())
+}
+
+/// Get a mutable JSON object member of a description, if present.
+fn get_object_mut<'a>(
+    description: &'a mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> Option<&'a mut serde_json::Map<String, serde_json::Value>> {
+    description
+        .get_mut(key)
+        .and_then(|value| value.as_object_mut())
+}
+
+/// Convert a WebThings Thing Description into a W3C WoT Thing Description 1.1.
+///
+/// The `links` of properties, actions and events are replaced with `forms`, and thing-level
+/// forms are added for the operations supported by the server. Hrefs are kept as they are, so
+/// they keep resolving against the description's `base`.
+///
+/// # Arguments
+///
+/// * `description` - description as returned by `Thing::as_thing_description()`
+/// * `ws_href` - absolute URL of the thing's WebSocket endpoint
+pub fn to_td11(
+    mut description: serde_json::Map<String, serde_json::Value>,
+    ws_href: &str,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut context = vec![json!(TD_11_CONTEXT)];
+    match description.remove("@context") {
+        Some(serde_json::Value::Array(values)) => context.extend(
+            values
+                .into_iter()
+                .filter(|v| !v.as_str().is_some_and(is_td_context)),
+        ),
+        Some(serde_json::Value::Null) | None => (),
+        Some(value) => {
+            if !value.as_str().is_some_and(is_td_context) {
+                context.push(value)
+            }
+        }
+    }
+    description.insert("@context".to_owned(), json!(context));
+
+    let properties_href = take_link(&mut description, "properties");
+    let actions_href = take_link(&mut description, "actions");
+    take_link(&mut description, "events");
+
+    if let Some(links) = description.get_mut("links").and_then(|l| l.as_array_mut()) {
+        for link in links.iter_mut().filter_map(|link| link.as_object_mut()) {
+            if let Some(media_type) = link.remove("mediaType") {
+                link.insert("type".to_owned(), media_type);
+            }
+        }
+    }
+
+    if let Some(properties) = get_object_mut(&mut description, "properties") {
+        for property in properties.values_mut().filter_map(|p| p.as_object_mut()) {
+            let href = take_link(property, "property");
+            property.remove("links");
+
+            let read_only = property
+                .get("readOnly")
+                .and_then(|b| b.as_bool())
+                .unwrap_or(false);
+            let op = if read_only {
+                json!(["readproperty"])
+            } else {
+                json!(["readproperty", "writeproperty"])
+            };
+
+            let mut forms = Vec::new();
+            if let Some(href) = href {
+                forms.push(json!({"href": href, "op": op, "contentType": CONTENT_TYPE}));
+            }
+            forms.push(json!({
+                "href": ws_href,
+                "op": ["observeproperty"],
+                "subprotocol": WEBSOCKET_SUBPROTOCOL,
+            }));
+            property.insert("forms".to_owned(), json!(forms));
+        }
+    }
+
+    if let Some(actions) = get_object_mut(&mut description, "actions") {
+        for action in actions.values_mut().filter_map(|a| a.as_object_mut()) {
+            let href = take_link(action, "action");
+            action.remove("links");
+
+            let mut forms = Vec::new();
+            if let Some(href) = href {
+                forms
+                    .push(json!({"href": href, "op": "invokeaction", "contentType": CONTENT_TYPE}));
+            }
+            forms.push(json!({
+                "href": ws_href,
+                "op": "invokeaction",
+                "subprotocol": WEBSOCKET_SUBPROTOCOL,
+            }));
+            action.insert("forms".to_owned(), json!(forms));
+        }
+    }
+
+    if let Some(events) = get_object_mut(&mut description, "events") {
+        for event in events.values_mut().filter_map(|e| e.as_object_mut()) {
+            event.remove("links");
+
+            if !event.contains_key("data") {
+                let mut data = serde_json::Map::new();
+                for keyword in DATA_SCHEMA_KEYWORDS {
+                    if let Some(value) = event.remove(*keyword) {
+                        data.insert(keyword.to_string(), value);
+                    }
+                }
+
+                if !data.is_empty() {
+                    event.insert("data".to_owned(), json!(data));
+                }
+            }
+
+            event.insert(
+                "forms".to_owned(),
+                json!([{
+                    "href": ws_href,
+                    "op": "subscribeevent",
+                    "subprotocol": WEBSOCKET_SUBPROTOCOL,
+                }]),
+            );
+        }
+    }
+
+    let mut forms = Vec::new();
+    if let Some(href) = properties_href {
+        forms.push(json!({"href": href, "op": "readallproperties", "contentType": CONTENT_TYPE}));
+    }
+    if let Some(href) = actions_href {
+        forms.push(json!({"href": href, "op": "queryallactions", "contentType": CONTENT_TYPE}));
+    }
+    forms.push(json!({
+        "href": ws_href,
+        "op": ["writemultipleproperties", "observeallproperties", "subscribeallevents"],
+        "subprotocol": WEBSOCKET_SUBPROTOCOL,
+    }));
+    description.insert("forms".to_owned(), json!(forms));
+
+    if !description.contains_key("security") {
+        description.insert(
+            "securityDefinitions".to_owned(),
+            json!({"nosec_sc": {"scheme": "nosec"}}),
+        );
+        description.insert("security".to_owned(), json!("nosec_sc"));
+    }
+
+    description
+}
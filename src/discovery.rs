@@ -0,0 +1,213 @@
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Service type web things are advertised under.
+pub const SERVICE_TYPE: &str = "_webthing._tcp.local.";
+
+/// A service reported by an mDNS browser, before it is resolved to a URL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedService {
+    /// Full instance name, i.e. `My Lamp._webthing._tcp.local.`.
+    pub name: String,
+    /// Host name, i.e. `raspberrypi.local.`.
+    pub hostname: String,
+    /// Addresses of the host.
+    pub addresses: Vec<IpAddr>,
+    /// Port the server listens on.
+    pub port: u16,
+    /// TXT records.
+    pub txt: HashMap<String, String>,
+}
+
+/// Changes reported by an mDNS browser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BrowseEvent {
+    /// A service was found or updated.
+    Resolved(ResolvedService),
+    /// A service was withdrawn, by its full instance name.
+    Removed(String),
+}
+
+/// A web thing server found on the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredThing {
+    /// Full instance name of the service, i.e. `My Lamp._webthing._tcp.local.`.
+    pub name: String,
+    /// URL of the Thing Description, or of the list of things for a multiple-things server.
+    pub url: String,
+    /// Whether the server is served over TLS, from the `tls` TXT record.
+    pub tls: bool,
+    /// The service as reported by the browser.
+    pub service: ResolvedService,
+}
+
+impl DiscoveredThing {
+    /// Resolve a service to the URL of its things.
+    ///
+    /// IPv4 addresses are preferred, as IPv6 link-local addresses need a scope to be reachable.
+    /// Returns `None` if the service has no addresses.
+    pub fn from_service(service: ResolvedService) -> Option<Self> {
+        let address = service
+            .addresses
+            .iter()
+            .min_by_key(|address| address.is_ipv6())?;
+        let host = match address {
+            IpAddr::V4(address) => address.to_string(),
+            IpAddr::V6(address) => format!("[{}]", address),
+        };
+
+        let tls = service.txt.get("tls").map(|tls| tls == "1") == Some(true);
+        let path = service.txt.get("path").map(|p| p.as_str()).unwrap_or("/");
+        let url = format!(
+            "{}://{}:{}/{}",
+            if tls { "https" } else { "http" },
+            host,
+            service.port,
+            path.trim_start_matches('/')
+        );
+
+        Some(DiscoveredThing {
+            name: service.name.clone(),
+            url,
+            tls,
+            service,
+        })
+    }
+}
+
+/// Changes to the web things found on the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiscoveryEvent {
+    /// A server appeared, or changed its address or TXT records.
+    Appeared(DiscoveredThing),
+    /// A server was withdrawn, as last seen.
+    Disappeared(DiscoveredThing),
+}
+
+/// Stream of web things appearing and disappearing on the network.
+///
+/// Changed servers are reported as disappeared, then appeared again.
+pub struct Discovery {
+    events: BoxStream<'static, BrowseEvent>,
+    known: HashMap<String, DiscoveredThing>,
+    pending: VecDeque<DiscoveryEvent>,
+    daemon: Option<ServiceDaemon>,
+}
+
+impl Discovery {
+    /// Browse the local network for `_webthing._tcp` services.
+    pub fn browse() -> Result<Self, mdns_sd::Error> {
+        Self::browse_with(ServiceDaemon::new()?)
+    }
+
+    /// Browse with an mDNS daemon configured by the caller, i.e. limited to some interfaces.
+    ///
+    /// The daemon is shut down when the discovery is dropped.
+    pub fn browse_with(daemon: ServiceDaemon) -> Result<Self, mdns_sd::Error> {
+        let events = daemon
+            .browse(SERVICE_TYPE)?
+            .into_stream()
+            .filter_map(|event| async move {
+                match event {
+                    ServiceEvent::ServiceResolved(info) => {
+                        // Sort the addresses, so the URL does not change between resolutions.
+                        let mut addresses: Vec<IpAddr> =
+                            info.get_addresses().iter().cloned().collect();
+                        addresses.sort_unstable();
+
+                        Some(BrowseEvent::Resolved(ResolvedService {
+                            name: info.get_fullname().to_owned(),
+                            hostname: info.get_hostname().to_owned(),
+                            addresses,
+                            port: info.get_port(),
+                            txt: info
+                                .get_properties()
+                                .iter()
+                                .map(|p| (p.key().to_owned(), p.val_str().to_owned()))
+                                .collect(),
+                        }))
+                    }
+                    ServiceEvent::ServiceRemoved(_, name) => Some(BrowseEvent::Removed(name)),
+                    _ => None,
+                }
+            });
+
+        let mut discovery = Self::from_events(events);
+        discovery.daemon = Some(daemon);
+        Ok(discovery)
+    }
+
+    /// Discover things from the events of another browser, i.e. a stand-in for tests.
+    pub fn from_events(events: impl Stream<Item = BrowseEvent> + Send + 'static) -> Self {
+        Discovery {
+            events: events.boxed(),
+            known: HashMap::new(),
+            pending: VecDeque::new(),
+            daemon: None,
+        }
+    }
+
+    /// Get the things currently known.
+    pub fn things(&self) -> Vec<DiscoveredThing> {
+        let mut things: Vec<DiscoveredThing> = self.known.values().cloned().collect();
+        things.sort_by(|a, b| a.name.cmp(&b.name));
+        things
+    }
+
+    /// Track a browser event, queueing the changes it causes.
+    fn track(&mut self, event: BrowseEvent) {
+        match event {
+            BrowseEvent::Resolved(service) => {
+                let thing = match DiscoveredThing::from_service(service) {
+                    Some(thing) => thing,
+                    None => return,
+                };
+
+                match self.known.insert(thing.name.clone(), thing.clone()) {
+                    Some(previous) if previous == thing => return,
+                    Some(previous) => self
+                        .pending
+                        .push_back(DiscoveryEvent::Disappeared(previous)),
+                    None => (),
+                }
+                self.pending.push_back(DiscoveryEvent::Appeared(thing));
+            }
+            BrowseEvent::Removed(name) => {
+                if let Some(thing) = self.known.remove(&name) {
+                    self.pending.push_back(DiscoveryEvent::Disappeared(thing));
+                }
+            }
+        }
+    }
+}
+
+impl Stream for Discovery {
+    type Item = DiscoveryEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+
+            match self.events.poll_next_unpin(cx) {
+                Poll::Ready(Some(event)) => self.track(event),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        if let Some(ref daemon) = self.daemon {
+            let _ = daemon.shutdown();
+        }
+    }
+}
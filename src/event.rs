@@ -1,7 +1,9 @@
 use serde_json;
 use serde_json::json;
 use std::marker::{Send, Sync};
+use std::sync::Arc;
 
+use super::clock::Clock;
 use super::utils::timestamp;
 
 /// High-level Event trait.
@@ -26,6 +28,11 @@ pub trait Event: Send + Sync {
     /// Get the event's name.
     fn get_name(&self) -> String;
 
+    /// Set the clock timestamping this event.
+    ///
+    /// Called with the thing's clock when the event is added to a thing that has one.
+    fn set_clock(&mut self, _clock: Arc<dyn Clock>) {}
+
     /// Get the event's data.
     fn get_data(&self) -> Option<serde_json::Value>;
 
@@ -61,6 +68,11 @@ impl Event for BaseEvent {
         self.name.clone()
     }
 
+    /// Set the clock timestamping this event, restamping the time it occurred.
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.time = clock.timestamp();
+    }
+
     /// Get the event's data.
     fn get_data(&self) -> Option<serde_json::Value> {
         self.data.clone()
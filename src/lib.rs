@@ -10,28 +10,68 @@ pub mod action;
 /// ActionGenerator trait and base implementation.
 pub mod action_generator;
 
+/// Clocks timestamping actions and events.
+pub mod clock;
+
+/// Client for web things served by other servers.
+#[cfg(feature = "client")]
+pub mod client;
+
+/// Catalogue of the WebThings capability schemas.
+pub mod capability;
+
+/// Thing Description formats.
+pub mod description;
+
+/// Discovery of web things on the local network over mDNS.
+#[cfg(feature = "discovery")]
+pub mod discovery;
+
 /// Event trait and base implementation.
 pub mod event;
 
 /// Property trait and base implementation.
 pub mod property;
 
+/// Things mirroring web things served by other servers.
+#[cfg(feature = "client")]
+pub mod proxy;
+
 /// WebThingServer implementation.
 #[cfg(feature = "actix")]
 pub mod server;
 
+/// Harness for testing things in-process.
+#[cfg(feature = "testing")]
+pub mod testing;
+
 /// Thing trait and base implementation.
 pub mod thing;
 
+/// TLS certificates for the server.
+#[cfg(any(feature = "ssl", feature = "rustls"))]
+mod tls;
+
 /// Utility functions.
 pub mod utils;
 
 pub use action::{Action, BaseAction};
 pub use action_generator::BaseActionGenerator;
+pub use clock::{Clock, ManualClock, SystemClock, TimestampPrecision};
+pub use description::{DescriptionFormat, ValidationError};
 pub use event::{BaseEvent, Event};
 pub use property::{BaseProperty, Property};
 
+#[cfg(feature = "client")]
+pub use proxy::{ProxyActionGenerator, ProxyThing};
+
 #[cfg(feature = "actix")]
-pub use server::{ThingsType, WebThingServer};
+pub use server::{
+    AdvertisedService, CorsPolicy, DescriptionValidation, HostPattern, ListenAddress, MdnsMode,
+    RateLimit, RequestLimits, ShutdownHandle, ThingsHandle, ThingsType, WebThingServer,
+};
+
+#[cfg(any(feature = "ssl", feature = "rustls"))]
+pub use server::{ClientIdentity, TlsHandle};
 
 pub use thing::{BaseThing, Thing, ThingContext};
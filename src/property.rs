@@ -1,14 +1,35 @@
 use serde_json;
 use serde_json::json;
+use std::future::Future;
 use std::marker::{Send, Sync};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use valico::json_schema;
 
+use super::utils::lock;
+
 /// Used to forward a new property value to the physical/virtual device.
 pub trait ValueForwarder: Send + Sync {
     /// Set the new value of the property.
     fn set_value(&mut self, value: serde_json::Value) -> Result<serde_json::Value, &'static str>;
 }
 
+/// A value forwarder behind its own lock, so it can be called without locking the thing.
+pub type SharedValueForwarder = Arc<Mutex<Box<dyn ValueForwarder>>>;
+
+/// Future returned by an `AsyncValueForwarder`, resolving to the value applied by the device.
+pub type ForwarderFuture =
+    Pin<Box<dyn Future<Output = Result<serde_json::Value, &'static str>> + Send>>;
+
+/// Used to forward a new property value to the physical/virtual device without blocking.
+///
+/// The server awaits the returned future without holding the thing's lock, so slow device I/O
+/// does not stall other requests to the same thing.
+pub trait AsyncValueForwarder: Send + Sync {
+    /// Set the new value of the property.
+    fn set_value(&self, value: serde_json::Value) -> ForwarderFuture;
+}
+
 /// High-level Property trait.
 pub trait Property: Send + Sync {
     /// Validate new property value before setting it.
@@ -76,6 +97,24 @@ pub trait Property: Send + Sync {
     /// Set the current value of the property with the value forwarder.
     fn set_value(&mut self, value: serde_json::Value) -> Result<(), &'static str>;
 
+    /// Get the asynchronous value forwarder of this property, if any.
+    ///
+    /// When set, the server validates new values, awaits the forwarder and then stores the
+    /// resolved value with `set_cached_value`, instead of calling `set_value`.
+    fn get_async_value_forwarder(&self) -> Option<Arc<dyn AsyncValueForwarder>> {
+        None
+    }
+
+    /// Get the synchronous value forwarder of this property, if any.
+    ///
+    /// When set, the server validates new values, calls the forwarder on a blocking thread with
+    /// only the forwarder's lock held and then stores the returned value with
+    /// `set_cached_value`, instead of calling `set_value`. Slow devices then only hold up writes
+    /// to the same property.
+    fn get_value_forwarder(&self) -> Option<SharedValueForwarder> {
+        None
+    }
+
     /// Set the cached value of the property.
     fn set_cached_value(&mut self, value: serde_json::Value) -> Result<(), &'static str>;
 
@@ -94,7 +133,8 @@ pub trait Property: Send + Sync {
 pub struct BaseProperty {
     name: String,
     last_value: serde_json::Value,
-    value_forwarder: Option<Box<dyn ValueForwarder>>,
+    value_forwarder: Option<SharedValueForwarder>,
+    async_value_forwarder: Option<Arc<dyn AsyncValueForwarder>>,
     href_prefix: String,
     href: String,
     metadata: serde_json::Map<String, serde_json::Value>,
@@ -125,12 +165,21 @@ impl BaseProperty {
         BaseProperty {
             name,
             last_value: initial_value,
-            value_forwarder,
+            value_forwarder: value_forwarder.map(|vf| Arc::new(Mutex::new(vf))),
+            async_value_forwarder: None,
             href_prefix: "".to_owned(),
             href,
             metadata: meta,
         }
     }
+
+    /// Forward new values to the device through an asynchronous value forwarder.
+    ///
+    /// Takes precedence over the synchronous value forwarder passed to `new`.
+    pub fn with_async_value_forwarder(mut self, forwarder: Arc<dyn AsyncValueForwarder>) -> Self {
+        self.async_value_forwarder = Some(forwarder);
+        self
+    }
 }
 
 impl Property for BaseProperty {
@@ -153,8 +202,12 @@ impl Property for BaseProperty {
     fn set_value(&mut self, value: serde_json::Value) -> Result<(), &'static str> {
         self.validate_value(&value)?;
 
+        if self.async_value_forwarder.is_some() {
+            return Err("Property must be set asynchronously");
+        }
+
         match self.value_forwarder {
-            Some(ref mut vf) => match vf.set_value(value) {
+            Some(ref vf) => match lock(vf).set_value(value) {
                 Ok(v) => {
                     self.last_value = v;
                     Ok(())
@@ -168,6 +221,16 @@ impl Property for BaseProperty {
         }
     }
 
+    /// Get the asynchronous value forwarder of this property, if any.
+    fn get_async_value_forwarder(&self) -> Option<Arc<dyn AsyncValueForwarder>> {
+        self.async_value_forwarder.clone()
+    }
+
+    /// Get the synchronous value forwarder of this property, if any.
+    fn get_value_forwarder(&self) -> Option<SharedValueForwarder> {
+        self.value_forwarder.clone()
+    }
+
     /// Set the cached value of the property.
     fn set_cached_value(&mut self, value: serde_json::Value) -> Result<(), &'static str> {
         self.last_value = value;
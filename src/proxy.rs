@@ -0,0 +1,611 @@
+use futures::future::{AbortHandle, Abortable};
+use futures::StreamExt;
+use log::warn;
+use serde_json;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::vec::Drain;
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+use super::action::{Action, BaseAction};
+use super::action_generator::ActionGenerator;
+use super::client::{ActionRequest, ClientError, Notification, ThingClient};
+use super::clock::Clock;
+use super::event::{BaseEvent, Event};
+use super::property::{AsyncValueForwarder, BaseProperty, ForwarderFuture, Property};
+use super::thing::{BaseThing, Thing};
+use super::utils::{lock, read_lock, write_lock};
+
+/// Remote actions relayed by a proxy, by remote ID, with their local name and ID.
+type RemoteActions = Arc<Mutex<HashMap<String, (String, String)>>>;
+
+/// Statuses of remote actions which haven't settled yet.
+const UNSETTLED_STATUSES: [&str; 2] = ["created", "pending"];
+
+/// Status mirrored for remote actions which no longer exist.
+const CANCELLED_STATUS: &str = "cancelled";
+
+/// Forwards new property values to the remote thing.
+struct RemoteForwarder {
+    client: ThingClient,
+    name: String,
+}
+
+impl AsyncValueForwarder for RemoteForwarder {
+    fn set_value(&self, value: serde_json::Value) -> ForwarderFuture {
+        let client = self.client.clone();
+        let name = self.name.clone();
+
+        Box::pin(async move {
+            client.write_property(&name, value).await.map_err(|e| {
+                warn!("Failed to write remote property {}: {}", name, e);
+                "Failed to write remote property"
+            })
+        })
+    }
+}
+
+/// A thing mirroring a web thing served by another server.
+///
+/// Property writes are forwarded to the remote thing over HTTP, while property changes, events
+/// and action status updates are received over its WebSocket. Actions are relayed when the
+/// server uses a `ProxyActionGenerator`. The WebSocket is closed when the proxy is dropped.
+pub struct ProxyThing {
+    thing: BaseThing,
+    client: ThingClient,
+    runtime: Handle,
+    remote_actions: RemoteActions,
+    observation: AbortHandle,
+}
+
+impl ProxyThing {
+    /// Connect to the thing served at a URL and mirror it.
+    ///
+    /// Must be called from within a Tokio runtime, which keeps receiving updates from the remote
+    /// thing and relaying actions for as long as the proxy is alive.
+    pub async fn connect(url: &str) -> Result<Arc<RwLock<Box<dyn Thing>>>, ClientError> {
+        Self::from_client(ThingClient::connect(url).await?).await
+    }
+
+    /// Connect to a server and mirror each of the things it serves.
+    pub async fn connect_all(url: &str) -> Result<Vec<Arc<RwLock<Box<dyn Thing>>>>, ClientError> {
+        let mut things = Vec::new();
+        for client in ThingClient::connect_all(url).await? {
+            things.push(Self::from_client(client).await?);
+        }
+
+        Ok(things)
+    }
+
+    /// Mirror the thing a client is connected to.
+    pub async fn from_client(
+        client: ThingClient,
+    ) -> Result<Arc<RwLock<Box<dyn Thing>>>, ClientError> {
+        let description = serde_json::Value::Object(client.description().clone());
+        let mut thing =
+            BaseThing::from_description(&description).map_err(ClientError::InvalidResponse)?;
+
+        let values = client.read_properties().await?;
+        let names: Vec<String> = thing.get_properties().keys().cloned().collect();
+        for name in names {
+            let metadata = match thing.find_property(&name) {
+                Some(property) => property.get_metadata(),
+                None => continue,
+            };
+            let value = values
+                .get(&name)
+                .cloned()
+                .or_else(|| thing.get_property(&name))
+                .unwrap_or(serde_json::Value::Null);
+            let forwarder = Arc::new(RemoteForwarder {
+                client: client.clone(),
+                name: name.clone(),
+            });
+
+            thing.remove_property(&name);
+            thing.add_property(Box::new(
+                BaseProperty::new(name, value, None, Some(metadata))
+                    .with_async_value_forwarder(forwarder),
+            ));
+        }
+
+        let events: Vec<String> = description
+            .get("events")
+            .and_then(|e| e.as_object())
+            .map(|e| e.keys().cloned().collect())
+            .unwrap_or_default();
+        let events: Vec<&str> = events.iter().map(|e| e.as_str()).collect();
+        let observation = client.observe(&events)?;
+
+        let runtime = Handle::current();
+        let remote_actions = RemoteActions::default();
+        let (abort, registration) = AbortHandle::new_pair();
+        let proxy: Arc<RwLock<Box<dyn Thing>>> = Arc::new(RwLock::new(Box::new(ProxyThing {
+            thing,
+            client: client.clone(),
+            runtime: runtime.clone(),
+            remote_actions: remote_actions.clone(),
+            observation: abort,
+        })));
+
+        let weak = Arc::downgrade(&proxy);
+        let observe = async move {
+            let mut observation = observation;
+            while let Some(notification) = observation.next().await {
+                let thing = match weak.upgrade() {
+                    Some(thing) => thing,
+                    None => break,
+                };
+
+                match notification {
+                    Notification::Connected => {
+                        // Catch up on any changes missed while disconnected.
+                        match client.read_properties().await {
+                            Ok(values) => update_properties(&thing, values),
+                            Err(e) => warn!("Failed to read remote properties: {}", e),
+                        }
+
+                        let remote_ids: Vec<(String, String)> = lock(&remote_actions)
+                            .iter()
+                            .map(|(remote_id, (name, _))| (name.clone(), remote_id.clone()))
+                            .collect();
+                        for (name, remote_id) in remote_ids {
+                            if let Some(status) = remote_status(&client, &name, &remote_id).await {
+                                settle_remote_action(&thing, &remote_actions, &remote_id, &status);
+                            }
+                        }
+                    }
+                    Notification::PropertyStatus(values) => update_properties(&thing, values),
+                    Notification::ActionStatus(request) => {
+                        settle_remote_action(&thing, &remote_actions, &request.id, &request.status)
+                    }
+                    Notification::Event(event) => write_lock(&thing)
+                        .add_event(Box::new(BaseEvent::new(event.name, event.data))),
+                    Notification::Error(error) => {
+                        warn!("Remote thing reported an error: {}", error)
+                    }
+                    Notification::Rejected(reason) => {
+                        warn!("Remote thing rejected the WebSocket: {}", reason)
+                    }
+                }
+            }
+        };
+        runtime.spawn(Abortable::new(observe, registration));
+
+        Ok(proxy)
+    }
+
+    /// Get the client connected to the remote thing.
+    pub fn client(&self) -> &ThingClient {
+        &self.client
+    }
+}
+
+/// Apply property values received from the remote thing and notify subscribers.
+fn update_properties(
+    thing: &Arc<RwLock<Box<dyn Thing>>>,
+    values: serde_json::Map<String, serde_json::Value>,
+) {
+    let mut thing = write_lock(thing);
+    for (name, value) in values {
+        let changed = match thing.find_property(&name) {
+            Some(property) => {
+                property.get_value() != value && property.set_cached_value(value.clone()).is_ok()
+            }
+            None => false,
+        };
+
+        if changed {
+            thing.property_notify(name, value);
+        }
+    }
+}
+
+/// Get the status of a remote action, which is cancelled if the remote action was deleted.
+async fn remote_status(client: &ThingClient, name: &str, remote_id: &str) -> Option<String> {
+    match client.get_action(name, remote_id).await {
+        Ok(ActionRequest { status, .. }) => Some(status),
+        Err(ClientError::Status(404)) => Some(CANCELLED_STATUS.to_owned()),
+        Err(_) => None,
+    }
+}
+
+/// Mirror the status of a remote action once it has settled on the local action relaying it.
+///
+/// Completed remote actions finish the local action, while any other final status, such as
+/// `cancelled`, is copied to it.
+fn settle_remote_action(
+    thing: &Arc<RwLock<Box<dyn Thing>>>,
+    remote_actions: &RemoteActions,
+    remote_id: &str,
+    status: &str,
+) {
+    if UNSETTLED_STATUSES.contains(&status) {
+        return;
+    }
+
+    let local = lock(remote_actions).remove(remote_id);
+    let (name, id) = match local {
+        Some(local) => local,
+        None => return,
+    };
+
+    let mut thing = write_lock(thing);
+    if status == "completed" {
+        thing.finish_action(name, id);
+    } else if let Some(action) = thing.get_action(name, id) {
+        let description = {
+            let mut action = write_lock(&action);
+            action.set_status(status.to_owned());
+            action.as_action_description()
+        };
+        thing.action_notify(description);
+    }
+}
+
+impl Drop for ProxyThing {
+    fn drop(&mut self) {
+        self.observation.abort();
+    }
+}
+
+impl Thing for ProxyThing {
+    fn as_thing_description(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.thing.as_thing_description()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_href(&self) -> String {
+        self.thing.get_href()
+    }
+
+    fn get_href_prefix(&self) -> String {
+        self.thing.get_href_prefix()
+    }
+
+    fn get_ui_href(&self) -> Option<String> {
+        self.thing.get_ui_href()
+    }
+
+    fn set_href_prefix(&mut self, prefix: String) {
+        self.thing.set_href_prefix(prefix)
+    }
+
+    fn set_ui_href(&mut self, href: String) {
+        self.thing.set_ui_href(href)
+    }
+
+    fn get_id(&self) -> String {
+        self.thing.get_id()
+    }
+
+    fn get_title(&self) -> String {
+        self.thing.get_title()
+    }
+
+    fn get_context(&self) -> serde_json::Value {
+        self.thing.get_context()
+    }
+
+    fn get_type(&self) -> Vec<String> {
+        self.thing.get_type()
+    }
+
+    fn get_description(&self) -> String {
+        self.thing.get_description()
+    }
+
+    fn get_property_descriptions(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.thing.get_property_descriptions()
+    }
+
+    fn get_action_descriptions(&self, action_name: Option<String>) -> serde_json::Value {
+        self.thing.get_action_descriptions(action_name)
+    }
+
+    fn get_event_descriptions(&self, event_name: Option<String>) -> serde_json::Value {
+        self.thing.get_event_descriptions(event_name)
+    }
+
+    fn add_property(&mut self, property: Box<dyn Property>) {
+        self.thing.add_property(property)
+    }
+
+    fn remove_property(&mut self, property_name: &str) {
+        self.thing.remove_property(property_name)
+    }
+
+    fn find_property(&mut self, property_name: &str) -> Option<&mut Box<dyn Property>> {
+        self.thing.find_property(property_name)
+    }
+
+    fn get_property(&self, property_name: &str) -> Option<serde_json::Value> {
+        self.thing.get_property(property_name)
+    }
+
+    fn get_properties(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.thing.get_properties()
+    }
+
+    fn has_property(&self, property_name: &str) -> bool {
+        self.thing.has_property(property_name)
+    }
+
+    fn get_action(
+        &self,
+        action_name: String,
+        action_id: String,
+    ) -> Option<Arc<RwLock<Box<dyn Action>>>> {
+        self.thing.get_action(action_name, action_id)
+    }
+
+    fn get_actions(&self) -> Vec<Arc<RwLock<Box<dyn Action>>>> {
+        self.thing.get_actions()
+    }
+
+    fn add_event(&mut self, event: Box<dyn Event>) {
+        self.thing.add_event(event)
+    }
+
+    fn add_available_event(
+        &mut self,
+        name: String,
+        metadata: serde_json::Map<String, serde_json::Value>,
+    ) {
+        self.thing.add_available_event(name, metadata)
+    }
+
+    fn add_action(
+        &mut self,
+        action: Arc<RwLock<Box<dyn Action>>>,
+        input: Option<&serde_json::Value>,
+    ) -> Result<(), &str> {
+        self.thing.add_action(action, input)
+    }
+
+    fn remove_action(&mut self, action_name: String, action_id: String) -> bool {
+        self.thing.remove_action(action_name, action_id)
+    }
+
+    fn add_available_action(
+        &mut self,
+        name: String,
+        metadata: serde_json::Map<String, serde_json::Value>,
+    ) {
+        self.thing.add_available_action(name, metadata)
+    }
+
+    fn add_subscriber(&mut self, ws_id: String) {
+        self.thing.add_subscriber(ws_id)
+    }
+
+    fn remove_subscriber(&mut self, ws_id: String) {
+        self.thing.remove_subscriber(ws_id)
+    }
+
+    fn add_event_subscriber(&mut self, name: String, ws_id: String) {
+        self.thing.add_event_subscriber(name, ws_id)
+    }
+
+    fn remove_event_subscriber(&mut self, name: String, ws_id: String) {
+        self.thing.remove_event_subscriber(name, ws_id)
+    }
+
+    fn property_notify(&mut self, name: String, value: serde_json::Value) {
+        self.thing.property_notify(name, value)
+    }
+
+    fn action_notify(&mut self, action: serde_json::Map<String, serde_json::Value>) {
+        self.thing.action_notify(action)
+    }
+
+    fn event_notify(&mut self, name: String, event: serde_json::Map<String, serde_json::Value>) {
+        self.thing.event_notify(name, event)
+    }
+
+    fn start_action(&mut self, name: String, id: String) {
+        self.thing.start_action(name, id)
+    }
+
+    fn cancel_action(&mut self, name: String, id: String) {
+        self.thing.cancel_action(name, id)
+    }
+
+    fn finish_action(&mut self, name: String, id: String) {
+        self.thing.finish_action(name, id)
+    }
+
+    fn drain_queue(&mut self, ws_id: String) -> Vec<Drain<'_, String>> {
+        self.thing.drain_queue(ws_id)
+    }
+}
+
+/// An action relayed to a remote thing.
+///
+/// The action is requested from the remote thing when performed, and finishes when the remote
+/// action completes. If the remote action is cancelled or fails instead, its status is mirrored,
+/// and if it is deleted, the action is marked as cancelled once the proxy notices, i.e. when it
+/// reconnects. Cancelling or removing it cancels the remote action.
+pub struct ProxyAction {
+    action: BaseAction,
+    client: ThingClient,
+    runtime: Handle,
+    remote_actions: RemoteActions,
+}
+
+impl ProxyAction {
+    /// Find the remote ID of this action, if it has been requested.
+    fn remote_id(&self) -> Option<String> {
+        let id = self.action.get_id();
+        lock(&self.remote_actions)
+            .iter()
+            .find(|(_, (_, local_id))| *local_id == id)
+            .map(|(remote_id, _)| remote_id.clone())
+    }
+}
+
+impl Action for ProxyAction {
+    fn set_href_prefix(&mut self, prefix: String) {
+        self.action.set_href_prefix(prefix)
+    }
+
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.action.set_clock(clock)
+    }
+
+    fn get_id(&self) -> String {
+        self.action.get_id()
+    }
+
+    fn get_name(&self) -> String {
+        self.action.get_name()
+    }
+
+    fn get_href(&self) -> String {
+        self.action.get_href()
+    }
+
+    fn get_status(&self) -> String {
+        self.action.get_status()
+    }
+
+    fn get_time_requested(&self) -> String {
+        self.action.get_time_requested()
+    }
+
+    fn get_time_completed(&self) -> Option<String> {
+        self.action.get_time_completed()
+    }
+
+    fn get_input(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        self.action.get_input()
+    }
+
+    fn get_thing(&self) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.action.get_thing()
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.action.set_status(status)
+    }
+
+    fn start(&mut self) {
+        self.action.start()
+    }
+
+    fn perform_action(&mut self) {
+        let thing = match self.get_thing() {
+            Some(thing) => Arc::downgrade(&thing),
+            None => return,
+        };
+
+        let client = self.client.clone();
+        let remote_actions = self.remote_actions.clone();
+        let input = self.get_input().map(serde_json::Value::Object);
+        let name = self.get_name();
+        let id = self.get_id();
+
+        self.runtime.spawn(async move {
+            let request = match client.request_action(&name, input).await {
+                Ok(request) => request,
+                Err(e) => {
+                    warn!("Failed to request remote action {}: {}", name, e);
+                    if let Some(thing) = thing.upgrade() {
+                        write_lock(&thing).remove_action(name, id);
+                    }
+                    return;
+                }
+            };
+
+            lock(&remote_actions).insert(request.id.clone(), (name.clone(), id));
+
+            // The remote action may have settled before it was registered.
+            let status = remote_status(&client, &name, &request.id).await;
+            if let (Some(status), Some(thing)) = (status, thing.upgrade()) {
+                settle_remote_action(&thing, &remote_actions, &request.id, &status);
+            }
+        });
+    }
+
+    fn cancel(&mut self) {
+        self.action.cancel();
+
+        let remote_id = match self.remote_id() {
+            Some(remote_id) => remote_id,
+            None => return,
+        };
+
+        lock(&self.remote_actions).remove(&remote_id);
+
+        let client = self.client.clone();
+        let name = self.get_name();
+        self.runtime.spawn(async move {
+            if let Err(e) = client.cancel_action(&name, &remote_id).await {
+                warn!("Failed to cancel remote action {}: {}", name, e);
+            }
+        });
+    }
+
+    fn finish(&mut self) {
+        self.action.finish()
+    }
+}
+
+/// Action generator relaying actions on proxy things to their remote things.
+///
+/// Actions on any other thing are generated by the wrapped generator.
+pub struct ProxyActionGenerator(Box<dyn ActionGenerator>);
+
+impl ProxyActionGenerator {
+    /// Create a new ProxyActionGenerator, falling back to `generator` for other things.
+    pub fn new(generator: Box<dyn ActionGenerator>) -> Self {
+        ProxyActionGenerator(generator)
+    }
+}
+
+impl ActionGenerator for ProxyActionGenerator {
+    fn generate(
+        &self,
+        thing: Weak<RwLock<Box<dyn Thing>>>,
+        name: String,
+        input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+        let proxy = thing.upgrade().and_then(|t| {
+            let t = read_lock(&t);
+            t.as_any().downcast_ref::<ProxyThing>().map(|proxy| {
+                (
+                    proxy.client.clone(),
+                    proxy.runtime.clone(),
+                    proxy.remote_actions.clone(),
+                )
+            })
+        });
+
+        let (client, runtime, remote_actions) = match proxy {
+            Some(proxy) => proxy,
+            None => return self.0.generate(thing, name, input),
+        };
+
+        let input = match input {
+            Some(serde_json::Value::Object(input)) => Some(input.clone()),
+            Some(_) => return None,
+            None => None,
+        };
+
+        Some(Box::new(ProxyAction {
+            action: BaseAction::new(Uuid::new_v4().to_string(), name, input, thing),
+            client,
+            runtime,
+            remote_actions,
+        }))
+    }
+}
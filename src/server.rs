@@ -3,66 +3,1480 @@ use actix;
 use actix::prelude::*;
 use actix_web;
 use actix_web::body::EitherBody;
-use actix_web::dev::{Server, ServiceRequest, ServiceResponse};
+use actix_web::dev::{Server, ServerHandle, ServiceRequest, ServiceResponse};
 use actix_web::dev::{Service, Transform};
 use actix_web::guard;
-use actix_web::http::header::HeaderValue;
+use actix_web::http::header;
 use actix_web::web::Data;
-use actix_web::{middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer};
-use actix_web_actors::ws;
+use actix_web::{middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer, Scope};
+use actix_web_actors::ws::{self, CloseCode, CloseReason};
 use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::FutureExt;
+use governor::clock::{Clock as _, DefaultClock};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
 use hostname;
+pub use ipnet::IpNet;
 use libmdns;
-#[cfg(feature = "ssl")]
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use log::warn;
 use serde_json;
 use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
 use std::marker::{Send, Sync};
-use std::sync::{Arc, RwLock};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroU32;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, TryLockError};
 use std::task::{Context, Poll};
 use std::time::Duration;
 use uuid::Uuid;
 
+use super::action::Action;
 pub use super::action_generator::ActionGenerator;
+use super::description::{to_td11, DescriptionFormat, WEBSOCKET_SUBPROTOCOL};
+use super::property::{AsyncValueForwarder, SharedValueForwarder};
 use super::thing::Thing;
-use super::utils::get_addresses;
+#[cfg(all(feature = "self-signed", any(feature = "ssl", feature = "rustls")))]
+use super::tls::self_signed;
+#[cfg(any(feature = "ssl", feature = "rustls"))]
+use super::tls::TlsSource;
+#[cfg(any(feature = "ssl", feature = "rustls"))]
+pub use super::tls::{ClientIdentity, TlsHandle};
+use super::utils::{get_interface_addresses, is_valid_path_segment, lock, read_lock, write_lock};
 
 const SERVICE_TYPE: &str = "_webthing._tcp";
 
+/// Route pattern matching the path of a child thing below its parent, i.e. `a/things/b`.
+const CHILD_PATH_PATTERN: &str = "{child_path:[^/]+(/things/[^/]+)*}";
+
+/// Default time to wait for an asynchronous value forwarder.
+const DEFAULT_FORWARDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time to wait for connections to close when shutting down, as in actix web.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Status reported for actions cancelled because the server shuts down.
+const CANCELLED_STATUS: &str = "cancelled";
+
+/// Default maximum size of JSON request bodies, as in actix-web.
+const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// Default maximum size of WebSocket frames, as in actix-web.
+const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// How often rate limiters forget clients that are back to their full quota.
+const RATE_LIMIT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Represents the things managed by the server.
 #[derive(Clone)]
-pub enum ThingsType {
-    /// Set when there are multiple things managed by the server
-    Multiple(Vec<Arc<RwLock<Box<dyn Thing>>>>, String),
-    /// Set when there is only one thing
+pub enum ThingsType {
+    /// Set when there are multiple things managed by the server
+    Multiple(Vec<Arc<RwLock<Box<dyn Thing>>>>, String),
+    /// Set when there is only one thing
+    Single(Arc<RwLock<Box<dyn Thing>>>),
+}
+
+/// How the server treats things whose Thing Description does not validate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DescriptionValidation {
+    /// Do not validate Thing Descriptions.
+    #[default]
+    Disabled,
+    /// Log a warning for each problem found at startup.
+    Warn,
+    /// Refuse to start if any problem is found.
+    Strict,
+}
+
+/// An address for the server to listen on.
+///
+/// Parsed from strings such as `0.0.0.0`, `[::]`, `192.168.1.10`, `eth0` or
+/// `unix:/run/webthing.sock`. IP addresses and interfaces are bound on the server's port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenAddress {
+    /// An IP address, i.e. `0.0.0.0` for all IPv4 addresses or `::` for all addresses.
+    ///
+    /// On most systems, `::` accepts IPv4 connections too, so it should not be combined with
+    /// `0.0.0.0`.
+    Ip(IpAddr),
+    /// All addresses of a network interface, except IPv6 link-local ones.
+    Interface(String),
+    /// A Unix domain socket, i.e. for use behind a local reverse proxy.
+    ///
+    /// Always serves plain HTTP. Requests carry the Host header the proxy sends, so set a
+    /// hostname or allow the proxy's host with `WebThingServer::with_allowed_hosts()`.
+    Unix(PathBuf),
+}
+
+impl ListenAddress {
+    /// Get the IP addresses this resolves to, without expanding unspecified addresses.
+    fn resolve(&self) -> io::Result<Vec<IpAddr>> {
+        match self {
+            ListenAddress::Ip(ip) => Ok(vec![*ip]),
+            ListenAddress::Interface(name) => {
+                let ips: Vec<IpAddr> = get_interface_addresses()
+                    .into_iter()
+                    .filter(|(iface, ip)| iface == name && !is_ipv6_link_local(ip))
+                    .map(|(_, ip)| ip)
+                    .collect();
+                if ips.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No addresses found for interface {}", name),
+                    ));
+                }
+
+                Ok(ips)
+            }
+            ListenAddress::Unix(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+impl FromStr for ListenAddress {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("Missing Unix socket path");
+            }
+
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+
+        let ip = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        if let Ok(ip) = ip.parse() {
+            return Ok(ListenAddress::Ip(ip));
+        }
+
+        if s.is_empty() || s.contains(|c: char| c.is_whitespace() || "[]:/".contains(c)) {
+            return Err("Invalid listen address");
+        }
+
+        Ok(ListenAddress::Interface(s.to_owned()))
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Ip(IpAddr::V6(ip)) => write!(f, "[{}]", ip),
+            ListenAddress::Ip(ip) => write!(f, "{}", ip),
+            ListenAddress::Interface(name) => write!(f, "{}", name),
+            ListenAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl From<IpAddr> for ListenAddress {
+    fn from(ip: IpAddr) -> Self {
+        ListenAddress::Ip(ip)
+    }
+}
+
+fn is_ipv6_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+        IpAddr::V4(_) => false,
+    }
+}
+
+/// Expand unspecified addresses to the addresses of all interfaces they accept connections on.
+fn expand_addresses(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let interfaces = get_interface_addresses();
+    let mut expanded: Vec<IpAddr> = Vec::new();
+    for ip in ips {
+        let matching: Vec<IpAddr> = match ip {
+            IpAddr::V4(v4) if v4.is_unspecified() => interfaces
+                .iter()
+                .map(|(_, ip)| *ip)
+                .filter(IpAddr::is_ipv4)
+                .collect(),
+            IpAddr::V6(v6) if v6.is_unspecified() => interfaces.iter().map(|(_, ip)| *ip).collect(),
+            _ => vec![*ip],
+        };
+
+        for ip in matching {
+            if !expanded.contains(&ip) {
+                expanded.push(ip);
+            }
+        }
+    }
+
+    expanded.sort_unstable();
+    expanded
+}
+
+/// Get the names a server listening on the given addresses is reachable under, without the port.
+///
+/// IPv6 addresses are enclosed in brackets, as in a `Host` header.
+fn host_names(addresses: &[ListenAddress], hostname: Option<&str>) -> Vec<String> {
+    let mut names = Vec::new();
+    let ips: Vec<IpAddr> = addresses
+        .iter()
+        .flat_map(|address| address.resolve().unwrap_or_default())
+        .collect();
+
+    if ips.iter().any(|ip| ip.is_loopback() || ip.is_unspecified()) {
+        names.push("localhost".to_owned());
+    }
+
+    if ips.iter().any(|ip| !ip.is_loopback()) {
+        if let Ok(system_hostname) = hostname::get() {
+            let name = system_hostname.to_string_lossy().to_lowercase();
+            names.push(format!("{}.local", name));
+        }
+    }
+
+    for ip in expand_addresses(&ips) {
+        names.push(match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        });
+    }
+
+    if let Some(hostname) = hostname {
+        names.push(hostname.to_lowercase());
+    }
+
+    names
+}
+
+/// Split a `Host` header into the host, without brackets around IPv6 addresses, and the port.
+fn split_host(host: &str) -> Option<(&str, Option<u16>)> {
+    let (name, port) = match host.strip_prefix('[') {
+        Some(rest) => {
+            let (ip, rest) = rest.split_once(']')?;
+            match rest {
+                "" => (ip, None),
+                rest => (ip, Some(rest.strip_prefix(':')?)),
+            }
+        }
+        None => match host.rsplit_once(':') {
+            Some((name, port)) => (name, Some(port)),
+            None => (host, None),
+        },
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    match port {
+        Some(port) => Some((name, Some(port.parse().ok()?))),
+        None => Some((name, None)),
+    }
+}
+
+/// A pattern of hosts the server answers to, on top of the names and addresses it listens on.
+///
+/// Parsed from strings such as `thing.example`, `thing.example:8443`, `*.example.com`,
+/// `10.0.0.0/8` or `fd00::/8`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostPattern {
+    /// A host name or IP address, on any port unless one is given.
+    Exact(String, Option<u16>),
+    /// Any subdomain of a domain, i.e. `a.example.com` for `*.example.com`, on any port unless
+    /// one is given.
+    Wildcard(String, Option<u16>),
+    /// Any IP address in a network, on any port.
+    Network(IpNet),
+}
+
+impl HostPattern {
+    /// Check whether a host, as split by `split_host()`, matches the pattern.
+    fn matches(&self, host: &str, port: Option<u16>) -> bool {
+        let port_matches = |expected: &Option<u16>| expected.is_none() || *expected == port;
+        match self {
+            HostPattern::Exact(name, expected) => host == name && port_matches(expected),
+            HostPattern::Wildcard(domain, expected) => {
+                host.strip_suffix(domain.as_str())
+                    .map(|sub| sub.len() > 1 && sub.ends_with('.'))
+                    == Some(true)
+                    && port_matches(expected)
+            }
+            HostPattern::Network(network) => host
+                .parse::<IpAddr>()
+                .map(|ip| network.contains(&ip))
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if s.contains('/') {
+            return s
+                .parse()
+                .map(HostPattern::Network)
+                .map_err(|_| "Invalid host network");
+        }
+
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(HostPattern::Exact(ip.to_string(), None));
+        }
+
+        let (host, port) = split_host(&s).ok_or("Invalid host pattern")?;
+        if host.contains(|c: char| c.is_whitespace() || "[]/".contains(c)) {
+            return Err("Invalid host pattern");
+        }
+        if host.parse::<IpAddr>().is_err() && host.contains(':') {
+            return Err("Invalid host pattern");
+        }
+
+        match host.strip_prefix("*.") {
+            Some(domain) if !domain.is_empty() && !domain.contains('*') => {
+                Ok(HostPattern::Wildcard(domain.to_owned(), port))
+            }
+            Some(_) => Err("Invalid host pattern"),
+            None if host.contains('*') => Err("Invalid host pattern"),
+            None => Ok(HostPattern::Exact(host.to_owned(), port)),
+        }
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (host, port) = match self {
+            HostPattern::Network(network) => return write!(f, "{}", network),
+            HostPattern::Exact(host, port) => (host.clone(), port),
+            HostPattern::Wildcard(domain, port) => (format!("*.{}", domain), port),
+        };
+
+        match port {
+            Some(port) if host.contains(':') => write!(f, "[{}]:{}", host, port),
+            Some(port) => write!(f, "{}:{}", host, port),
+            None => write!(f, "{}", host),
+        }
+    }
+}
+
+/// Methods allowed in cross-origin requests, covering the whole Web Thing API.
+const CORS_METHODS: [&str; 6] = ["GET", "HEAD", "PUT", "POST", "DELETE", "OPTIONS"];
+
+/// Request headers allowed in cross-origin requests by default.
+const CORS_HEADERS: [&str; 5] = [
+    "Origin",
+    "Content-Type",
+    "Accept",
+    "X-Requested-With",
+    "Authorization",
+];
+
+/// Policy for cross-origin requests from browsers.
+///
+/// The default allows any origin without credentials, answering with
+/// `Access-Control-Allow-Origin: *`. Preflight `OPTIONS` requests are answered by the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorsPolicy {
+    origins: Vec<String>,
+    credentials: bool,
+    allowed_headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        CorsPolicy {
+            origins: vec!["*".to_owned()],
+            credentials: false,
+            allowed_headers: CORS_HEADERS.iter().map(|h| h.to_string()).collect(),
+            exposed_headers: Vec::new(),
+            max_age: None,
+        }
+    }
+}
+
+impl CorsPolicy {
+    /// Only allow the given origins, i.e. `https://app.example.com`, or patterns of them, i.e.
+    /// `https://*.example.com`. `*` allows any origin.
+    ///
+    /// An empty list refuses all cross-origin requests.
+    pub fn with_origins(origins: Vec<String>) -> Self {
+        CorsPolicy {
+            origins,
+            ..Default::default()
+        }
+    }
+
+    /// Allow browsers to send cookies and `Authorization` headers. Needs explicit origins.
+    pub fn with_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Allow another request header, on top of `Origin`, `Content-Type`, `Accept`,
+    /// `X-Requested-With` and `Authorization`.
+    pub fn with_allowed_header(mut self, header: &str) -> Self {
+        self.allowed_headers.push(header.to_owned());
+        self
+    }
+
+    /// Let scripts read the given response headers.
+    pub fn with_exposed_headers(mut self, headers: Vec<String>) -> Self {
+        self.exposed_headers = headers;
+        self
+    }
+
+    /// Let browsers cache preflight responses for a while.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Check that the origins are valid, and that credentials are only allowed for explicit
+    /// origins.
+    fn check(&self) -> Result<(), String> {
+        for origin in &self.origins {
+            if !is_origin_pattern(origin) {
+                return Err(format!("Invalid CORS origin: {}", origin));
+            }
+        }
+
+        if self.credentials && self.origins.iter().any(|origin| origin == "*") {
+            return Err("CORS credentials cannot be allowed for any origin".to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Build the middleware enforcing the policy.
+    ///
+    /// It is applied by `start()`; wrap an `App` configured with `make_config()` in it to test
+    /// cross-origin requests.
+    pub fn middleware(&self) -> actix_cors::Cors {
+        let mut cors = actix_cors::Cors::default()
+            .allowed_methods(CORS_METHODS)
+            .allowed_headers(self.allowed_headers.iter().map(|h| h.as_str()))
+            .max_age(self.max_age.map(|max_age| max_age.as_secs() as usize));
+
+        if !self.exposed_headers.is_empty() {
+            cors = cors.expose_headers(self.exposed_headers.iter().map(|h| h.as_str()));
+        }
+
+        if self.origins.iter().any(|origin| origin == "*") {
+            cors = cors.allow_any_origin().send_wildcard();
+        } else {
+            let origins = self.origins.clone();
+            cors = cors.allowed_origin_fn(move |origin, _| match origin.to_str() {
+                Ok(origin) => origins
+                    .iter()
+                    .any(|pattern| origin_matches(pattern, origin)),
+                Err(_) => false,
+            });
+        }
+
+        if self.credentials {
+            cors = cors.supports_credentials();
+        }
+
+        cors
+    }
+}
+
+/// Check whether a string is `*`, or an origin like `https://example.com:8443` whose host may
+/// start with a `*.` wildcard.
+fn is_origin_pattern(pattern: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+
+    let (scheme, host) = match pattern.split_once("://") {
+        Some(parts) => parts,
+        None => return false,
+    };
+    if scheme.is_empty()
+        || !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    {
+        return false;
+    }
+
+    let host = host.strip_prefix("*.").unwrap_or(host);
+    match split_host(host) {
+        Some((name, _)) => !name.contains(|c: char| c.is_whitespace() || "*/".contains(c)),
+        None => false,
+    }
+}
+
+/// Check whether an origin matches an allowed origin or pattern, from `is_origin_pattern()`.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let origin = origin.to_lowercase();
+    if pattern == "*" || pattern == origin {
+        return true;
+    }
+
+    // The port, if any, is part of the domain, so it has to be the same.
+    match pattern.split_once("://*.") {
+        Some((scheme, domain)) => {
+            origin
+                .strip_prefix(scheme)
+                .and_then(|origin| origin.strip_prefix("://"))
+                .and_then(|host| host.strip_suffix(domain))
+                .and_then(|subdomain| subdomain.strip_suffix('.'))
+                .map(|subdomain| !subdomain.is_empty() && !subdomain.contains([':', '/']))
+                == Some(true)
+        }
+        None => false,
+    }
+}
+
+/// Hosts the server answers to, and the proxies trusted to tell which host was asked for.
+struct AllowedHosts {
+    disabled: bool,
+    /// Names and addresses the server listens on, with and without its port.
+    names: RwLock<Vec<String>>,
+    patterns: RwLock<Vec<HostPattern>>,
+    trusted_proxies: RwLock<Vec<IpNet>>,
+}
+
+impl AllowedHosts {
+    fn new(disabled: bool) -> Self {
+        AllowedHosts {
+            disabled,
+            names: RwLock::new(Vec::new()),
+            patterns: RwLock::new(Vec::new()),
+            trusted_proxies: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Set the names the server listens on, from `host_names()`.
+    fn set_names(&self, names: Vec<String>, port: u16) {
+        let mut hosts = Vec::new();
+        for name in names {
+            hosts.push(format!("{}:{}", name, port));
+            hosts.push(name);
+        }
+        *write_lock(&self.names) = hosts;
+    }
+
+    /// Check whether the server answers to a host.
+    fn allows(&self, host: &str) -> bool {
+        if self.disabled {
+            return true;
+        }
+
+        let host = host.to_lowercase();
+        if read_lock(&self.names).contains(&host) {
+            return true;
+        }
+
+        match split_host(&host) {
+            Some((name, port)) => read_lock(&self.patterns)
+                .iter()
+                .any(|pattern| pattern.matches(name, port)),
+            None => false,
+        }
+    }
+
+    /// Get the scheme and host a request was made to, as seen by the client.
+    ///
+    /// The `Forwarded` and `X-Forwarded-Host` and `-Proto` headers are only honoured from
+    /// trusted proxies.
+    fn origin(&self, req: &HttpRequest) -> (String, Option<String>) {
+        let scheme = if req.app_config().secure() {
+            "https"
+        } else {
+            "http"
+        };
+        let host = req
+            .headers()
+            .get("Host")
+            .and_then(|host| host.to_str().ok())
+            .map(|host| host.to_owned());
+
+        if !self.is_from_trusted_proxy(req) {
+            return (scheme.to_owned(), host);
+        }
+
+        let (forwarded_host, forwarded_proto) = forwarded(req);
+        (
+            forwarded_proto.unwrap_or_else(|| scheme.to_owned()),
+            forwarded_host.or(host),
+        )
+    }
+
+    /// Get the address of the client that made a request.
+    ///
+    /// Behind a trusted proxy, this is the address the proxy forwarded the request for.
+    fn client(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr().map(|peer| peer.ip());
+        if !self.is_from_trusted_proxy(req) {
+            return peer;
+        }
+
+        forwarded_client(req, &read_lock(&self.trusted_proxies)).or(peer)
+    }
+
+    /// Check whether a request was received from a trusted proxy.
+    fn is_from_trusted_proxy(&self, req: &HttpRequest) -> bool {
+        req.peer_addr()
+            .map(|peer| {
+                read_lock(&self.trusted_proxies)
+                    .iter()
+                    .any(|proxy| proxy.contains(&peer.ip()))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Read the host and scheme a proxy forwarded a request for.
+///
+/// The `Forwarded` header takes precedence over `X-Forwarded-Host` and `X-Forwarded-Proto`.
+/// Each proxy appends its values, so only the last ones, set by the trusted proxy the request was
+/// received from, are used, as earlier ones could be made up by the client.
+fn forwarded(req: &HttpRequest) -> (Option<String>, Option<String>) {
+    let header = |name: &str| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+
+    let mut host = None;
+    let mut proto = None;
+    if let Some(element) = header("Forwarded") {
+        for pair in element.split(';') {
+            if let Some((key, value)) = pair.split_once('=') {
+                let value = value.trim().trim_matches('"');
+                match key.trim().to_lowercase().as_str() {
+                    "host" if !value.is_empty() => host = Some(value.to_owned()),
+                    "proto" if !value.is_empty() => proto = Some(value.to_lowercase()),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    let proto = proto
+        .or_else(|| header("X-Forwarded-Proto").map(|p| p.to_lowercase()))
+        .filter(|proto| proto == "http" || proto == "https");
+    (host.or_else(|| header("X-Forwarded-Host")), proto)
+}
+
+/// Read the address of the client a request was forwarded for.
+///
+/// Each proxy appends the address it received the request from, so the addresses are walked
+/// from the last one, skipping trusted proxies, as earlier ones could be made up by the client.
+fn forwarded_client(req: &HttpRequest, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let values = |name: &str| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|value| value.trim().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let mut addresses: Vec<Option<IpAddr>> = values("Forwarded")
+        .iter()
+        .filter_map(|element| {
+            element
+                .split(';')
+                .find_map(|pair| match pair.split_once('=') {
+                    Some((key, value)) if key.trim().eq_ignore_ascii_case("for") => {
+                        Some(parse_forwarded_address(value))
+                    }
+                    _ => None,
+                })
+        })
+        .collect();
+    if addresses.is_empty() {
+        addresses = values("X-Forwarded-For")
+            .iter()
+            .map(|value| parse_forwarded_address(value))
+            .collect();
+    }
+
+    for address in addresses.into_iter().rev() {
+        match address {
+            Some(ip) if trusted_proxies.iter().any(|proxy| proxy.contains(&ip)) => continue,
+            address => return address,
+        }
+    }
+
+    None
+}
+
+/// Parse an address forwarded by a proxy, which may be quoted and include a port.
+///
+/// Returns `None` for obfuscated identifiers such as `unknown`.
+fn parse_forwarded_address(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// A number of requests allowed within a period.
+///
+/// Requests are replenished evenly over the period, and all of them can be made in a burst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    requests: u32,
+    period: Duration,
+}
+
+impl RateLimit {
+    /// Allow `requests` requests every `period`.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        RateLimit { requests, period }
+    }
+
+    /// Allow `requests` requests every second.
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    /// Allow `requests` requests every minute.
+    pub fn per_minute(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(60))
+    }
+
+    /// Get the quota enforcing this limit, or `None` if no request would be allowed.
+    fn quota(&self) -> Option<Quota> {
+        let burst = NonZeroU32::new(self.requests)?;
+        Quota::with_period(self.period / self.requests).map(|quota| quota.allow_burst(burst))
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} requests per {:?}", self.requests, self.period)
+    }
+}
+
+/// Limits protecting things from clients sending too many or too large requests.
+///
+/// Rate limits apply to property writes and action requests, over HTTP and WebSockets, and the
+/// per-client limit to every message sent over a WebSocket. Requests over a limit are answered
+/// with 429 Too Many Requests, or an error message on WebSockets.
+///
+/// The default doesn't limit the rate of requests, and allows JSON bodies of up to 2 MiB and
+/// WebSocket frames of up to 64 KiB.
+#[derive(Clone, Debug)]
+pub struct RequestLimits {
+    per_client: Option<RateLimit>,
+    per_thing: Option<RateLimit>,
+    per_interaction: Option<RateLimit>,
+    max_body_size: usize,
+    max_frame_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            per_client: None,
+            per_thing: None,
+            per_interaction: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl RequestLimits {
+    /// Limit the requests of each client, identified by its IP address.
+    ///
+    /// Behind a trusted proxy, the client address forwarded by the proxy is used. Clients
+    /// connecting over Unix domain sockets share one limit.
+    pub fn with_per_client(mut self, limit: RateLimit) -> Self {
+        self.per_client = Some(limit);
+        self
+    }
+
+    /// Limit the requests to each thing, from all clients together.
+    pub fn with_per_thing(mut self, limit: RateLimit) -> Self {
+        self.per_thing = Some(limit);
+        self
+    }
+
+    /// Limit the requests to each property and action of a thing, from all clients together.
+    pub fn with_per_interaction(mut self, limit: RateLimit) -> Self {
+        self.per_interaction = Some(limit);
+        self
+    }
+
+    /// Set the maximum size of JSON request bodies, in bytes.
+    ///
+    /// Larger bodies are answered with 413 Payload Too Large.
+    pub fn with_max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Set the maximum size of WebSocket frames, in bytes.
+    ///
+    /// A WebSocket sending a larger frame is sent an error message and closed.
+    pub fn with_max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// Check that every rate limit allows some requests.
+    fn check(&self) -> Result<(), String> {
+        for limit in [self.per_client, self.per_thing, self.per_interaction]
+            .iter()
+            .flatten()
+        {
+            if limit.quota().is_none() {
+                return Err(format!("Invalid rate limit: {}", limit));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Rate limiters shared by all server threads, keyed by client, thing and interaction.
+struct RateLimiters {
+    per_client: Option<DefaultKeyedRateLimiter<Option<IpAddr>>>,
+    per_thing: Option<DefaultKeyedRateLimiter<String>>,
+    per_interaction: Option<DefaultKeyedRateLimiter<(String, String)>>,
+}
+
+impl RateLimiters {
+    fn new(limits: &RequestLimits) -> Self {
+        fn limiter<K: Clone + Eq + Hash>(
+            limit: Option<RateLimit>,
+        ) -> Option<DefaultKeyedRateLimiter<K>> {
+            limit
+                .and_then(|limit| limit.quota())
+                .map(RateLimiter::dashmap)
+        }
+
+        RateLimiters {
+            per_client: limiter(limits.per_client),
+            per_thing: limiter(limits.per_thing),
+            per_interaction: limiter(limits.per_interaction),
+        }
+    }
+
+    /// Count a request from a client, returning how long to wait if it is over the limit.
+    fn check_client(&self, client: Option<IpAddr>) -> Result<(), Duration> {
+        check_key(&self.per_client, &client)
+    }
+
+    /// Count a request to a property or action of a thing, e.g. `properties/on`, returning how
+    /// long to wait if it is over the limit.
+    fn check_interaction(
+        &self,
+        thing: &Arc<RwLock<Box<dyn Thing>>>,
+        interaction: &str,
+    ) -> Result<(), Duration> {
+        if self.per_thing.is_none() && self.per_interaction.is_none() {
+            return Ok(());
+        }
+
+        let id = read_lock(thing).get_id();
+        check_key(&self.per_thing, &id)?;
+        check_key(&self.per_interaction, &(id, interaction.to_owned()))
+    }
+
+    /// Forget clients, things and interactions that are back to their full quota.
+    fn retain_recent(&self) {
+        if let Some(ref limiter) = self.per_client {
+            limiter.retain_recent();
+            limiter.shrink_to_fit();
+        }
+        if let Some(ref limiter) = self.per_thing {
+            limiter.retain_recent();
+            limiter.shrink_to_fit();
+        }
+        if let Some(ref limiter) = self.per_interaction {
+            limiter.retain_recent();
+            limiter.shrink_to_fit();
+        }
+    }
+}
+
+fn check_key<K: Clone + Eq + Hash>(
+    limiter: &Option<DefaultKeyedRateLimiter<K>>,
+    key: &K,
+) -> Result<(), Duration> {
+    match limiter {
+        Some(limiter) => limiter
+            .check_key(key)
+            .map_err(|not_until| not_until.wait_time_from(DefaultClock::default().now())),
+        None => Ok(()),
+    }
+}
+
+/// A thing served by the server.
+struct ThingSlot {
+    /// Path segment the thing is served under.
+    path: String,
+    /// Position-based index, served as an alias when enabled.
+    index: usize,
+    thing: Arc<RwLock<Box<dyn Thing>>>,
+}
+
+/// Things served by a server, keyed by the path segment they are mounted at.
+struct ThingSlots {
+    things: Vec<ThingSlot>,
+    next_index: usize,
+    index_aliases: bool,
+}
+
+impl ThingSlots {
+    /// Find the thing served under a path segment, or under an index alias if enabled.
+    fn find(&self, path: &str) -> Option<usize> {
+        self.things
+            .iter()
+            .position(|slot| slot.path == path)
+            .or_else(|| {
+                if !self.index_aliases {
+                    return None;
+                }
+
+                let index = path.parse::<usize>().ok()?;
+                self.things.iter().position(|slot| slot.index == index)
+            })
+    }
+
+    /// Determine whether a path segment is already in use, or reserved for index aliases.
+    fn is_taken(&self, path: &str) -> bool {
+        (self.index_aliases && is_index(path)) || self.things.iter().any(|slot| slot.path == path)
+    }
+
+    /// Find a free path segment for a thing, derived from its ID.
+    ///
+    /// The last segment of the ID is used if it is free, falling back to the whole ID and then
+    /// to a numbered suffix.
+    fn free_path(&self, id: &str) -> String {
+        let short = slugify(id.rsplit([':', '/']).find(|s| !s.is_empty()).unwrap_or(""));
+        let full = slugify(id);
+
+        [short, full.clone()]
+            .into_iter()
+            .find(|path| !path.is_empty() && !self.is_taken(path))
+            .unwrap_or_else(|| {
+                let base = if full.is_empty() {
+                    "thing".to_owned()
+                } else {
+                    full
+                };
+                (2..)
+                    .map(|n| format!("{}-{}", base, n))
+                    .find(|path| !self.is_taken(path))
+                    .unwrap()
+            })
+    }
+}
+
+/// Determine whether a path segment looks like an index, i.e. `/0`.
+fn is_index(path: &str) -> bool {
+    path.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Turn a string into a URL path segment, keeping only lowercase alphanumerics, `-` and `_`.
+fn slugify(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// How the server is advertised over mDNS.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MdnsMode {
+    /// Do not advertise the server, i.e. in containers without multicast.
+    Disabled,
+    /// Advertise a single service for the server.
+    #[default]
+    Server,
+    /// Advertise one service per thing, named after its title, instead of one for the server.
+    ///
+    /// Services are added and withdrawn as things are added to and removed from a running
+    /// server. Servers managing a single thing advertise a single service either way.
+    PerThing,
+}
+
+/// A service advertised over mDNS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdvertisedService {
+    /// Instance name, i.e. `My Lamp`.
+    pub name: String,
+    /// TXT records, i.e. `path=/things/my-lamp-1234`.
+    pub txt: Vec<String>,
+}
+
+impl AdvertisedService {
+    /// Describe the service advertised under a key.
+    ///
+    /// Instance names must be unique, so names already `taken` by another key get the key
+    /// appended, i.e. `My Lamp (my-lamp-2)`. The `path` record is replaced by any of `records`
+    /// with the same key.
+    fn new(name: String, key: &str, path: &str, records: &[String], taken: &[&str]) -> Self {
+        let name = [name.clone(), format!("{} ({})", name, key)]
+            .into_iter()
+            .chain((2..).map(|n| format!("{} ({} {})", name, key, n)))
+            .find(|candidate| !taken.contains(&candidate.as_str()))
+            .unwrap();
+
+        let mut txt = vec![format!("path={}", path)];
+        for record in records {
+            let key = record.split('=').next().unwrap_or_default();
+            txt.retain(|r| r.split('=').next() != Some(key));
+            txt.push(record.clone());
+        }
+        txt.retain(|record| {
+            // Longer records cannot be encoded, and libmdns panics on them.
+            let valid = record.len() <= 255;
+            if !valid {
+                warn!("Skipping mDNS TXT record longer than 255 bytes: {}", record);
+            }
+            valid
+        });
+
+        AdvertisedService { name, txt }
+    }
+}
+
+/// Services advertised over mDNS by a running server.
+struct Advertiser {
+    responder: libmdns::Responder,
+    port: u16,
+    txt: Vec<String>,
+    per_thing: bool,
+    /// Advertised services, keyed by the path segment of their thing, or the empty string for the
+    /// server.
+    services: HashMap<String, (AdvertisedService, libmdns::Service)>,
+}
+
+/// The advertiser of a server, shared with its things handle and shutdown handles. `None` when
+/// the server is not advertised.
+type SharedAdvertiser = Arc<Mutex<Option<Advertiser>>>;
+
+impl Advertiser {
+    /// Advertise a service under a key, replacing any previous service under that key.
+    fn advertise(&mut self, key: &str, name: String, path: &str) {
+        // The previous service must be dropped first, as libmdns tracks services by name.
+        self.withdraw(key);
+
+        let taken: Vec<&str> = self
+            .services
+            .values()
+            .map(|(service, _)| service.name.as_str())
+            .collect();
+        let advertised = AdvertisedService::new(name, key, path, &self.txt, &taken);
+        let txt: Vec<&str> = advertised.txt.iter().map(|r| r.as_str()).collect();
+
+        let service = self.responder.register(
+            SERVICE_TYPE.to_owned(),
+            advertised.name.clone(),
+            self.port,
+            &txt,
+        );
+        self.services.insert(key.to_owned(), (advertised, service));
+    }
+
+    /// Withdraw the service advertised under a key.
+    fn withdraw(&mut self, key: &str) {
+        // Dropping the service sends a goodbye announcement.
+        self.services.remove(key);
+    }
+}
+
+/// Handle to add, replace and remove things while a multiple-things server is running.
+///
+/// Things are served under a path segment derived from their ID, e.g. `urn:dev:ops:my-lamp-1234`
+/// is served at `/my-lamp-1234`, so their URLs don't depend on the order they were added in.
+#[derive(Clone)]
+pub struct ThingsHandle {
+    slots: Arc<RwLock<ThingSlots>>,
+    base_path: String,
+    advertiser: SharedAdvertiser,
+    websockets: SharedWebSockets,
+}
+
+impl ThingsHandle {
+    /// Create a new handle serving the given things under `base_path`.
+    fn new(
+        things: Vec<Arc<RwLock<Box<dyn Thing>>>>,
+        base_path: String,
+        advertiser: SharedAdvertiser,
+        websockets: SharedWebSockets,
+    ) -> Self {
+        let handle = Self {
+            slots: Arc::new(RwLock::new(ThingSlots {
+                things: Vec::new(),
+                next_index: 0,
+                index_aliases: false,
+            })),
+            base_path,
+            advertiser,
+            websockets,
+        };
+
+        for thing in things {
+            handle.add_thing(thing);
+        }
+
+        handle
+    }
+
+    /// Add a thing under a path segment and return the segment.
+    fn insert(
+        &self,
+        slots: &mut ThingSlots,
+        path: String,
+        thing: Arc<RwLock<Box<dyn Thing>>>,
+    ) -> String {
+        write_lock(&thing).set_href_prefix(format!("{}/{}", self.base_path, path));
+        self.advertise(&path, &thing);
+
+        let index = slots.next_index;
+        slots.next_index += 1;
+        slots.things.push(ThingSlot {
+            path: path.clone(),
+            index,
+            thing,
+        });
+
+        path
+    }
+
+    /// Add a new thing, served under a path segment derived from its ID.
+    ///
+    /// The last segment of the ID is used if it is free, falling back to the whole ID and then
+    /// to a numbered suffix. Returns the path segment the thing is served under.
+    pub fn add_thing(&self, thing: Arc<RwLock<Box<dyn Thing>>>) -> String {
+        let id = read_lock(&thing).get_id();
+        let mut slots = write_lock(&self.slots);
+        let path = slots.free_path(&id);
+        self.insert(&mut slots, path, thing)
+    }
+
+    /// Add a new thing, served under the given path segment.
+    ///
+    /// The segment may only contain letters, digits, `-`, `.`, `_` and `~`, and can't be `.` or
+    /// `..`, or a number when index aliases are enabled. Returns the path segment, or an error if
+    /// it is invalid or already in use.
+    pub fn add_thing_at(
+        &self,
+        path: &str,
+        thing: Arc<RwLock<Box<dyn Thing>>>,
+    ) -> Result<String, &'static str> {
+        if !is_valid_path_segment(path) {
+            return Err("Invalid path segment");
+        }
+
+        let mut slots = write_lock(&self.slots);
+        if slots.index_aliases && is_index(path) {
+            return Err("Path segment reserved for index aliases");
+        }
+        if slots.is_taken(path) {
+            return Err("Path segment already in use");
+        }
+
+        Ok(self.insert(&mut slots, path.to_owned(), thing))
+    }
+
+    /// Replace the thing served under `path`, keeping its URL.
+    ///
+    /// Returns the previous thing, or `None` if no thing is served under `path`, in which case
+    /// the new thing is not added. WebSockets connected to the previous thing are closed.
+    pub fn replace_thing(
+        &self,
+        path: &str,
+        thing: Arc<RwLock<Box<dyn Thing>>>,
+    ) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        let previous = {
+            let mut slots = write_lock(&self.slots);
+            let position = slots.find(path)?;
+            let slot = &mut slots.things[position];
+
+            write_lock(&thing).set_href_prefix(format!("{}/{}", self.base_path, slot.path));
+            self.advertise(&slot.path, &thing);
+            std::mem::replace(&mut slot.thing, thing)
+        };
+
+        self.close_websockets();
+        Some(previous)
+    }
+
+    /// Remove the thing served under `path`.
+    ///
+    /// Returns the removed thing, if any. WebSockets connected to it are closed.
+    pub fn remove_thing(&self, path: &str) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        let slot = {
+            let mut slots = write_lock(&self.slots);
+            let position = slots.find(path)?;
+            slots.things.remove(position)
+        };
+
+        if let Some(advertiser) = lock(&self.advertiser).as_mut() {
+            advertiser.withdraw(&slot.path);
+        }
+        self.close_websockets();
+        Some(slot.thing)
+    }
+
+    /// Ask the WebSockets to close if their thing is no longer served.
+    fn close_websockets(&self) {
+        for websocket in lock(&self.websockets).values() {
+            websocket.do_send(CloseIfRemoved);
+        }
+    }
+
+    /// Get the thing served under `path`.
+    pub fn get_thing(&self, path: &str) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        let slots = read_lock(&self.slots);
+        slots
+            .find(path)
+            .map(|position| slots.things[position].thing.clone())
+    }
+
+    /// Get all things with the path segments they are served under.
+    pub fn get_things(&self) -> Vec<(String, Arc<RwLock<Box<dyn Thing>>>)> {
+        read_lock(&self.slots)
+            .things
+            .iter()
+            .map(|slot| (slot.path.clone(), slot.thing.clone()))
+            .collect()
+    }
+
+    /// Enable or disable serving things under their position-based index, i.e. `/0`.
+    ///
+    /// Things already served under a numeric path segment are moved to a path derived from
+    /// their ID, so the segment can't be mistaken for an index.
+    fn set_index_aliases(&self, enabled: bool) {
+        let mut slots = write_lock(&self.slots);
+        slots.index_aliases = enabled;
+        if !enabled {
+            return;
+        }
+
+        for position in 0..slots.things.len() {
+            if !is_index(&slots.things[position].path) {
+                continue;
+            }
+
+            let thing = slots.things[position].thing.clone();
+            let path = slots.free_path(&read_lock(&thing).get_id());
+            write_lock(&thing).set_href_prefix(format!("{}/{}", self.base_path, path));
+            if let Some(advertiser) = lock(&self.advertiser).as_mut() {
+                advertiser.withdraw(&slots.things[position].path);
+            }
+            self.advertise(&path, &thing);
+            slots.things[position].path = path;
+        }
+    }
+
+    /// Advertise a thing served under a path segment, if things are advertised individually.
+    fn advertise(&self, path: &str, thing: &Arc<RwLock<Box<dyn Thing>>>) {
+        if let Some(advertiser) = lock(&self.advertiser).as_mut() {
+            if advertiser.per_thing {
+                let thing = read_lock(thing);
+                advertiser.advertise(path, thing.get_title(), &thing.get_href());
+            }
+        }
+    }
+}
+
+/// Things served by a running server.
+#[derive(Clone)]
+enum ServedThings {
+    /// Multiple things, and the name the server is advertised under.
+    Multiple(ThingsHandle, String),
+    /// A single thing.
     Single(Arc<RwLock<Box<dyn Thing>>>),
 }
 
+impl ServedThings {
+    /// Find a thing by its path segment and the path of nested children below it, if any.
+    fn resolve(
+        &self,
+        thing_id: Option<&str>,
+        child_path: Option<&str>,
+    ) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        let mut thing = match self {
+            ServedThings::Multiple(ref handle, _) => handle.get_thing(thing_id?)?,
+            ServedThings::Single(ref thing) => thing.clone(),
+        };
+
+        if let Some(child_path) = child_path {
+            for path in child_path.split("/things/") {
+                let child = read_lock(&thing).get_child(path)?;
+                thing = child;
+            }
+        }
+
+        Some(thing)
+    }
+
+    /// Get the top-level things, without their children.
+    fn all(&self) -> Vec<Arc<RwLock<Box<dyn Thing>>>> {
+        match self {
+            ServedThings::Multiple(ref handle, _) => handle
+                .get_things()
+                .into_iter()
+                .map(|(_, thing)| thing)
+                .collect(),
+            ServedThings::Single(ref thing) => vec![thing.clone()],
+        }
+    }
+}
+
+/// WebSockets connected to the server, by ID.
+type SharedWebSockets = Arc<Mutex<HashMap<String, Addr<ThingWebSocket>>>>;
+
+/// State shared between a server's workers and its shutdown handles.
+struct ShutdownState {
+    things: ServedThings,
+    shutting_down: AtomicBool,
+    timeout: Mutex<Duration>,
+    websockets: SharedWebSockets,
+    server: Mutex<Option<ServerHandle>>,
+    advertiser: SharedAdvertiser,
+}
+
+/// Handle to shut a server down gracefully, from any thread.
+///
+/// Get one with `WebThingServer::shutdown_handle()` before starting the server.
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<ShutdownState>);
+
+impl ShutdownHandle {
+    fn new(
+        things: ServedThings,
+        advertiser: SharedAdvertiser,
+        websockets: SharedWebSockets,
+    ) -> Self {
+        ShutdownHandle(Arc::new(ShutdownState {
+            things,
+            shutting_down: AtomicBool::new(false),
+            timeout: Mutex::new(DEFAULT_SHUTDOWN_TIMEOUT),
+            websockets,
+            server: Mutex::new(None),
+            advertiser,
+        }))
+    }
+
+    /// Determine whether the server is shutting down.
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Shut the server down.
+    ///
+    /// Withdraws the mDNS advertisement, cancels the actions that have not completed, notifying
+    /// subscribers with a final `actionStatus` of `cancelled`, sends close frames to all
+    /// WebSockets once their queued messages are sent, and finally stops the HTTP server,
+    /// letting in-flight requests finish. New WebSocket connections are refused with 503 from
+    /// the start. Waits for WebSockets and requests up to the shutdown timeout.
+    pub async fn shutdown(&self) {
+        self.0.shutting_down.store(true, Ordering::SeqCst);
+        let timeout = *lock(&self.0.timeout);
+        let deadline = actix_web::rt::time::Instant::now() + timeout;
+
+        // Dropping the services sends goodbye announcements.
+        drop(lock(&self.0.advertiser).take());
+
+        for thing in self.0.things.all() {
+            cancel_actions(&thing);
+        }
+
+        let websockets: Vec<Addr<ThingWebSocket>> =
+            lock(&self.0.websockets).values().cloned().collect();
+        for websocket in websockets {
+            websocket.do_send(CloseWebSocket);
+        }
+
+        while !lock(&self.0.websockets).is_empty() && actix_web::rt::time::Instant::now() < deadline
+        {
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let server = lock(&self.0.server).take();
+        if let Some(server) = server {
+            server.stop(true).await;
+        }
+    }
+
+    /// Set the HTTP server to stop, for apps built with `make_config()` rather than `start()`.
+    pub fn set_server(&self, server: ServerHandle) {
+        *lock(&self.0.server) = Some(server);
+    }
+}
+
+/// Cancel the actions of a thing and its children which have not completed.
+fn cancel_actions(thing: &Arc<RwLock<Box<dyn Thing>>>) {
+    let mut thing = write_lock(thing);
+    for action in thing.get_actions() {
+        let description = {
+            let mut action = write_lock(&action);
+            let status = action.get_status();
+            if status == "completed" || status == CANCELLED_STATUS {
+                continue;
+            }
+
+            if catch_panic(|| action.cancel()).is_err() {
+                warn!("Action {} panicked while cancelling", action.get_id());
+            }
+            action.set_status(CANCELLED_STATUS.to_owned());
+            action.as_action_description()
+        };
+        thing.action_notify(description);
+    }
+
+    for (_, child) in thing.get_children() {
+        cancel_actions(&child);
+    }
+}
+
 /// Shared app state, used by server threads.
 struct AppState {
-    things: Arc<ThingsType>,
-    hosts: Arc<Vec<String>>,
-    disable_host_validation: Arc<bool>,
+    things: ServedThings,
+    hosts: Arc<AllowedHosts>,
+    limiters: Arc<RateLimiters>,
+    max_frame_size: usize,
     action_generator: Arc<dyn ActionGenerator>,
+    forwarder_timeout: Duration,
+    description_format: DescriptionFormat,
+    shutdown: ShutdownHandle,
 }
 
 impl AppState {
     /// Get the thing this request is for.
-    fn get_thing(&self, thing_id: Option<&str>) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
-        match self.things.as_ref() {
-            ThingsType::Multiple(ref inner_things, _) => {
-                let id = thing_id?.parse::<usize>().ok()?;
-                if id >= inner_things.len() {
-                    None
-                } else {
-                    Some(inner_things[id].clone())
-                }
-            }
-            ThingsType::Single(ref thing) => Some(thing.clone()),
-        }
+    fn get_thing(&self, req: &HttpRequest) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.things.resolve(
+            req.match_info().get("thing_id"),
+            req.match_info().get("child_path"),
+        )
     }
 
-    fn get_things(&self) -> Arc<ThingsType> {
+    fn get_things(&self) -> ServedThings {
         self.things.clone()
     }
 
@@ -70,23 +1484,38 @@ impl AppState {
         self.action_generator.clone()
     }
 
-    fn validate_host(&self, host: Option<&HeaderValue>) -> Result<(), ()> {
-        if *self.disable_host_validation {
+    /// Count a request to a property or action of a thing against the rate limits, returning how
+    /// long to wait if it is over a limit.
+    fn check_rate(
+        &self,
+        req: &HttpRequest,
+        thing: &Arc<RwLock<Box<dyn Thing>>>,
+        interaction: &str,
+    ) -> Result<(), Duration> {
+        self.limiters
+            .check_client(self.hosts.client(req))
+            .and_then(|()| self.limiters.check_interaction(thing, interaction))
+    }
+
+    fn validate_host(&self, req: &HttpRequest) -> Result<(), ()> {
+        if self.hosts.disabled {
             return Ok(());
         }
 
-        if let Some(Ok(host)) = host.map(|h| h.to_str()) {
-            if self.hosts.contains(&host.to_lowercase()) {
-                return Ok(());
-            }
+        match self.hosts.origin(req) {
+            (_, Some(host)) if self.hosts.allows(&host) => Ok(()),
+            _ => Err(()),
         }
-
-        Err(())
     }
 }
 
-/// Host validation middleware
-struct HostValidator;
+/// Host validation middleware.
+///
+/// Rejects requests whose Host header is not one of the names and addresses the server answers
+/// to, or one of the allowed host patterns. Behind a trusted proxy, the forwarded host is checked
+/// instead. It is applied by `start()`; wrap an `App` configured with `make_config()` in it to test
+/// host validation.
+pub struct HostValidator;
 
 impl<S, B> Transform<S, ServiceRequest> for HostValidator
 where
@@ -105,7 +1534,8 @@ where
     }
 }
 
-struct HostValidatorMiddleware<S: Service<ServiceRequest>> {
+/// Service created by `HostValidator`.
+pub struct HostValidatorMiddleware<S: Service<ServiceRequest>> {
     service: S,
 }
 
@@ -125,8 +1555,7 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         if let Some(state) = req.app_data::<web::Data<AppState>>() {
-            let host = req.headers().get("Host");
-            match state.validate_host(host) {
+            match state.validate_host(req.request()) {
                 Ok(_) => {
                     let res = self.service.call(req);
                     Box::pin(async move { res.await.map(ServiceResponse::map_into_left_body) })
@@ -146,9 +1575,15 @@ where
 /// Shared state used by individual websockets.
 struct ThingWebSocket {
     id: String,
-    thing_id: usize,
-    things: Arc<ThingsType>,
+    thing_id: Option<String>,
+    child_path: Option<String>,
+    thing: Arc<RwLock<Box<dyn Thing>>>,
+    things: ServedThings,
+    client: Option<IpAddr>,
+    limiters: Arc<RateLimiters>,
     action_generator: Arc<dyn ActionGenerator>,
+    forwarder_timeout: Duration,
+    shutdown: ShutdownHandle,
 }
 
 impl ThingWebSocket {
@@ -159,40 +1594,121 @@ impl ThingWebSocket {
 
     /// Get the thing associated with this websocket.
     fn get_thing(&self) -> Arc<RwLock<Box<dyn Thing>>> {
-        match self.things.as_ref() {
-            ThingsType::Multiple(ref things, _) => things[self.thing_id].clone(),
-            ThingsType::Single(ref thing) => thing.clone(),
-        }
+        self.thing.clone()
+    }
+
+    /// Determine whether the thing is still served, i.e. not removed or replaced.
+    fn is_thing_served(&self) -> bool {
+        self.things
+            .resolve(self.thing_id.as_deref(), self.child_path.as_deref())
+            .map(|thing| Arc::ptr_eq(&thing, &self.thing))
+            .unwrap_or(false)
     }
 
     /// Drain all message queues associated with this websocket.
     fn drain_queue(&self, ctx: &mut ws::WebsocketContext<Self>) {
         ctx.run_later(Duration::from_millis(200), |act, ctx| {
-            let thing = act.get_thing();
-            let mut thing = thing.write().unwrap();
+            if !act.is_thing_served() {
+                write_lock(&act.thing).remove_subscriber(act.get_id());
+                ctx.close(Some(CloseReason {
+                    code: CloseCode::Away,
+                    description: Some("Thing removed".to_owned()),
+                }));
+                return ctx.stop();
+            }
 
-            let drains = thing.drain_queue(act.get_id());
-            for iter in drains {
-                for message in iter {
-                    ctx.text(message);
+            let thing = act.get_thing();
+            let mut thing = match thing.try_write() {
+                Ok(thing) => thing,
+                // Don't block the actor on a busy thing, pick the messages up on the next run.
+                Err(TryLockError::WouldBlock) => return act.drain_queue(ctx),
+                Err(TryLockError::Poisoned(poisoned)) => {
+                    thing.clear_poison();
+                    poisoned.into_inner()
                 }
-            }
+            };
 
+            act.send_queued(&mut **thing, ctx);
             act.drain_queue(ctx);
         });
     }
+
+    /// Send the messages queued for this websocket.
+    fn send_queued(&self, thing: &mut dyn Thing, ctx: &mut ws::WebsocketContext<Self>) {
+        let drains = thing.drain_queue(self.get_id());
+        for iter in drains {
+            for message in iter {
+                ctx.text(message);
+            }
+        }
+    }
+
+    /// Send the remaining queued messages, then close the websocket.
+    fn close(&self, ctx: &mut ws::WebsocketContext<Self>, code: CloseCode, description: &str) {
+        {
+            let mut thing = write_lock(&self.thing);
+            self.send_queued(&mut **thing, ctx);
+            thing.remove_subscriber(self.get_id());
+        }
+
+        ctx.close(Some(CloseReason {
+            code,
+            description: Some(description.to_owned()),
+        }));
+        ctx.stop();
+    }
 }
 
 impl Actor for ThingWebSocket {
     type Context = ws::WebsocketContext<Self>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        lock(&self.shutdown.0.websockets).remove(&self.id);
+    }
 }
 
-fn bad_request(message: impl AsRef<str>, request: Option<serde_json::Value>) -> serde_json::Value {
+/// Message asking a websocket to close because the server is shutting down.
+struct CloseWebSocket;
+
+impl Message for CloseWebSocket {
+    type Result = ();
+}
+
+impl Handler<CloseWebSocket> for ThingWebSocket {
+    type Result = ();
+
+    fn handle(&mut self, _msg: CloseWebSocket, ctx: &mut Self::Context) {
+        self.close(ctx, CloseCode::Away, "Server shutting down");
+    }
+}
+
+/// Message asking a websocket to close if its thing was removed or replaced.
+struct CloseIfRemoved;
+
+impl Message for CloseIfRemoved {
+    type Result = ();
+}
+
+impl Handler<CloseIfRemoved> for ThingWebSocket {
+    type Result = ();
+
+    fn handle(&mut self, _msg: CloseIfRemoved, ctx: &mut Self::Context) {
+        if !self.is_thing_served() {
+            self.close(ctx, CloseCode::Away, "Thing removed");
+        }
+    }
+}
+
+fn error_message(
+    status: &str,
+    message: impl AsRef<str>,
+    request: Option<serde_json::Value>,
+) -> serde_json::Value {
     if let Some(request) = request {
         json!({
               "messageType": "error",
               "data": {
-                  "status": "400 Bad Request",
+                  "status": status,
                   "message": message.as_ref(),
                   "request": request,
               }
@@ -201,19 +1717,238 @@ fn bad_request(message: impl AsRef<str>, request: Option<serde_json::Value>) ->
         json!({
             "messageType": "error",
             "data": {
-                "status": "400 Bad Request",
+                "status": status,
                 "message": message.as_ref(),
             }
         })
     }
 }
 
+fn bad_request(message: impl AsRef<str>, request: Option<serde_json::Value>) -> serde_json::Value {
+    error_message("400 Bad Request", message, request)
+}
+
 fn bad_request_string(message: impl AsRef<str>, request: Option<serde_json::Value>) -> String {
     serde_json::to_string(&bad_request(message, request)).unwrap()
 }
 
+fn internal_error(request: Option<serde_json::Value>) -> serde_json::Value {
+    error_message("500 Internal Server Error", "Internal error", request)
+}
+
+fn rate_limited(wait: Duration, request: Option<serde_json::Value>) -> serde_json::Value {
+    error_message(
+        "429 Too Many Requests",
+        format!("Rate limit exceeded, retry in {} ms", wait.as_millis()),
+        request,
+    )
+}
+
+/// Answer a request over a rate limit, telling the client when to retry.
+fn too_many_requests(wait: Duration) -> HttpResponse {
+    let retry_after = wait.as_millis().div_ceil(1000).max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(rate_limited(wait, None))
+}
+
+/// Run user-supplied code, turning a panic into an error.
+///
+/// Callers hold any lock guard outside of `f`, so the panic is caught before the guard is
+/// dropped and the lock is not poisoned.
+fn catch_panic<R>(f: impl FnOnce() -> R) -> Result<R, ()> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| ())
+}
+
+/// Reasons a property could not be set.
+enum SetPropertyError {
+    /// The thing has no such property.
+    NotFound,
+    /// The value was rejected before reaching the device.
+    Invalid(&'static str),
+    /// The asynchronous value forwarder failed.
+    Forwarder(&'static str),
+    /// The asynchronous value forwarder did not resolve in time.
+    Timeout,
+    /// User code panicked while setting the value.
+    Panicked,
+}
+
+impl SetPropertyError {
+    /// Build the error message sent to the client.
+    fn to_message(&self, request: serde_json::Value) -> serde_json::Value {
+        match self {
+            // WebSocket clients have always been told 400 for unknown properties, unlike HTTP.
+            SetPropertyError::NotFound => bad_request("Property not found", Some(request)),
+            SetPropertyError::Invalid(err) => bad_request(err, Some(request)),
+            SetPropertyError::Forwarder(err) => {
+                error_message("502 Bad Gateway", err, Some(request))
+            }
+            SetPropertyError::Timeout => error_message(
+                "504 Gateway Timeout",
+                "Timed out setting property value",
+                Some(request),
+            ),
+            SetPropertyError::Panicked => internal_error(Some(request)),
+        }
+    }
+
+    /// Build the HTTP response sent to the client.
+    fn to_response(&self, request: serde_json::Value) -> HttpResponse {
+        let message = self.to_message(request);
+        match self {
+            SetPropertyError::NotFound => HttpResponse::NotFound().finish(),
+            SetPropertyError::Invalid(_) => HttpResponse::BadRequest().json(message),
+            SetPropertyError::Forwarder(_) => HttpResponse::BadGateway().json(message),
+            SetPropertyError::Timeout => HttpResponse::GatewayTimeout().json(message),
+            SetPropertyError::Panicked => HttpResponse::InternalServerError().json(message),
+        }
+    }
+}
+
+/// How a property hands new values to its device.
+enum Forwarding {
+    Async(Arc<dyn AsyncValueForwarder>),
+    Blocking(SharedValueForwarder),
+}
+
+/// Set a property on a thing.
+///
+/// Properties with a value forwarder are validated under the thing's lock, but the forwarder
+/// itself is called with the lock released, so other requests are not blocked. Synchronous
+/// forwarders run on a blocking thread, holding only their own lock. Both are given up on after
+/// the timeout, though a synchronous forwarder's thread keeps running until it returns.
+async fn set_thing_property(
+    thing: &Arc<RwLock<Box<dyn Thing>>>,
+    property_name: &str,
+    value: serde_json::Value,
+    timeout: Duration,
+) -> Result<(), SetPropertyError> {
+    let forwarder = {
+        let mut thing = write_lock(thing);
+        let property = thing
+            .find_property(property_name)
+            .ok_or(SetPropertyError::NotFound)?;
+
+        let forwarder = match property.get_async_value_forwarder() {
+            Some(forwarder) => Forwarding::Async(forwarder),
+            None => match property.get_value_forwarder() {
+                Some(forwarder) => Forwarding::Blocking(forwarder),
+                None => {
+                    return catch_panic(|| thing.set_property(property_name.to_owned(), value))
+                        .map_err(|_| SetPropertyError::Panicked)?
+                        .map_err(SetPropertyError::Invalid);
+                }
+            },
+        };
+
+        property
+            .validate_value(&value)
+            .map_err(SetPropertyError::Invalid)?;
+        forwarder
+    };
+
+    let value = match forwarder {
+        Forwarding::Async(forwarder) => {
+            let update = catch_panic(|| forwarder.set_value(value))
+                .map_err(|_| SetPropertyError::Panicked)?;
+            actix_web::rt::time::timeout(timeout, AssertUnwindSafe(update).catch_unwind())
+                .await
+                .map_err(|_| SetPropertyError::Timeout)?
+                .map_err(|_| SetPropertyError::Panicked)?
+                .map_err(SetPropertyError::Forwarder)?
+        }
+        Forwarding::Blocking(forwarder) => {
+            let update = web::block(move || {
+                let mut forwarder = lock(&forwarder);
+                catch_panic(|| forwarder.set_value(value))
+            });
+            actix_web::rt::time::timeout(timeout, update)
+                .await
+                .map_err(|_| SetPropertyError::Timeout)?
+                .map_err(|_| SetPropertyError::Panicked)?
+                .map_err(|_| SetPropertyError::Panicked)?
+                .map_err(SetPropertyError::Invalid)?
+        }
+    };
+
+    let mut thing = write_lock(thing);
+    thing
+        .find_property(property_name)
+        .ok_or(SetPropertyError::NotFound)?
+        .set_cached_value(value.clone())
+        .map_err(SetPropertyError::Invalid)?;
+    thing.property_notify(property_name.to_owned(), value);
+
+    Ok(())
+}
+
+/// Reasons an action could not be requested.
+enum RequestActionError {
+    /// The action generator does not know the action.
+    Unknown,
+    /// The thing rejected the action, e.g. because of invalid input.
+    Rejected(String),
+    /// User code panicked while creating or starting the action.
+    Panicked,
+}
+
+/// Generate an action, add it to the thing and start it.
+///
+/// The thing is only locked to add the action and notify subscribers that it started. The action
+/// is performed holding just its own lock, so requests to the rest of the thing are not blocked.
+///
+/// Returns the description of the action as it was before starting it.
+fn request_action(
+    action_generator: &dyn ActionGenerator,
+    thing: &Arc<RwLock<Box<dyn Thing>>>,
+    action_name: &str,
+    input: Option<&serde_json::Value>,
+) -> Result<serde_json::Map<String, serde_json::Value>, RequestActionError> {
+    let action = catch_panic(|| {
+        action_generator.generate(Arc::downgrade(thing), action_name.to_owned(), input)
+    })
+    .map_err(|_| RequestActionError::Panicked)?
+    .ok_or(RequestActionError::Unknown)?;
+
+    let action: Arc<RwLock<Box<dyn Action>>> = Arc::new(RwLock::new(action));
+
+    {
+        let mut thing = write_lock(thing);
+        catch_panic(|| {
+            thing
+                .add_action(action.clone(), input)
+                .map_err(|err| err.to_owned())
+        })
+        .map_err(|_| RequestActionError::Panicked)?
+        .map_err(RequestActionError::Rejected)?;
+    }
+
+    let description = read_lock(&action).as_action_description();
+
+    catch_panic(|| {
+        let mut thing = write_lock(thing);
+        let mut action = write_lock(&action);
+        action.start();
+        thing.action_notify(action.as_action_description());
+    })
+    .map_err(|_| RequestActionError::Panicked)?;
+    catch_panic(|| write_lock(&action).perform_action())
+        .map_err(|_| RequestActionError::Panicked)?;
+
+    Ok(description)
+}
+
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ThingWebSocket {
     fn started(&mut self, ctx: &mut Self::Context) {
+        lock(&self.shutdown.0.websockets).insert(self.get_id(), ctx.address());
+
+        // Close sockets that connected while the server began shutting down, as they were not
+        // registered in time to be closed.
+        if self.shutdown.is_shutting_down() {
+            return self.close(ctx, CloseCode::Away, "Server shutting down");
+        }
+
         self.drain_queue(ctx);
     }
 
@@ -222,6 +1957,10 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ThingWebSocket {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
             Ok(ws::Message::Pong(_)) => (),
             Ok(ws::Message::Text(text)) => {
+                if let Err(wait) = self.limiters.check_client(self.client) {
+                    return ctx.text(rate_limited(wait, None).to_string());
+                }
+
                 let message: serde_json::Value = if let Ok(message) = serde_json::from_str(&text) {
                     message
                 } else {
@@ -250,63 +1989,87 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ThingWebSocket {
 
                 match msg_type {
                     "setProperty" => {
-                        for (property_name, property_value) in data.iter() {
-                            let result = thing
-                                .write()
-                                .unwrap()
-                                .set_property(property_name.to_string(), property_value.clone());
-
-                            if let Err(err) = result {
-                                return ctx.text(bad_request_string(err, Some(json!(message))));
+                        for property_name in data.keys() {
+                            let interaction = format!("properties/{}", property_name);
+                            if let Err(wait) = self.limiters.check_interaction(&thing, &interaction)
+                            {
+                                return ctx
+                                    .text(rate_limited(wait, Some(json!(message))).to_string());
                             }
                         }
+
+                        let data = data.clone();
+                        let request = json!(message);
+                        let timeout = self.forwarder_timeout;
+                        let update = async move {
+                            for (property_name, property_value) in data {
+                                let result = set_thing_property(
+                                    &thing,
+                                    &property_name,
+                                    property_value,
+                                    timeout,
+                                )
+                                .await;
+
+                                if let Err(err) = result {
+                                    return Some(err.to_message(request));
+                                }
+                            }
+
+                            None
+                        };
+
+                        // Later messages wait for the update, so they are applied in order.
+                        ctx.wait(actix::fut::wrap_future(update).map(
+                            |error, _act, ctx: &mut ws::WebsocketContext<Self>| {
+                                if let Some(error) = error {
+                                    ctx.text(error.to_string());
+                                }
+                            },
+                        ));
                     }
                     "requestAction" => {
                         for (action_name, action_params) in data.iter() {
+                            let interaction = format!("actions/{}", action_name);
+                            if let Err(wait) = self.limiters.check_interaction(&thing, &interaction)
+                            {
+                                return ctx
+                                    .text(rate_limited(wait, Some(json!(message))).to_string());
+                            }
+
                             let input = action_params.get("input");
-                            let action = self.action_generator.generate(
-                                Arc::downgrade(&self.get_thing()),
-                                action_name.to_string(),
+                            let result = request_action(
+                                self.action_generator.as_ref(),
+                                &thing,
+                                action_name,
                                 input,
                             );
 
-                            if action.is_none() {
-                                return ctx.text(bad_request_string(
-                                    "Invalid action request",
+                            let error = match result {
+                                Ok(_) => continue,
+                                Err(RequestActionError::Unknown) => {
+                                    bad_request("Invalid action request", Some(json!(message)))
+                                }
+                                Err(RequestActionError::Rejected(err)) => bad_request(
+                                    format!("Failed to start action: {}", err),
                                     Some(json!(message)),
-                                ));
-                            }
-
-                            let action = action.unwrap();
-                            let id = action.get_id();
-                            let action = Arc::new(RwLock::new(action));
-
-                            {
-                                let mut thing = thing.write().unwrap();
-                                if let Err(err) = thing.add_action(action.clone(), input) {
-                                    return ctx.text(bad_request_string(
-                                        format!("Failed to start action: {}", err),
-                                        Some(json!(message)),
-                                    ));
+                                ),
+                                Err(RequestActionError::Panicked) => {
+                                    internal_error(Some(json!(message)))
                                 }
-                            }
+                            };
 
-                            thing
-                                .write()
-                                .unwrap()
-                                .start_action(action_name.to_string(), id);
+                            return ctx.text(error.to_string());
                         }
                     }
                     "addEventSubscription" => {
                         for event_name in data.keys() {
-                            thing
-                                .write()
-                                .unwrap()
+                            write_lock(&thing)
                                 .add_event_subscriber(event_name.to_string(), self.get_id());
                         }
                     }
                     unknown => {
-                        return ctx.text(bad_request_string(
+                        ctx.text(bad_request_string(
                             format!("Unknown messageType: {}", unknown),
                             Some(json!(message)),
                         ));
@@ -315,59 +2078,84 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ThingWebSocket {
             }
             Ok(ws::Message::Close(_)) => {
                 let thing = self.get_thing();
-                thing.write().unwrap().remove_subscriber(self.get_id());
+                write_lock(&thing).remove_subscriber(self.get_id());
+            }
+            Err(ws::ProtocolError::Overflow) => {
+                ctx.text(
+                    error_message("413 Payload Too Large", "Message too large", None).to_string(),
+                );
+                self.close(ctx, CloseCode::Size, "Message too large");
             }
             _ => (),
         }
     }
 }
 
-/// Handle a GET request to / when the server manages multiple things.
-async fn handle_get_things(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let mut response: Vec<serde_json::Map<String, serde_json::Value>> = Vec::new();
-
-    // The host header is already checked by HostValidator, so the unwrapping is safe here.
-    let host = req.headers().get("Host").unwrap().to_str().unwrap();
-    let connection = req.connection_info();
-    let scheme = connection.scheme();
+/// Build the Thing Description served for a thing, in the server's description format.
+fn describe_thing(
+    thing: &dyn Thing,
+    req: &HttpRequest,
+    state: &AppState,
+) -> serde_json::Map<String, serde_json::Value> {
+    // Use the host and scheme the client asked for, which differ behind a proxy.
+    let (scheme, host) = state.hosts.origin(req);
+    let host = host.unwrap_or_else(|| req.app_config().host().to_owned());
     let ws_href = format!(
-        "{}://{}",
+        "{}://{}{}",
         if scheme == "https" { "wss" } else { "ws" },
-        host
+        host,
+        thing.get_href()
     );
 
-    if let ThingsType::Multiple(things, _) = state.things.as_ref() {
-        for thing in things.iter() {
-            let thing = thing.read().unwrap();
-
-            let mut link = serde_json::Map::new();
-            link.insert("rel".to_owned(), json!("alternate"));
-            link.insert(
-                "href".to_owned(),
-                json!(format!("{}{}", ws_href, thing.get_href())),
-            );
+    let mut link = serde_json::Map::new();
+    link.insert("rel".to_owned(), json!("alternate"));
+    link.insert("href".to_owned(), json!(ws_href));
 
-            let mut description = thing.as_thing_description().clone();
-            {
-                let links = description
-                    .get_mut("links")
-                    .unwrap()
-                    .as_array_mut()
-                    .unwrap();
-                links.push(json!(link));
+    let mut description = thing.as_thing_description();
+    {
+        let links = description
+            .get_mut("links")
+            .unwrap()
+            .as_array_mut()
+            .unwrap();
+        links.push(json!(link));
+
+        if req.match_info().get("child_path").is_some() {
+            let href_prefix = thing.get_href_prefix();
+            if let Some((parent_href, _)) = href_prefix.rsplit_once("/things/") {
+                links.push(json!({
+                    "rel": "collection",
+                    "href": if parent_href.is_empty() { "/" } else { parent_href },
+                }));
             }
+        }
+    }
 
-            description.insert("href".to_owned(), json!(thing.get_href()));
-            description.insert(
-                "base".to_owned(),
-                json!(format!("{}://{}{}", scheme, host, thing.get_href())),
-            );
-            description.insert(
-                "securityDefinitions".to_owned(),
-                json!({"nosec_sc": {"scheme": "nosec"}}),
-            );
-            description.insert("security".to_owned(), json!("nosec_sc"));
+    description.insert(
+        "base".to_owned(),
+        json!(format!("{}://{}{}", scheme, host, thing.get_href())),
+    );
+    description.insert(
+        "securityDefinitions".to_owned(),
+        json!({"nosec_sc": {"scheme": "nosec"}}),
+    );
+    description.insert("security".to_owned(), json!("nosec_sc"));
+
+    match state.description_format {
+        DescriptionFormat::WebThings => description,
+        DescriptionFormat::Td11 => to_td11(description, &ws_href),
+    }
+}
+
+/// Handle a GET request to / when the server manages multiple things.
+async fn handle_get_things(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let mut response: Vec<serde_json::Map<String, serde_json::Value>> = Vec::new();
 
+    if let ServedThings::Multiple(ref handle, _) = state.things {
+        for (_, thing) in handle.get_things() {
+            let thing = read_lock(&thing);
+            let mut description = describe_thing(&**thing, &req, &state);
+            description.insert("href".to_owned(), json!(thing.get_href()));
             response.push(description);
         }
     }
@@ -376,48 +2164,11 @@ async fn handle_get_things(req: HttpRequest, state: web::Data<AppState>) -> Http
 
 /// Handle a GET request to /.
 async fn handle_get_thing(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let thing = state.get_thing(req.match_info().get("thing_id"));
-    match thing {
+    match state.get_thing(&req) {
         None => HttpResponse::NotFound().finish(),
         Some(thing) => {
-            let thing = thing.read().unwrap();
-
-            // The host header is already checked by HostValidator, so the unwrapping is safe here.
-            let host = req.headers().get("Host").unwrap().to_str().unwrap();
-            let connection = req.connection_info();
-            let scheme = connection.scheme();
-            let ws_href = format!(
-                "{}://{}{}",
-                if scheme == "https" { "wss" } else { "ws" },
-                host,
-                thing.get_href()
-            );
-
-            let mut link = serde_json::Map::new();
-            link.insert("rel".to_owned(), json!("alternate"));
-            link.insert("href".to_owned(), json!(ws_href));
-
-            let mut description = thing.as_thing_description();
-            {
-                let links = description
-                    .get_mut("links")
-                    .unwrap()
-                    .as_array_mut()
-                    .unwrap();
-                links.push(json!(link));
-            }
-
-            description.insert(
-                "base".to_owned(),
-                json!(format!("{}://{}{}", scheme, host, thing.get_href())),
-            );
-            description.insert(
-                "securityDefinitions".to_owned(),
-                json!({"nosec_sc": {"scheme": "nosec"}}),
-            );
-            description.insert("security".to_owned(), json!("nosec_sc"));
-
-            HttpResponse::Ok().json(description)
+            let thing = read_lock(&thing);
+            HttpResponse::Ok().json(describe_thing(&**thing, &req, &state))
         }
     }
 }
@@ -428,31 +2179,41 @@ async fn handle_ws_thing(
     state: web::Data<AppState>,
     stream: web::Payload,
 ) -> Result<HttpResponse, Error> {
-    let thing_id = req.match_info().get("thing_id");
+    if state.shutdown.is_shutting_down() {
+        return Ok(HttpResponse::ServiceUnavailable().finish());
+    }
 
-    match state.get_thing(thing_id) {
+    match state.get_thing(&req) {
         None => Ok(HttpResponse::NotFound().finish()),
         Some(thing) => {
-            let thing_id = match thing_id {
-                None => 0,
-                Some(id) => id.parse::<usize>().unwrap(),
-            };
             let ws = ThingWebSocket {
                 id: Uuid::new_v4().to_string(),
-                thing_id,
+                thing_id: req.match_info().get("thing_id").map(|id| id.to_owned()),
+                child_path: req
+                    .match_info()
+                    .get("child_path")
+                    .map(|path| path.to_owned()),
+                thing: thing.clone(),
                 things: state.get_things(),
+                client: state.hosts.client(&req),
+                limiters: state.limiters.clone(),
                 action_generator: state.get_action_generator(),
+                forwarder_timeout: state.forwarder_timeout,
+                shutdown: state.shutdown.clone(),
             };
-            thing.write().unwrap().add_subscriber(ws.get_id());
-            ws::start(ws, &req, stream)
+            write_lock(&thing).add_subscriber(ws.get_id());
+            ws::WsResponseBuilder::new(ws, &req, stream)
+                .protocols(&[WEBSOCKET_SUBPROTOCOL])
+                .frame_size(state.max_frame_size)
+                .start()
         }
     }
 }
 
 /// Handle a GET request to /properties.
 async fn handle_get_properties(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    if let Some(thing) = state.get_thing(req.match_info().get("thing_id")) {
-        let thing = thing.read().unwrap();
+    if let Some(thing) = state.get_thing(&req) {
+        let thing = read_lock(&thing);
         HttpResponse::Ok().json(json!(thing.get_properties()))
     } else {
         HttpResponse::NotFound().finish()
@@ -461,7 +2222,7 @@ async fn handle_get_properties(req: HttpRequest, state: web::Data<AppState>) ->
 
 /// Handle a GET request to /properties/<property>.
 async fn handle_get_property(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let thing = match state.get_thing(req.match_info().get("thing_id")) {
+    let thing = match state.get_thing(&req) {
         Some(thing) => thing,
         None => return HttpResponse::NotFound().finish(),
     };
@@ -471,10 +2232,9 @@ async fn handle_get_property(req: HttpRequest, state: web::Data<AppState>) -> Ht
         None => return HttpResponse::NotFound().finish(),
     };
 
-    let thing = thing.read().unwrap();
-    if thing.has_property(&property_name.to_string()) {
-        HttpResponse::Ok()
-            .json(json!({property_name: thing.get_property(&property_name.to_string()).unwrap()}))
+    let thing = read_lock(&thing);
+    if thing.has_property(property_name) {
+        HttpResponse::Ok().json(json!({property_name: thing.get_property(property_name).unwrap()}))
     } else {
         HttpResponse::NotFound().finish()
     }
@@ -486,7 +2246,7 @@ async fn handle_put_property(
     state: web::Data<AppState>,
     body: web::Json<serde_json::Value>,
 ) -> HttpResponse {
-    let thing = match state.get_thing(req.match_info().get("thing_id")) {
+    let thing = match state.get_thing(&req) {
         Some(thing) => thing,
         None => return HttpResponse::NotFound().finish(),
     };
@@ -496,6 +2256,10 @@ async fn handle_put_property(
         None => return HttpResponse::NotFound().finish(),
     };
 
+    if let Err(wait) = state.check_rate(&req, &thing, &format!("properties/{}", property_name)) {
+        return too_many_requests(wait);
+    }
+
     let args = match body.as_object() {
         Some(args) => args,
         None => {
@@ -515,26 +2279,20 @@ async fn handle_put_property(
         ));
     };
 
-    let mut thing = thing.write().unwrap();
-    if thing.has_property(&property_name.to_string()) {
-        let set_property_result = thing.set_property(property_name.to_string(), arg.clone());
-
-        match set_property_result {
-            Ok(()) => HttpResponse::Ok().json(
-                json!({property_name: thing.get_property(&property_name.to_string()).unwrap()}),
-            ),
-            Err(err) => HttpResponse::BadRequest().json(bad_request(err, Some(json!(args)))),
-        }
-    } else {
-        HttpResponse::NotFound().finish()
+    match set_thing_property(&thing, property_name, arg.clone(), state.forwarder_timeout).await {
+        Ok(()) => match read_lock(&thing).get_property(property_name) {
+            Some(value) => HttpResponse::Ok().json(json!({ property_name: value })),
+            None => HttpResponse::NotFound().finish(),
+        },
+        Err(err) => err.to_response(json!(args)),
     }
 }
 
 /// Handle a GET request to /actions.
 async fn handle_get_actions(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    match state.get_thing(req.match_info().get("thing_id")) {
+    match state.get_thing(&req) {
         None => HttpResponse::NotFound().finish(),
-        Some(thing) => HttpResponse::Ok().json(thing.read().unwrap().get_action_descriptions(None)),
+        Some(thing) => HttpResponse::Ok().json(read_lock(&thing).get_action_descriptions(None)),
     }
 }
 
@@ -544,7 +2302,7 @@ async fn handle_post_actions(
     state: web::Data<AppState>,
     body: web::Json<serde_json::Value>,
 ) -> HttpResponse {
-    let thing = match state.get_thing(req.match_info().get("thing_id")) {
+    let thing = match state.get_thing(&req) {
         Some(thing) => thing,
         None => return HttpResponse::NotFound().finish(),
     };
@@ -560,56 +2318,30 @@ async fn handle_post_actions(
     }
 
     let action_name = keys[0];
+    if let Err(wait) = state.check_rate(&req, &thing, &format!("actions/{}", action_name)) {
+        return too_many_requests(wait);
+    }
+
     let action_params = message.get(action_name).unwrap();
     let input = action_params.get("input");
 
-    let action = state.get_action_generator().generate(
-        Arc::downgrade(&thing.clone()),
-        action_name.to_string(),
+    match request_action(
+        state.get_action_generator().as_ref(),
+        &thing,
+        action_name,
         input,
-    );
-
-    if let Some(action) = action {
-        let id = action.get_id();
-        let action = Arc::new(RwLock::new(action));
-
-        {
-            let mut thing = thing.write().unwrap();
-            let result = thing.add_action(action.clone(), input);
-
-            if result.is_err() {
-                return HttpResponse::BadRequest().finish();
-            }
-        }
-
-        let mut response: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
-        response.insert(
-            action_name.to_string(),
-            action
-                .read()
-                .unwrap()
-                .as_action_description()
-                .get(action_name)
-                .unwrap()
-                .clone(),
-        );
-
-        thing
-            .write()
-            .unwrap()
-            .start_action(action_name.to_string(), id);
-
-        HttpResponse::Created().json(response)
-    } else {
-        HttpResponse::BadRequest().finish()
+    ) {
+        Ok(description) => HttpResponse::Created().json(description),
+        Err(RequestActionError::Panicked) => HttpResponse::InternalServerError().finish(),
+        Err(_) => HttpResponse::BadRequest().finish(),
     }
 }
 
 /// Handle a GET request to /actions/<action_name>.
 async fn handle_get_action(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    if let Some(thing) = state.get_thing(req.match_info().get("thing_id")) {
+    if let Some(thing) = state.get_thing(&req) {
         if let Some(action_name) = req.match_info().get("action_name") {
-            let thing = thing.read().unwrap();
+            let thing = read_lock(&thing);
             return HttpResponse::Ok()
                 .json(thing.get_action_descriptions(Some(action_name.to_string())));
         }
@@ -624,7 +2356,7 @@ async fn handle_post_action(
     state: web::Data<AppState>,
     body: web::Json<serde_json::Value>,
 ) -> HttpResponse {
-    let thing = if let Some(thing) = state.get_thing(req.match_info().get("thing_id")) {
+    let thing = if let Some(thing) = state.get_thing(&req) {
         thing
     } else {
         return HttpResponse::NotFound().finish();
@@ -652,51 +2384,25 @@ async fn handle_post_action(
         return HttpResponse::BadRequest().finish();
     };
 
-    let action = state.get_action_generator().generate(
-        Arc::downgrade(&thing.clone()),
-        action_name.to_string(),
-        input,
-    );
-
-    if let Some(action) = action {
-        let id = action.get_id();
-        let action = Arc::new(RwLock::new(action));
-
-        {
-            let mut thing = thing.write().unwrap();
-            let result = thing.add_action(action.clone(), input);
-
-            if result.is_err() {
-                return HttpResponse::BadRequest().finish();
-            }
-        }
-
-        let mut response: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
-        response.insert(
-            action_name.to_string(),
-            action
-                .read()
-                .unwrap()
-                .as_action_description()
-                .get(action_name)
-                .unwrap()
-                .clone(),
-        );
-
-        thing
-            .write()
-            .unwrap()
-            .start_action(action_name.to_string(), id);
+    if let Err(wait) = state.check_rate(&req, &thing, &format!("actions/{}", action_name)) {
+        return too_many_requests(wait);
+    }
 
-        HttpResponse::Created().json(response)
-    } else {
-        HttpResponse::BadRequest().finish()
+    match request_action(
+        state.get_action_generator().as_ref(),
+        &thing,
+        action_name,
+        input,
+    ) {
+        Ok(description) => HttpResponse::Created().json(description),
+        Err(RequestActionError::Panicked) => HttpResponse::InternalServerError().finish(),
+        Err(_) => HttpResponse::BadRequest().finish(),
     }
 }
 
 /// Handle a GET request to /actions/<action_name>/<action_id>.
 async fn handle_get_action_id(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let thing = if let Some(thing) = state.get_thing(req.match_info().get("thing_id")) {
+    let thing = if let Some(thing) = state.get_thing(&req) {
         thing
     } else {
         return HttpResponse::NotFound().finish();
@@ -710,9 +2416,9 @@ async fn handle_get_action_id(req: HttpRequest, state: web::Data<AppState>) -> H
         return HttpResponse::NotFound().finish();
     };
 
-    let thing = thing.read().unwrap();
+    let thing = read_lock(&thing);
     if let Some(action) = thing.get_action(action_name.to_string(), action_id.to_string()) {
-        HttpResponse::Ok().json(action.read().unwrap().as_action_description())
+        HttpResponse::Ok().json(read_lock(&action).as_action_description())
     } else {
         HttpResponse::NotFound().finish()
     }
@@ -724,7 +2430,7 @@ async fn handle_put_action_id(
     state: web::Data<AppState>,
     _body: web::Json<serde_json::Value>,
 ) -> HttpResponse {
-    match state.get_thing(req.match_info().get("thing_id")) {
+    match state.get_thing(&req) {
         Some(_) => {
             // TODO: this is not yet defined in the spec
             HttpResponse::Ok().finish()
@@ -735,7 +2441,7 @@ async fn handle_put_action_id(
 
 /// Handle a DELETE request to /actions/<action_name>/<action_id>.
 async fn handle_delete_action_id(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let thing = match state.get_thing(req.match_info().get("thing_id")) {
+    let thing = match state.get_thing(&req) {
         Some(thing) => thing,
         None => return HttpResponse::NotFound().finish(),
     };
@@ -743,12 +2449,11 @@ async fn handle_delete_action_id(req: HttpRequest, state: web::Data<AppState>) -
     let action_name = req.match_info().get("action_name");
     let action_id = req.match_info().get("action_id");
     if let Some((action_name, action_id)) = action_name.zip(action_id) {
-        if thing
-            .write()
-            .unwrap()
-            .remove_action(action_name.to_string(), action_id.to_string())
-        {
-            return HttpResponse::NoContent().finish();
+        let mut thing = write_lock(&thing);
+        match catch_panic(|| thing.remove_action(action_name.to_string(), action_id.to_string())) {
+            Ok(true) => return HttpResponse::NoContent().finish(),
+            Ok(false) => (),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
         }
     }
 
@@ -757,15 +2462,15 @@ async fn handle_delete_action_id(req: HttpRequest, state: web::Data<AppState>) -
 
 /// Handle a GET request to /events.
 async fn handle_get_events(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    match state.get_thing(req.match_info().get("thing_id")) {
+    match state.get_thing(&req) {
         None => HttpResponse::NotFound().finish(),
-        Some(thing) => HttpResponse::Ok().json(thing.read().unwrap().get_event_descriptions(None)),
+        Some(thing) => HttpResponse::Ok().json(read_lock(&thing).get_event_descriptions(None)),
     }
 }
 
 /// Handle a GET request to /events/<event_name>.
 async fn handle_get_event(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let thing = match state.get_thing(req.match_info().get("thing_id")) {
+    let thing = match state.get_thing(&req) {
         Some(thing) => thing,
         None => return HttpResponse::NotFound().finish(),
     };
@@ -775,21 +2480,77 @@ async fn handle_get_event(req: HttpRequest, state: web::Data<AppState>) -> HttpR
         None => return HttpResponse::NotFound().finish(),
     };
 
-    let thing = thing.read().unwrap();
+    let thing = read_lock(&thing);
     HttpResponse::Ok().json(thing.get_event_descriptions(Some(event_name.to_string())))
 }
 
+/// Build the routes for a single thing, mounted at `path`.
+fn thing_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(
+            web::resource("")
+                .route(
+                    web::route()
+                        .guard(guard::Get())
+                        .guard(guard::Header("upgrade", "websocket"))
+                        .to(handle_ws_thing),
+                )
+                .route(web::get().to(handle_get_thing)),
+        )
+        .service(web::resource("/properties").route(web::get().to(handle_get_properties)))
+        .service(
+            web::resource("/properties/{property_name}")
+                .route(web::get().to(handle_get_property))
+                .route(web::put().to(handle_put_property)),
+        )
+        .service(
+            web::resource("/actions")
+                .route(web::get().to(handle_get_actions))
+                .route(web::post().to(handle_post_actions)),
+        )
+        .service(
+            web::resource("/actions/{action_name}")
+                .route(web::get().to(handle_get_action))
+                .route(web::post().to(handle_post_action)),
+        )
+        .service(
+            web::resource("/actions/{action_name}/{action_id}")
+                .route(web::get().to(handle_get_action_id))
+                .route(web::delete().to(handle_delete_action_id))
+                .route(web::put().to(handle_put_action_id)),
+        )
+        .service(web::resource("/events").route(web::get().to(handle_get_events)))
+        .service(web::resource("/events/{event_name}").route(web::get().to(handle_get_event)))
+}
+
 /// Server to represent a Web Thing over HTTP.
 pub struct WebThingServer {
-    things: ThingsType,
+    things: ServedThings,
     base_path: String,
-    disable_host_validation: bool,
+    hosts: Arc<AllowedHosts>,
+    host_refresh_interval: Option<Duration>,
+    cors: CorsPolicy,
+    limits: RequestLimits,
+    limiters: Arc<RateLimiters>,
     port: Option<u16>,
+    bound_addresses: Vec<SocketAddr>,
     hostname: Option<String>,
-    dns_service: Option<libmdns::Service>,
-    #[allow(dead_code)]
-    ssl_options: Option<(String, String)>,
+    listen_addresses: Vec<ListenAddress>,
+    mdns_mode: MdnsMode,
+    mdns_name: Option<String>,
+    mdns_txt: Vec<String>,
+    advertiser: SharedAdvertiser,
+    shutdown: ShutdownHandle,
+    #[cfg(any(feature = "ssl", feature = "rustls"))]
+    tls: TlsHandle,
+    #[cfg(any(feature = "ssl", feature = "rustls"))]
+    tls_reload_interval: Option<Duration>,
+    #[cfg(all(feature = "self-signed", any(feature = "ssl", feature = "rustls")))]
+    self_signed_dir: Option<PathBuf>,
     generator_arc: Arc<dyn ActionGenerator>,
+    forwarder_timeout: Duration,
+    description_format: DescriptionFormat,
+    description_validation: DescriptionValidation,
 }
 
 impl WebThingServer {
@@ -800,7 +2561,8 @@ impl WebThingServer {
     /// * `things` - list of Things managed by this server
     /// * `port` - port to listen on (defaults to 80)
     /// * `hostname` - optional host name, i.e. mything.com
-    /// * `ssl_options` - tuple of SSL options to pass to the actix web server
+    /// * `ssl_options` - paths of the PEM private key and certificate chain files to serve TLS
+    ///   with, when built with the `ssl` or `rustls` feature; OpenSSL is used if both are enabled
     /// * `action_generator` - action generator struct
     /// * `base_path` - base URL to use, rather than '/'
     /// * `disable_host_validation` - whether or not to disable host validation -- note that this
@@ -815,83 +2577,327 @@ impl WebThingServer {
         base_path: Option<String>,
         disable_host_validation: Option<bool>,
     ) -> Self {
+        let base_path = base_path
+            .map(|p| p.trim_end_matches('/').to_string())
+            .unwrap_or_default();
+
+        let advertiser: SharedAdvertiser = Arc::new(Mutex::new(None));
+        let websockets: SharedWebSockets = Arc::new(Mutex::new(HashMap::new()));
+        let things = match things {
+            ThingsType::Multiple(things, name) => ServedThings::Multiple(
+                ThingsHandle::new(
+                    things,
+                    base_path.clone(),
+                    advertiser.clone(),
+                    websockets.clone(),
+                ),
+                name,
+            ),
+            ThingsType::Single(thing) => {
+                write_lock(&thing).set_href_prefix(base_path.clone());
+                ServedThings::Single(thing)
+            }
+        };
+        let shutdown = ShutdownHandle::new(things.clone(), advertiser.clone(), websockets);
+
+        #[cfg(not(any(feature = "ssl", feature = "rustls")))]
+        let _ = ssl_options;
+
         Self {
             things,
-            base_path: base_path
-                .map(|p| p.trim_end_matches('/').to_string())
-                .unwrap_or_else(|| "".to_owned()),
-            disable_host_validation: disable_host_validation.unwrap_or(false),
+            base_path,
+            hosts: Arc::new(AllowedHosts::new(disable_host_validation.unwrap_or(false))),
+            host_refresh_interval: None,
+            cors: CorsPolicy::default(),
+            limits: RequestLimits::default(),
+            limiters: Arc::new(RateLimiters::new(&RequestLimits::default())),
             port,
+            bound_addresses: Vec::new(),
             hostname,
-            dns_service: None,
-            ssl_options,
+            listen_addresses: vec![ListenAddress::Ip(Ipv4Addr::UNSPECIFIED.into())],
+            mdns_mode: MdnsMode::default(),
+            mdns_name: None,
+            mdns_txt: Vec::new(),
+            advertiser,
+            shutdown,
+            #[cfg(any(feature = "ssl", feature = "rustls"))]
+            tls: TlsHandle::new(ssl_options.map(|(key, cert)| TlsSource::Files {
+                key: key.into(),
+                cert: cert.into(),
+            })),
+            #[cfg(any(feature = "ssl", feature = "rustls"))]
+            tls_reload_interval: None,
+            #[cfg(all(feature = "self-signed", any(feature = "ssl", feature = "rustls")))]
+            self_signed_dir: None,
             generator_arc: Arc::from(action_generator),
+            forwarder_timeout: DEFAULT_FORWARDER_TIMEOUT,
+            description_format: DescriptionFormat::default(),
+            description_validation: DescriptionValidation::default(),
         }
     }
 
-    fn set_href_prefix(&mut self) {
-        match &mut self.things {
-            ThingsType::Multiple(ref mut things, _) => {
-                for (idx, thing) in things.iter_mut().enumerate() {
-                    let mut thing = thing.write().unwrap();
-                    thing.set_href_prefix(format!("{}/{}", self.base_path, idx));
-                }
-            }
-            ThingsType::Single(ref mut thing) => {
-                thing
-                    .write()
-                    .unwrap()
-                    .set_href_prefix(self.base_path.clone());
-            }
-        }
+    /// Set how long to wait for value forwarders.
+    ///
+    /// Requests that exceed this timeout are answered with 504 Gateway Timeout. A synchronous
+    /// forwarder's blocking thread can't be interrupted and keeps running until it returns.
+    /// Defaults to 10 seconds.
+    pub fn with_forwarder_timeout(mut self, timeout: Duration) -> Self {
+        self.forwarder_timeout = timeout;
+        self
     }
 
-    /// Return the base actix configuration for the server
-    /// useful for testing.
-    pub fn make_config(&mut self) -> impl Fn(&mut web::ServiceConfig) + Clone + 'static {
-        let port = self.port.unwrap_or(80);
+    /// Set the format of the served Thing Descriptions.
+    ///
+    /// Defaults to `DescriptionFormat::WebThings`. With `DescriptionFormat::Td11`, things are
+    /// described as W3C WoT Thing Description 1.1 documents with `forms`.
+    pub fn with_description_format(mut self, format: DescriptionFormat) -> Self {
+        self.description_format = format;
+        self
+    }
 
-        let mut hosts = vec!["localhost".to_owned(), format!("localhost:{}", port)];
+    /// Validate Thing Descriptions when the server starts.
+    ///
+    /// Defaults to `DescriptionValidation::Disabled`.
+    pub fn with_description_validation(mut self, validation: DescriptionValidation) -> Self {
+        self.description_validation = validation;
+        self
+    }
 
-        if let Ok(system_hostname) = hostname::get() {
-            let name = system_hostname.into_string().unwrap().to_lowercase();
-            hosts.push(format!("{}.local", name));
-            hosts.push(format!("{}.local:{}", name, port));
+    /// Also serve things under their position-based index, i.e. `/0`, as in previous versions.
+    ///
+    /// Only applies when the server manages multiple things. Hrefs in Thing Descriptions always
+    /// use the ID-based path, and numeric paths are reserved for the aliases: things whose ID
+    /// ends in a number are served under their whole ID instead, e.g. `/urn-dev-ops-42`.
+    pub fn with_index_aliases(self, enabled: bool) -> Self {
+        if let ServedThings::Multiple(ref handle, _) = self.things {
+            handle.set_index_aliases(enabled);
         }
+        self
+    }
 
-        for address in get_addresses() {
-            hosts.push(address.clone());
-            hosts.push(format!("{}:{}", address, port));
-        }
+    /// Set the addresses to listen on.
+    ///
+    /// Defaults to `0.0.0.0`, all IPv4 addresses. Host validation only accepts the addresses
+    /// that are bound, and only those are announced over mDNS. The server is not announced when
+    /// it only listens on loopback addresses or Unix domain sockets.
+    pub fn with_listen_addresses(mut self, addresses: Vec<ListenAddress>) -> Self {
+        self.listen_addresses = addresses;
+        self
+    }
 
-        if let Some(ref hostname) = self.hostname {
-            let name = hostname.to_lowercase();
-            hosts.push(name.clone());
-            hosts.push(format!("{}:{}", name, port));
+    /// Set how the server is advertised over mDNS.
+    ///
+    /// Defaults to `MdnsMode::Server`.
+    pub fn with_mdns(mut self, mode: MdnsMode) -> Self {
+        self.mdns_mode = mode;
+        self
+    }
+
+    /// Set the instance name the server is advertised under.
+    ///
+    /// Defaults to the title of the thing, or the name given for multiple things.
+    pub fn with_mdns_name(mut self, name: String) -> Self {
+        self.mdns_name = Some(name);
+        self
+    }
+
+    /// Add a TXT record to the advertised services, i.e. `("model", "lamp-2")`.
+    ///
+    /// Records replace the built-in `path` and `tls` records with the same key.
+    pub fn with_mdns_txt_record(mut self, key: &str, value: &str) -> Self {
+        self.mdns_txt.push(format!("{}={}", key, value));
+        self
+    }
+
+    /// Serve TLS with a PEM-encoded certificate chain and private key held in memory.
+    ///
+    /// Replaces the files given in `ssl_options`.
+    #[cfg(any(feature = "ssl", feature = "rustls"))]
+    pub fn with_tls_pem(self, cert_chain: Vec<u8>, private_key: Vec<u8>) -> Self {
+        self.tls.set_source(TlsSource::Pem {
+            key: private_key,
+            cert: cert_chain,
+        });
+        self
+    }
+
+    /// Require clients to present a certificate issued by one of the PEM-encoded CA certificates.
+    ///
+    /// The identity of the client is available to handlers as a `ClientIdentity`. The CA
+    /// certificates are read once when the server starts.
+    #[cfg(any(feature = "ssl", feature = "rustls"))]
+    pub fn with_tls_client_ca(self, ca_certificates: Vec<u8>) -> Self {
+        self.tls.set_client_ca(ca_certificates);
+        self
+    }
+
+    /// Check the certificate and key files for changes at an interval, reloading them when they
+    /// change.
+    ///
+    /// Disabled by default; the files can also be reloaded with `TlsHandle::reload()`.
+    #[cfg(any(feature = "ssl", feature = "rustls"))]
+    pub fn with_tls_reload_interval(mut self, interval: Duration) -> Self {
+        self.tls_reload_interval = Some(interval);
+        self
+    }
+
+    /// Serve TLS with a self-signed certificate for development, kept in `dir`.
+    ///
+    /// The certificate covers the names accepted by host validation. It is generated when the
+    /// server starts, and reused while it covers the same names. Replaces `ssl_options` and
+    /// `with_tls_pem()`.
+    #[cfg(all(feature = "self-signed", any(feature = "ssl", feature = "rustls")))]
+    pub fn with_self_signed_certificate(mut self, dir: PathBuf) -> Self {
+        self.self_signed_dir = Some(dir);
+        self
+    }
+
+    /// Get a handle to reload or replace the TLS certificate while the server is running.
+    #[cfg(any(feature = "ssl", feature = "rustls"))]
+    pub fn tls_handle(&self) -> TlsHandle {
+        self.tls.clone()
+    }
+
+    /// Also answer to hosts matching the given patterns, i.e. the public name of a reverse proxy.
+    pub fn with_allowed_hosts(self, patterns: Vec<HostPattern>) -> Self {
+        *write_lock(&self.hosts.patterns) = patterns;
+        self
+    }
+
+    /// Trust proxies in the given networks to forward the host and scheme a request was made to.
+    ///
+    /// For requests from these proxies, the `Forwarded` header, or the `X-Forwarded-Host` and
+    /// `X-Forwarded-Proto` headers, are used for host validation and in the `base` and WebSocket
+    /// hrefs of Thing Descriptions. Requests over Unix domain sockets are never trusted.
+    pub fn with_trusted_proxies(self, proxies: Vec<IpNet>) -> Self {
+        *write_lock(&self.hosts.trusted_proxies) = proxies;
+        self
+    }
+
+    /// Check the addresses of the listened interfaces at an interval, so host validation follows
+    /// addresses that change or come up later, i.e. from DHCP or a VPN.
+    ///
+    /// Disabled by default.
+    pub fn with_host_refresh_interval(mut self, interval: Duration) -> Self {
+        self.host_refresh_interval = Some(interval);
+        self
+    }
+
+    /// Set the policy for cross-origin requests.
+    ///
+    /// Defaults to `CorsPolicy::default()`, which allows any origin without credentials.
+    pub fn with_cors(mut self, policy: CorsPolicy) -> Self {
+        self.cors = policy;
+        self
+    }
+
+    /// Set the limits on the rate and size of requests.
+    ///
+    /// Defaults to `RequestLimits::default()`, which doesn't limit the rate of requests.
+    pub fn with_request_limits(mut self, limits: RequestLimits) -> Self {
+        self.limiters = Arc::new(RateLimiters::new(&limits));
+        self.limits = limits;
+        self
+    }
+
+    /// Set how long to wait for WebSockets and requests to finish when shutting down.
+    ///
+    /// The HTTP server counts in whole seconds, so it waits for requests up to the timeout rounded
+    /// up to the next second. Defaults to 30 seconds.
+    pub fn with_shutdown_timeout(self, timeout: Duration) -> Self {
+        *lock(&self.shutdown.0.timeout) = timeout;
+        self
+    }
+
+    /// Get a handle to shut the server down gracefully while it is running.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Shut the server down gracefully, see `ShutdownHandle::shutdown()`.
+    pub async fn shutdown(&self) {
+        self.shutdown.shutdown().await
+    }
+
+    /// Get the socket addresses bound by `start()`, i.e. to find out which ephemeral port was
+    /// picked when asked for port 0.
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.bound_addresses
+    }
+
+    /// Get the services currently advertised over mDNS, ordered by instance name.
+    ///
+    /// Empty until the server is started, and when it is not advertised.
+    pub fn advertised_services(&self) -> Vec<AdvertisedService> {
+        let mut services: Vec<AdvertisedService> = lock(&self.advertiser)
+            .as_ref()
+            .map(|advertiser| {
+                advertiser
+                    .services
+                    .values()
+                    .map(|(service, _)| service.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        services
+    }
+
+    /// Get a handle to add, replace and remove things while the server is running.
+    ///
+    /// Returns `None` if the server manages a single thing.
+    pub fn things_handle(&self) -> Option<ThingsHandle> {
+        match self.things {
+            ServedThings::Multiple(ref handle, _) => Some(handle.clone()),
+            ServedThings::Single(_) => None,
         }
+    }
+
+    /// Return the base actix configuration for the server
+    /// useful for testing.
+    pub fn make_config(&mut self) -> impl Fn(&mut web::ServiceConfig) + Clone + 'static {
+        let port = self.port.unwrap_or(80);
 
-        self.set_href_prefix();
+        self.hosts.set_names(
+            host_names(&self.listen_addresses, self.hostname.as_deref()),
+            port,
+        );
 
-        let single = match &self.things {
-            ThingsType::Multiple(_, _) => false,
-            ThingsType::Single(_) => true,
+        let single = match self.things {
+            ServedThings::Multiple(_, _) => false,
+            ServedThings::Single(_) => true,
         };
-        let things_arc = Arc::new(self.things.clone());
-        let hosts_arc = Arc::new(hosts.clone());
+        let things = self.things.clone();
+        let hosts = self.hosts.clone();
+        let limiters = self.limiters.clone();
+        let max_body_size = self.limits.max_body_size;
+        let max_frame_size = self.limits.max_frame_size;
         let generator_arc_clone = self.generator_arc.clone();
-        let disable_host_validation_arc = Arc::new(self.disable_host_validation);
+        let forwarder_timeout = self.forwarder_timeout;
+        let description_format = self.description_format;
+        let shutdown = self.shutdown.clone();
 
         let bp = self.base_path.clone();
 
         move |app: &mut web::ServiceConfig| {
             app.app_data(Data::new(AppState {
-                things: things_arc.clone(),
-                hosts: hosts_arc.clone(),
-                disable_host_validation: disable_host_validation_arc.clone(),
+                things: things.clone(),
+                hosts: hosts.clone(),
+                limiters: limiters.clone(),
+                max_frame_size,
                 action_generator: generator_arc_clone.clone(),
-            }));
+                forwarder_timeout,
+                description_format,
+                shutdown: shutdown.clone(),
+            }))
+            .app_data(web::JsonConfig::default().limit(max_body_size));
 
             if single {
+                app.service(thing_scope(&format!(
+                    "{}/things/{}",
+                    bp, CHILD_PATH_PATTERN
+                )));
+
                 let root = if bp.is_empty() {
                     "/".to_owned()
                 } else {
@@ -909,119 +2915,204 @@ impl WebThingServer {
                         .route(web::get().to(handle_get_thing)),
                 )
                 .service(
-                    web::resource(&format!("{}/properties", bp))
+                    web::resource(format!("{}/properties", bp))
                         .route(web::get().to(handle_get_properties)),
                 )
                 .service(
-                    web::resource(&format!("{}/properties/{{property_name}}", bp))
+                    web::resource(format!("{}/properties/{{property_name}}", bp))
                         .route(web::get().to(handle_get_property))
                         .route(web::put().to(handle_put_property)),
                 )
                 .service(
-                    web::resource(&format!("{}/actions", bp))
+                    web::resource(format!("{}/actions", bp))
                         .route(web::get().to(handle_get_actions))
                         .route(web::post().to(handle_post_actions)),
                 )
                 .service(
-                    web::resource(&format!("{}/actions/{{action_name}}", bp))
+                    web::resource(format!("{}/actions/{{action_name}}", bp))
                         .route(web::get().to(handle_get_action))
                         .route(web::post().to(handle_post_action)),
                 )
                 .service(
-                    web::resource(&format!("{}/actions/{{action_name}}/{{action_id}}", bp))
+                    web::resource(format!("{}/actions/{{action_name}}/{{action_id}}", bp))
                         .route(web::get().to(handle_get_action_id))
                         .route(web::delete().to(handle_delete_action_id))
                         .route(web::put().to(handle_put_action_id)),
                 )
                 .service(
-                    web::resource(&format!("{}/events", bp))
-                        .route(web::get().to(handle_get_events)),
+                    web::resource(format!("{}/events", bp)).route(web::get().to(handle_get_events)),
                 )
                 .service(
-                    web::resource(&format!("{}/events/{{event_name}}", bp))
+                    web::resource(format!("{}/events/{{event_name}}", bp))
                         .route(web::get().to(handle_get_event)),
                 );
             } else {
-                app.service(web::resource("/").route(web::get().to(handle_get_things)))
-                    .service(
-                        web::scope(&format!("{}/{{thing_id}}", bp))
-                            .service(
-                                web::resource("")
-                                    .route(
-                                        web::route()
-                                            .guard(guard::Get())
-                                            .guard(guard::Header("upgrade", "websocket"))
-                                            .to(handle_ws_thing),
-                                    )
-                                    .route(web::get().to(handle_get_thing)),
-                            )
-                            .service(
-                                web::resource("/properties")
-                                    .route(web::get().to(handle_get_properties)),
-                            )
-                            .service(
-                                web::resource("/properties/{property_name}")
-                                    .route(web::get().to(handle_get_property))
-                                    .route(web::put().to(handle_put_property)),
-                            )
-                            .service(
-                                web::resource("/actions")
-                                    .route(web::get().to(handle_get_actions))
-                                    .route(web::post().to(handle_post_actions)),
-                            )
-                            .service(
-                                web::resource("/actions/{action_name}")
-                                    .route(web::get().to(handle_get_action))
-                                    .route(web::post().to(handle_post_action)),
-                            )
-                            .service(
-                                web::resource("/actions/{action_name}/{action_id}")
-                                    .route(web::get().to(handle_get_action_id))
-                                    .route(web::delete().to(handle_delete_action_id))
-                                    .route(web::put().to(handle_put_action_id)),
-                            )
-                            .service(
-                                web::resource("/events").route(web::get().to(handle_get_events)),
-                            )
-                            .service(
-                                web::resource("/events/{event_name}")
-                                    .route(web::get().to(handle_get_event)),
-                            ),
+                // The list is served at the advertised base path, and at the root as before.
+                if !bp.is_empty() {
+                    app.service(
+                        web::resource(format!("{}/", bp)).route(web::get().to(handle_get_things)),
                     );
+                }
+
+                app.service(web::resource("/").route(web::get().to(handle_get_things)))
+                    .service(thing_scope(&format!(
+                        "{}/{{thing_id}}/things/{}",
+                        bp, CHILD_PATH_PATTERN
+                    )))
+                    .service(thing_scope(&format!("{}/{{thing_id}}", bp)));
+            }
+        }
+    }
+
+    /// Validate the Thing Descriptions of all things and their children.
+    ///
+    /// Returns the problems found, each prefixed with the ID of the thing.
+    pub fn validate_descriptions(&self) -> Vec<String> {
+        fn validate(thing: &Arc<RwLock<Box<dyn Thing>>>, problems: &mut Vec<String>) {
+            let thing = read_lock(thing);
+            if let Err(errors) = thing.validate_description() {
+                for error in errors {
+                    problems.push(format!("{}: {}", thing.get_id(), error));
+                }
+            }
+
+            for (_, child) in thing.get_children() {
+                validate(&child, problems);
+            }
+        }
+
+        let things = self.things.all();
+
+        let mut problems = Vec::new();
+        for thing in things {
+            validate(&thing, &mut problems);
+        }
+        problems
+    }
+
+    /// Advertise the server, or its things, over mDNS.
+    fn advertise(&self, responder: libmdns::Responder, name: String, port: u16) {
+        let mut txt = Vec::new();
+
+        #[cfg(any(feature = "ssl", feature = "rustls"))]
+        if self.tls.is_enabled() {
+            txt.push("tls=1".to_owned());
+        }
+
+        txt.extend(self.mdns_txt.iter().cloned());
+
+        let mut advertiser = Advertiser {
+            responder,
+            port,
+            txt,
+            per_thing: false,
+            services: HashMap::new(),
+        };
+
+        match self.things {
+            ServedThings::Multiple(ref handle, _) if self.mdns_mode == MdnsMode::PerThing => {
+                advertiser.per_thing = true;
+                for (path, thing) in handle.get_things() {
+                    let thing = read_lock(&thing);
+                    advertiser.advertise(&path, thing.get_title(), &thing.get_href());
+                }
+            }
+            ServedThings::Multiple(_, _) => {
+                let name = self.mdns_name.clone().unwrap_or(name);
+                advertiser.advertise("", name, &format!("{}/", self.base_path));
+            }
+            ServedThings::Single(_) => {
+                let name = self.mdns_name.clone().unwrap_or(name);
+                let path = if self.base_path.is_empty() {
+                    "/"
+                } else {
+                    &self.base_path
+                };
+                advertiser.advertise("", name, path);
             }
         }
+
+        *lock(&self.advertiser) = Some(advertiser);
     }
 
     /// Start listening for incoming connections.
+    ///
+    /// Fails if the socket cannot be bound, if the CORS policy or a rate limit is invalid, if the
+    /// TLS certificate or private key cannot be read or do not match, or if a Thing Description is
+    /// invalid and `DescriptionValidation::Strict` is set.
     pub fn start(
         &mut self,
         configure: Option<&'static (dyn Fn(&mut web::ServiceConfig) + Send + Sync + 'static)>,
-    ) -> Server {
+    ) -> io::Result<Server> {
         let port = self.port.unwrap_or(80);
 
+        if self.description_validation != DescriptionValidation::Disabled {
+            let problems = self.validate_descriptions();
+            if self.description_validation == DescriptionValidation::Strict && !problems.is_empty()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid Thing Description: {}", problems.join("; ")),
+                ));
+            }
+
+            for problem in problems {
+                warn!("Invalid Thing Description: {}", problem);
+            }
+        }
+
         let name = match &self.things {
-            ThingsType::Single(thing) => thing.read().unwrap().get_title(),
-            ThingsType::Multiple(_, name) => name.to_owned(),
+            ServedThings::Single(thing) => read_lock(thing).get_title(),
+            ServedThings::Multiple(_, name) => name.to_owned(),
+        };
+
+        #[cfg(all(feature = "self-signed", any(feature = "ssl", feature = "rustls")))]
+        if let Some(ref dir) = self.self_signed_dir {
+            let names = host_names(&self.listen_addresses, self.hostname.as_deref());
+            self.tls.set_source(self_signed(dir, &names)?);
+        }
+
+        // Load the certificate once, so a bad key fails before anything is bound.
+        #[cfg(any(feature = "ssl", feature = "rustls"))]
+        let tls = if self.tls.is_enabled() {
+            self.tls.reload()?;
+            Some(self.tls.clone())
+        } else {
+            None
         };
+        #[cfg(all(feature = "rustls", not(feature = "ssl")))]
+        let rustls_config = tls.as_ref().map(|tls| tls.rustls_config()).transpose()?;
+        #[cfg(all(feature = "rustls", feature = "ssl"))]
+        if tls.is_some() {
+            warn!("Both the ssl and rustls features are enabled, serving TLS with OpenSSL");
+        }
+
+        self.cors
+            .check()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let cors = self.cors.clone();
+        self.limits
+            .check()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
 
         let things_config = self.make_config();
+        let timeout = *lock(&self.shutdown.0.timeout);
+        let shutdown_timeout = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+
+        // Background tasks stop with the server, also when it is dropped rather than shut down.
+        let running = Arc::new(());
+        let stopped = {
+            let running = Arc::downgrade(&running);
+            let shutdown = self.shutdown.clone();
+            move || running.strong_count() == 0 || shutdown.is_shutting_down()
+        };
 
-        let server = HttpServer::new(move || {
+        let mut server = HttpServer::new(move || {
+            let _ = &running;
             let app = App::new()
                 .wrap(middleware::Logger::default())
                 .wrap(HostValidator)
-                .wrap(
-                    middleware::DefaultHeaders::new()
-                        .add(("Access-Control-Allow-Origin", "*"))
-                        .add((
-                            "Access-Control-Allow-Methods",
-                            "GET, HEAD, PUT, POST, DELETE, OPTIONS",
-                        ))
-                        .add((
-                            "Access-Control-Allow-Headers",
-                            "Origin, Content-Type, Accept, X-Requested-With",
-                        )),
-                )
+                .wrap(cors.middleware())
                 .configure(&things_config);
 
             if let Some(ref configure) = configure {
@@ -1029,52 +3120,142 @@ impl WebThingServer {
             } else {
                 app
             }
-        });
+        })
+        .shutdown_timeout(shutdown_timeout);
+
+        #[cfg(any(feature = "ssl", feature = "rustls"))]
+        {
+            server = server.on_connect(|connection, data| {
+                if let Some(identity) = ClientIdentity::from_connection(connection) {
+                    data.insert(identity);
+                }
+            });
+        }
 
-        let responder = libmdns::Responder::new().unwrap();
+        let mut ips = Vec::new();
+        for address in &self.listen_addresses {
+            match address {
+                ListenAddress::Unix(path) => {
+                    #[cfg(unix)]
+                    {
+                        server = server.bind_uds(path)?;
+                    }
 
-        #[cfg(feature = "ssl")]
-        match self.ssl_options {
-            Some(ref o) => {
-                self.dns_service = Some(responder.register(
-                    SERVICE_TYPE.to_owned(),
-                    name.clone(),
-                    port,
-                    &["path=/", "tls=1"],
-                ));
+                    #[cfg(not(unix))]
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Unsupported,
+                            format!("Unix domain sockets are not supported: {}", path.display()),
+                        ));
+                    }
+                }
+                address => {
+                    for ip in address.resolve()? {
+                        let socket_address = SocketAddr::new(ip, port);
+
+                        #[cfg(feature = "ssl")]
+                        if let Some(ref tls) = tls {
+                            server =
+                                server.bind_openssl(socket_address, tls.openssl_acceptor()?)?;
+                        } else {
+                            server = server.bind(socket_address)?;
+                        }
+
+                        #[cfg(all(feature = "rustls", not(feature = "ssl")))]
+                        if let Some(ref config) = rustls_config {
+                            server = server.bind_rustls_0_23(socket_address, config.clone())?;
+                        } else {
+                            server = server.bind(socket_address)?;
+                        }
+
+                        #[cfg(not(any(feature = "ssl", feature = "rustls")))]
+                        {
+                            server = server.bind(socket_address)?;
+                        }
 
-                let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-                builder
-                    .set_private_key_file(o.0.clone(), SslFiletype::PEM)
-                    .unwrap();
-                builder.set_certificate_chain_file(o.1.clone()).unwrap();
-                server
-                    .bind_openssl(format!("0.0.0.0:{}", port), builder)
-                    .expect("Failed to bind socket")
-                    .run()
+                        ips.push(ip);
+                    }
+                }
             }
-            None => {
-                self.dns_service = Some(responder.register(
-                    SERVICE_TYPE.to_owned(),
-                    name.clone(),
-                    port,
-                    &["path=/"],
-                ));
-                server
-                    .bind(format!("0.0.0.0:{}", port))
-                    .expect("Failed to bind socket")
-                    .run()
+        }
+
+        // Answer to and advertise the port actually bound, in case an ephemeral one was asked for.
+        self.bound_addresses = server.addrs();
+        let port = self
+            .bound_addresses
+            .first()
+            .map_or(port, |bound| bound.port());
+        self.hosts.set_names(
+            host_names(&self.listen_addresses, self.hostname.as_deref()),
+            port,
+        );
+
+        // Only announce the addresses that are reachable from other hosts.
+        let announced: Vec<IpAddr> = expand_addresses(&ips)
+            .into_iter()
+            .filter(|ip| !ip.is_loopback())
+            .collect();
+        if self.mdns_mode != MdnsMode::Disabled && !announced.is_empty() {
+            match libmdns::Responder::new_with_ip_list(announced) {
+                Ok(responder) => self.advertise(responder, name, port),
+                Err(err) => warn!("Not advertising the server over mDNS: {}", err),
             }
         }
 
-        #[cfg(not(feature = "ssl"))]
+        #[cfg(any(feature = "ssl", feature = "rustls"))]
+        if let (Some(tls), Some(interval)) = (tls, self.tls_reload_interval) {
+            let stopped = stopped.clone();
+            actix_web::rt::spawn(async move {
+                let mut ticks = actix_web::rt::time::interval(interval);
+                loop {
+                    ticks.tick().await;
+                    if stopped() {
+                        break;
+                    }
+                    tls.reload_if_modified();
+                }
+            });
+        }
+
+        if let Some(interval) = self.host_refresh_interval {
+            let hosts = self.hosts.clone();
+            let addresses = self.listen_addresses.clone();
+            let hostname = self.hostname.clone();
+            let stopped = stopped.clone();
+            actix_web::rt::spawn(async move {
+                let mut ticks = actix_web::rt::time::interval(interval);
+                ticks.tick().await;
+                loop {
+                    ticks.tick().await;
+                    if stopped() {
+                        break;
+                    }
+                    hosts.set_names(host_names(&addresses, hostname.as_deref()), port);
+                }
+            });
+        }
+
+        let limits = &self.limits;
+        if limits.per_client.is_some()
+            || limits.per_thing.is_some()
+            || limits.per_interaction.is_some()
         {
-            self.dns_service =
-                Some(responder.register(SERVICE_TYPE.to_owned(), name, port, &["path=/"]));
-            server
-                .bind(format!("0.0.0.0:{}", port))
-                .expect("Failed to bind socket")
-                .run()
+            let limiters = self.limiters.clone();
+            let stopped = stopped.clone();
+            actix_web::rt::spawn(async move {
+                let mut ticks = actix_web::rt::time::interval(RATE_LIMIT_CLEANUP_INTERVAL);
+                loop {
+                    ticks.tick().await;
+                    if stopped() {
+                        break;
+                    }
+                    limiters.retain_recent();
+                }
+            });
         }
+
+        let server = server.run();
+        self.shutdown.set_server(server.handle());
+        Ok(server)
     }
 }
@@ -0,0 +1,393 @@
+use actix_web::{App, HttpServer};
+use futures::StreamExt;
+use serde_json;
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, timeout, Instant};
+
+use super::action_generator::{ActionGenerator, BaseActionGenerator};
+use super::client::{
+    ActionRequest, ClientError, EventRecord, Notification, Observation, ThingClient,
+};
+use super::description::DescriptionFormat;
+use super::property::{AsyncValueForwarder, ForwarderFuture, ValueForwarder};
+use super::server::{HostValidator, ShutdownHandle, ThingsHandle, ThingsType, WebThingServer};
+
+pub use super::clock::ManualClock;
+
+/// Interval at which `TestClient::expect_action_status` polls the action.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Builder for a `TestServer`.
+pub struct TestServerBuilder {
+    things: ThingsType,
+    action_generator: Box<dyn ActionGenerator>,
+    base_path: Option<String>,
+    description_format: DescriptionFormat,
+    forwarder_timeout: Option<Duration>,
+}
+
+impl TestServerBuilder {
+    /// Set the action generator, `BaseActionGenerator` by default.
+    pub fn with_action_generator(mut self, generator: Box<dyn ActionGenerator>) -> Self {
+        self.action_generator = generator;
+        self
+    }
+
+    /// Serve the things below a base path, i.e. `/things`.
+    pub fn with_base_path(mut self, base_path: &str) -> Self {
+        self.base_path = Some(base_path.to_owned());
+        self
+    }
+
+    /// Set the format of the Thing Descriptions served.
+    pub fn with_description_format(mut self, format: DescriptionFormat) -> Self {
+        self.description_format = format;
+        self
+    }
+
+    /// Set how long to wait for asynchronous value forwarders.
+    pub fn with_forwarder_timeout(mut self, timeout: Duration) -> Self {
+        self.forwarder_timeout = Some(timeout);
+        self
+    }
+
+    /// Start serving the things on an ephemeral port of the loopback interface.
+    ///
+    /// Host validation is disabled. Must be called from within an actix `System`, i.e. in tests
+    /// marked `#[actix_rt::test]`, as the server runs on actix workers; a plain Tokio runtime
+    /// such as `#[tokio::test]` panics.
+    pub fn start(self) -> io::Result<TestServer> {
+        let mut server = WebThingServer::new(
+            self.things,
+            None,
+            None,
+            None,
+            self.action_generator,
+            self.base_path.clone(),
+            Some(true),
+        )
+        .with_description_format(self.description_format);
+        if let Some(timeout) = self.forwarder_timeout {
+            server = server.with_forwarder_timeout(timeout);
+        }
+
+        let config = server.make_config();
+        let http =
+            HttpServer::new(move || App::new().wrap(HostValidator).configure(config.clone()))
+                .workers(1)
+                .bind(("127.0.0.1", 0))?;
+        let url = format!(
+            "http://{}{}/",
+            http.addrs()[0],
+            self.base_path.unwrap_or_default()
+        );
+
+        let http = http.run();
+        let shutdown = server.shutdown_handle();
+        shutdown.set_server(http.handle());
+        tokio::spawn(http);
+
+        Ok(TestServer {
+            url,
+            shutdown,
+            things: server.things_handle(),
+        })
+    }
+}
+
+/// A server running in-process, for testing things.
+pub struct TestServer {
+    url: String,
+    shutdown: ShutdownHandle,
+    things: Option<ThingsHandle>,
+}
+
+impl TestServer {
+    /// Create a builder for a server of the given things.
+    pub fn builder(things: ThingsType) -> TestServerBuilder {
+        TestServerBuilder {
+            things,
+            action_generator: Box::new(BaseActionGenerator),
+            base_path: None,
+            description_format: DescriptionFormat::default(),
+            forwarder_timeout: None,
+        }
+    }
+
+    /// Start serving things with the default settings.
+    pub fn start(things: ThingsType) -> io::Result<Self> {
+        Self::builder(things).start()
+    }
+
+    /// Get the URL of the server's root, i.e. `http://127.0.0.1:41234/`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Get the handle to the things of a multiple-things server.
+    pub fn things_handle(&self) -> Option<ThingsHandle> {
+        self.things.clone()
+    }
+
+    /// Connect a test client to the thing of a single-thing server.
+    pub async fn client(&self) -> Result<TestClient, ClientError> {
+        TestClient::new(ThingClient::connect(&self.url).await?).await
+    }
+
+    /// Connect a test client to a thing of a multiple-things server, by its path.
+    pub async fn client_for(&self, path: &str) -> Result<TestClient, ClientError> {
+        let url = format!("{}{}", self.url, path.trim_start_matches('/'));
+        TestClient::new(ThingClient::connect(&url).await?).await
+    }
+
+    /// Get a handle to shut the server down, i.e. to test how clients handle it.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Stop the server gracefully, closing any open WebSockets and cancelling pending actions.
+    pub async fn stop(self) {
+        self.shutdown.shutdown().await;
+    }
+}
+
+/// A client for testing a thing, observing its WebSocket from the moment it connects.
+///
+/// Dereferences to the underlying `ThingClient`, for reading, writing and invoking the thing.
+/// The `expect_*` methods panic with a description of what was received when the expectation
+/// is not met in time, like the assertion macros.
+pub struct TestClient {
+    client: ThingClient,
+    observation: Observation,
+    received: Vec<Notification>,
+}
+
+impl TestClient {
+    /// Create a test client, subscribing to all of the thing's events.
+    pub async fn new(client: ThingClient) -> Result<Self, ClientError> {
+        let events: Vec<String> = client
+            .description()
+            .get("events")
+            .and_then(|e| e.as_object())
+            .map(|e| e.keys().cloned().collect())
+            .unwrap_or_default();
+        let events: Vec<&str> = events.iter().map(|e| e.as_str()).collect();
+        let mut observation = client.observe(&events)?;
+
+        // Wait for the subscriptions to be in place before any test steps.
+        match timeout(Duration::from_secs(5), observation.next()).await {
+            Ok(Some(Notification::Connected)) => (),
+            _ => return Err(ClientError::InvalidResponse("WebSocket did not connect")),
+        }
+
+        Ok(TestClient {
+            client,
+            observation,
+            received: Vec::new(),
+        })
+    }
+
+    /// Wait for a notification matching `predicate`, skipping any others.
+    ///
+    /// Returns `None` if none is received within `within`.
+    pub async fn next_matching<T>(
+        &mut self,
+        within: Duration,
+        mut predicate: impl FnMut(&Notification) -> Option<T>,
+    ) -> Option<T> {
+        let deadline = Instant::now() + within;
+        loop {
+            let notification =
+                match tokio::time::timeout_at(deadline, self.observation.next()).await {
+                    Ok(Some(notification)) => notification,
+                    _ => return None,
+                };
+
+            if let Some(result) = predicate(&notification) {
+                return Some(result);
+            }
+            self.received.push(notification);
+        }
+    }
+
+    /// Expect a `propertyStatus` for a property within a time, returning its value.
+    pub async fn expect_property_status(
+        &mut self,
+        name: &str,
+        within: Duration,
+    ) -> serde_json::Value {
+        let found = self
+            .next_matching(within, |notification| match notification {
+                Notification::PropertyStatus(values) => values.get(name).cloned(),
+                _ => None,
+            })
+            .await;
+
+        found.unwrap_or_else(|| self.fail(&format!("propertyStatus for {}", name), within))
+    }
+
+    /// Expect a `propertyStatus` setting a property to a value within a time.
+    pub async fn expect_property_value(
+        &mut self,
+        name: &str,
+        value: serde_json::Value,
+        within: Duration,
+    ) {
+        let found = self
+            .next_matching(within, |notification| match notification {
+                Notification::PropertyStatus(values) if values.get(name) == Some(&value) => {
+                    Some(())
+                }
+                _ => None,
+            })
+            .await;
+
+        if found.is_none() {
+            self.fail(&format!("propertyStatus of {} for {}", value, name), within)
+        }
+    }
+
+    /// Expect an event within a time, returning it.
+    pub async fn expect_event(&mut self, name: &str, within: Duration) -> EventRecord {
+        let found = self
+            .next_matching(within, |notification| match notification {
+                Notification::Event(event) if event.name == name => Some(event.clone()),
+                _ => None,
+            })
+            .await;
+
+        found.unwrap_or_else(|| self.fail(&format!("event {}", name), within))
+    }
+
+    /// Expect an action request to reach a status within a time, i.e. `completed`.
+    ///
+    /// The request is polled over HTTP, so statuses reached before the call are seen too.
+    pub async fn expect_action_status(
+        &self,
+        request: &ActionRequest,
+        status: &str,
+        within: Duration,
+    ) -> ActionRequest {
+        let deadline = Instant::now() + within;
+        loop {
+            let current = self.client.get_action(&request.name, &request.id).await;
+            match current {
+                Ok(current) if current.status == status => return current,
+                _ if Instant::now() >= deadline => panic!(
+                    "expected action {} {} to be {} within {:?}, last seen: {:?}",
+                    request.name, request.id, status, within, current
+                ),
+                _ => sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Expect no notification at all for a time.
+    pub async fn expect_quiet(&mut self, within: Duration) {
+        if let Some(notification) = self
+            .next_matching(within, |notification| Some(notification.clone()))
+            .await
+        {
+            panic!(
+                "expected no notifications within {:?}, got {:?}",
+                within, notification
+            );
+        }
+    }
+
+    /// Get the notifications skipped while waiting for expected ones.
+    pub fn skipped(&self) -> &[Notification] {
+        &self.received
+    }
+
+    fn fail(&self, expected: &str, within: Duration) -> ! {
+        panic!(
+            "expected {} within {:?}, received: {:?}",
+            expected, within, self.received
+        )
+    }
+}
+
+impl Deref for TestClient {
+    type Target = ThingClient;
+
+    fn deref(&self) -> &ThingClient {
+        &self.client
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    values: Vec<serde_json::Value>,
+    failure: Option<&'static str>,
+    delay: Duration,
+}
+
+/// Value forwarder recording the values it is given, for use as a `ValueForwarder` or an
+/// `AsyncValueForwarder`.
+///
+/// Clones share their state, so a test can keep one to inspect and control the forwarder passed
+/// to a property.
+#[derive(Clone, Default)]
+pub struct MockForwarder(Arc<Mutex<MockState>>);
+
+impl MockForwarder {
+    /// Create a new MockForwarder, accepting all values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the forwarder fail with a message, or succeed again with `None`.
+    pub fn set_failure(&self, failure: Option<&'static str>) {
+        self.state().failure = failure;
+    }
+
+    /// Delay the asynchronous forwarder's results, i.e. to test timeouts.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state().delay = delay;
+    }
+
+    /// Get the values forwarded so far, including rejected ones.
+    pub fn values(&self) -> Vec<serde_json::Value> {
+        self.state().values.clone()
+    }
+
+    /// Get the last value forwarded.
+    pub fn last_value(&self) -> Option<serde_json::Value> {
+        self.state().values.last().cloned()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn forward(&self, value: serde_json::Value) -> Result<serde_json::Value, &'static str> {
+        let mut state = self.state();
+        state.values.push(value.clone());
+        match state.failure {
+            Some(failure) => Err(failure),
+            None => Ok(value),
+        }
+    }
+}
+
+impl ValueForwarder for MockForwarder {
+    fn set_value(&mut self, value: serde_json::Value) -> Result<serde_json::Value, &'static str> {
+        self.forward(value)
+    }
+}
+
+impl AsyncValueForwarder for MockForwarder {
+    fn set_value(&self, value: serde_json::Value) -> ForwarderFuture {
+        let delay = self.state().delay;
+        let result = self.forward(value);
+
+        Box::pin(async move {
+            sleep(delay).await;
+            result
+        })
+    }
+}
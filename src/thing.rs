@@ -5,17 +5,30 @@ use std::collections::HashMap;
 use std::marker::{Send, Sync};
 use std::sync::{Arc, RwLock};
 use std::vec::Drain;
+use uuid::Uuid;
 use valico::json_schema;
 
 use super::action::Action;
+use super::clock::Clock;
+use super::description::{self, is_td_context, ValidationError};
 use super::event::Event;
-use super::property::Property;
+use super::property::{BaseProperty, Property};
+use super::utils::{is_valid_path_segment, read_lock, write_lock};
 
 /// High-level Thing trait.
 pub trait Thing: Send + Sync {
     /// Return the thing state as a Thing Description.
     fn as_thing_description(&self) -> serde_json::Map<String, serde_json::Value>;
 
+    /// Validate this thing's Thing Description.
+    ///
+    /// Returns the problems found by checking `as_thing_description()` against the WebThings
+    /// schema and the subset of the W3C WoT Thing Description 1.1 schema covering the members
+    /// this crate produces.
+    fn validate_description(&self) -> Result<(), Vec<ValidationError>> {
+        description::validate(&self.as_thing_description())
+    }
+
     /// Return this thing as an Any.
     fn as_any(&self) -> &dyn Any;
 
@@ -25,7 +38,7 @@ pub trait Thing: Send + Sync {
     /// Get this thing's href.
     fn get_href(&self) -> String;
 
-    /// Get this thing's href prefix, i.e. /0.
+    /// Get this thing's href prefix, i.e. /my-lamp-1234.
     fn get_href_prefix(&self) -> String;
 
     /// Get the UI href.
@@ -108,6 +121,13 @@ pub trait Thing: Send + Sync {
         action_id: String,
     ) -> Option<Arc<RwLock<Box<dyn Action>>>>;
 
+    /// Get all actions requested of this thing.
+    ///
+    /// Used to cancel the actions still running when the server shuts down.
+    fn get_actions(&self) -> Vec<Arc<RwLock<Box<dyn Action>>>> {
+        Vec::new()
+    }
+
     /// Add a new event and notify subscribers.
     fn add_event(&mut self, event: Box<dyn Event>);
 
@@ -189,6 +209,8 @@ pub trait Thing: Send + Sync {
     fn event_notify(&mut self, name: String, event: serde_json::Map<String, serde_json::Value>);
 
     /// Start the specified action.
+    ///
+    /// The server doesn't call this, as it performs actions without holding the thing's lock.
     fn start_action(&mut self, name: String, id: String);
 
     /// Cancel the specified action.
@@ -202,7 +224,22 @@ pub trait Thing: Send + Sync {
     /// # Arguments
     ///
     /// * `ws_id` - ID of the websocket
-    fn drain_queue(&mut self, ws_id: String) -> Vec<Drain<String>>;
+    fn drain_queue(&mut self, ws_id: String) -> Vec<Drain<'_, String>>;
+
+    /// Get the child things of this thing, with the path segments they are served under.
+    ///
+    /// Children are served below their parent, i.e. `<parent href>/things/<path>`.
+    fn get_children(&self) -> Vec<(String, Arc<RwLock<Box<dyn Thing>>>)> {
+        Vec::new()
+    }
+
+    /// Find a child thing by the path segment it is served under.
+    fn get_child(&self, path: &str) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.get_children()
+            .into_iter()
+            .find(|(child_path, _)| child_path == path)
+            .map(|(_, child)| child)
+    }
 }
 
 /// Vocabularies to be passed as json-ld @context
@@ -257,6 +294,38 @@ impl Default for ThingContext {
     }
 }
 
+/// Get a value to initialize a property with, based on its schema.
+fn initial_value(schema: &serde_json::Map<String, serde_json::Value>) -> serde_json::Value {
+    if let Some(value) = schema.get("const").or_else(|| schema.get("default")) {
+        return value.clone();
+    }
+
+    if let Some(value) = schema
+        .get("enum")
+        .and_then(|e| e.as_array())
+        .and_then(|e| e.first())
+    {
+        return value.clone();
+    }
+
+    match schema.get("type").and_then(|t| t.as_str()) {
+        Some("boolean") => json!(false),
+        Some("integer") | Some("number") => {
+            let minimum = schema.get("minimum").filter(|m| m.is_number());
+            let maximum = schema.get("maximum").filter(|m| m.is_number());
+            match (minimum, maximum) {
+                (Some(minimum), _) if minimum.as_f64() > Some(0.0) => minimum.clone(),
+                (_, Some(maximum)) if maximum.as_f64() < Some(0.0) => maximum.clone(),
+                _ => json!(0),
+            }
+        }
+        Some("string") => json!(""),
+        Some("array") => json!([]),
+        Some("object") => json!({}),
+        _ => serde_json::Value::Null,
+    }
+}
+
 /// Basic web thing implementation.
 ///
 /// This can easily be used by other things to handle most of the boring work.
@@ -275,6 +344,8 @@ pub struct BaseThing {
     subscribers: HashMap<String, Vec<String>>,
     href_prefix: String,
     ui_href: Option<String>,
+    children: Vec<(String, Arc<RwLock<Box<dyn Thing>>>)>,
+    clock: Option<Arc<dyn Clock>>,
 }
 
 impl BaseThing {
@@ -295,19 +366,213 @@ impl BaseThing {
     ) -> Self {
         Self {
             id,
-            type_: type_.unwrap_or_else(Vec::new),
+            type_: type_.unwrap_or_default(),
             title,
-            description: description.unwrap_or_else(|| "".to_string()),
+            description: description.unwrap_or_default(),
             ..Default::default()
         }
     }
 
+    /// Create a BaseThing from a Thing Description.
+    ///
+    /// Both WebThings descriptions and W3C WoT Thing Descriptions with `forms` are supported.
+    /// Properties start out with their `const` or `default` value, or else a value matching their
+    /// schema, and are writable unless marked read-only. Links, forms and the W3C context are
+    /// dropped, as the server provides its own, and so are members the WebThings format has no
+    /// equivalent for, such as `uriVariables` and the `safe`, `idempotent` and `synchronous`
+    /// action hints.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - the Thing Description, as a JSON object
+    pub fn from_description(description: &serde_json::Value) -> Result<Self, &'static str> {
+        let description = description
+            .as_object()
+            .ok_or("Description must be an object")?;
+
+        let title = description
+            .get("title")
+            .and_then(|t| t.as_str())
+            .ok_or("Description has no title")?;
+        let id = match description.get("id") {
+            Some(id) => id.as_str().ok_or("Invalid id")?.to_owned(),
+            None => format!("urn:uuid:{}", Uuid::new_v4()),
+        };
+        let type_ = match description.get("@type") {
+            Some(serde_json::Value::String(type_)) => vec![type_.to_owned()],
+            Some(serde_json::Value::Array(types)) => types
+                .iter()
+                .map(|t| t.as_str().map(|t| t.to_owned()).ok_or("Invalid @type"))
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err("Invalid @type"),
+            None => Vec::new(),
+        };
+
+        let mut thing = BaseThing::new(
+            id,
+            title.to_owned(),
+            Some(type_),
+            description
+                .get("description")
+                .and_then(|d| d.as_str())
+                .map(|d| d.to_owned()),
+        );
+
+        let entries = match description.get("@context") {
+            Some(serde_json::Value::Array(entries)) => entries.iter().collect(),
+            Some(entry) => vec![entry],
+            None => Vec::new(),
+        };
+        let mut context = ThingContext {
+            plain: Vec::new(),
+            namespaced: HashMap::new(),
+        };
+        for entry in entries {
+            match entry {
+                serde_json::Value::String(uri) if !is_td_context(uri) => {
+                    context.plain.push(uri.to_owned())
+                }
+                serde_json::Value::Object(namespaces) => {
+                    for (prefix, uri) in namespaces {
+                        if let Some(uri) = uri.as_str() {
+                            context.namespaced.insert(prefix.to_owned(), uri.to_owned());
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        if !context.plain.is_empty() || !context.namespaced.is_empty() {
+            thing = thing.with_context(context);
+        }
+
+        let interactions = |key: &str| -> Result<
+            Vec<(String, serde_json::Map<String, serde_json::Value>)>,
+            &'static str,
+        > {
+            match description.get(key) {
+                Some(serde_json::Value::Object(interactions)) => interactions
+                    .iter()
+                    .map(|(name, metadata)| {
+                        let mut metadata = metadata
+                            .as_object()
+                            .ok_or("Interaction must be an object")?
+                            .clone();
+                        metadata.remove("links");
+                        metadata.remove("forms");
+                        metadata.remove("uriVariables");
+                        Ok((name.to_owned(), metadata))
+                    })
+                    .collect(),
+                Some(_) => Err("Interactions must be an object"),
+                None => Ok(Vec::new()),
+            }
+        };
+
+        for (name, metadata) in interactions("properties")? {
+            let value = initial_value(&metadata);
+            thing.add_property(Box::new(BaseProperty::new(
+                name,
+                value,
+                None,
+                Some(metadata),
+            )));
+        }
+
+        for (name, mut metadata) in interactions("actions")? {
+            // WebThings actions are always asynchronous and unsafe, so the W3C hints don't apply.
+            metadata.remove("safe");
+            metadata.remove("idempotent");
+            metadata.remove("synchronous");
+            thing.add_available_action(name, metadata);
+        }
+
+        for (name, mut metadata) in interactions("events")? {
+            // W3C descriptions nest the event's schema under "data", WebThings ones inline it.
+            if let Some(serde_json::Value::Object(data)) = metadata.remove("data") {
+                for (keyword, value) in data {
+                    if keyword != "title" && keyword != "description" {
+                        metadata.entry(keyword).or_insert(value);
+                    }
+                }
+            }
+            metadata.remove("subscription");
+            metadata.remove("cancellation");
+            metadata.remove("dataResponse");
+            thing.add_available_event(name, metadata);
+        }
+
+        if let Some(links) = description.get("links").and_then(|l| l.as_array()) {
+            for link in links {
+                let media_type = link.get("mediaType").or_else(|| link.get("type"));
+                if link.get("rel").and_then(|r| r.as_str()) == Some("alternate")
+                    && media_type.and_then(|t| t.as_str()) == Some("text/html")
+                {
+                    if let Some(href) = link.get("href").and_then(|h| h.as_str()) {
+                        thing.set_ui_href(href.to_owned());
+                    }
+                }
+            }
+        }
+
+        Ok(thing)
+    }
+
     /// Overwrite the Thing Description context with a new set of
     /// vocabularies.
     pub fn with_context(mut self, context: ThingContext) -> Self {
         self.context = context;
         self
     }
+
+    /// Timestamp the thing's actions and events with a clock, instead of the system time in
+    /// whole seconds.
+    ///
+    /// Actions and events are restamped when they are added to the thing.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Add a child thing, e.g. an outlet of a power strip.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path segment to serve the child under, relative to `<href>/things/`, made of
+    ///   unreserved URL characters only
+    /// * `child` - the child thing
+    pub fn add_child(
+        &mut self,
+        path: String,
+        child: Arc<RwLock<Box<dyn Thing>>>,
+    ) -> Result<(), &'static str> {
+        if !is_valid_path_segment(&path) {
+            return Err("Invalid path segment");
+        }
+
+        if self
+            .children
+            .iter()
+            .any(|(child_path, _)| *child_path == path)
+        {
+            return Err("Path segment already in use");
+        }
+
+        write_lock(&child).set_href_prefix(format!("{}/things/{}", self.href_prefix, path));
+        self.children.push((path, child));
+        Ok(())
+    }
+
+    /// Remove a child thing.
+    ///
+    /// Returns the removed child, if any.
+    pub fn remove_child(&mut self, path: &str) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        let position = self
+            .children
+            .iter()
+            .position(|(child_path, _)| child_path == path)?;
+        Some(self.children.remove(position).1)
+    }
 }
 
 impl Thing for BaseThing {
@@ -358,6 +623,13 @@ impl Thing for BaseThing {
             links.push(ui_link);
         }
 
+        for (_, child) in self.children.iter() {
+            let mut child_link = serde_json::Map::new();
+            child_link.insert("rel".to_owned(), json!("item"));
+            child_link.insert("href".to_owned(), json!(read_lock(child).get_href()));
+            links.push(child_link);
+        }
+
         description.insert("links".to_owned(), json!(links));
 
         let mut actions = serde_json::Map::new();
@@ -420,7 +692,7 @@ impl Thing for BaseThing {
         }
     }
 
-    /// Get this thing's href prefix, i.e. /0.
+    /// Get this thing's href prefix, i.e. /my-lamp-1234.
     fn get_href_prefix(&self) -> String {
         self.href_prefix.clone()
     }
@@ -440,9 +712,13 @@ impl Thing for BaseThing {
 
         for actions in self.actions.values_mut() {
             for action in actions {
-                action.write().unwrap().set_href_prefix(prefix.clone());
+                write_lock(action).set_href_prefix(prefix.clone());
             }
         }
+
+        for (path, child) in self.children.iter() {
+            write_lock(child).set_href_prefix(format!("{}/things/{}", prefix, path));
+        }
     }
 
     /// Set the href of this thing's custom UI.
@@ -508,13 +784,13 @@ impl Thing for BaseThing {
             Some(action_name) => {
                 if let Some(actions) = self.actions.get(&action_name) {
                     for action in actions {
-                        descriptions.push(action.read().unwrap().as_action_description());
+                        descriptions.push(read_lock(action).as_action_description());
                     }
                 }
             }
             None => {
                 for action in self.actions.values().flatten() {
-                    descriptions.push(action.read().unwrap().as_action_description());
+                    descriptions.push(read_lock(action).as_action_description());
                 }
             }
         }
@@ -590,7 +866,7 @@ impl Thing for BaseThing {
         match self.actions.get(&action_name) {
             Some(entry) => {
                 for action in entry {
-                    if action.read().unwrap().get_id() == action_id {
+                    if read_lock(action).get_id() == action_id {
                         return Some(action.clone());
                     }
                 }
@@ -601,8 +877,17 @@ impl Thing for BaseThing {
         }
     }
 
+    /// Get all actions requested of this thing.
+    fn get_actions(&self) -> Vec<Arc<RwLock<Box<dyn Action>>>> {
+        self.actions.values().flatten().cloned().collect()
+    }
+
     /// Add a new event and notify subscribers.
-    fn add_event(&mut self, event: Box<dyn Event>) {
+    fn add_event(&mut self, mut event: Box<dyn Event>) {
+        if let Some(ref clock) = self.clock {
+            event.set_clock(clock.clone());
+        }
+
         self.event_notify(event.get_name(), event.as_event_description());
         self.events.push(event);
     }
@@ -630,7 +915,7 @@ impl Thing for BaseThing {
         action: Arc<RwLock<Box<dyn Action>>>,
         input: Option<&serde_json::Value>,
     ) -> Result<(), &str> {
-        let action_name = action.read().unwrap().get_name();
+        let action_name = read_lock(&action).get_name();
 
         if let Some(action_type) = self.available_actions.get(&action_name) {
             if !action_type.validate_action_input(input) {
@@ -640,11 +925,14 @@ impl Thing for BaseThing {
             return Err("Action type not found");
         }
 
-        action
-            .write()
-            .unwrap()
-            .set_href_prefix(self.get_href_prefix());
-        self.action_notify(action.read().unwrap().as_action_description());
+        {
+            let mut action = write_lock(&action);
+            action.set_href_prefix(self.get_href_prefix());
+            if let Some(ref clock) = self.clock {
+                action.set_clock(clock.clone());
+            }
+        }
+        self.action_notify(read_lock(&action).as_action_description());
         self.actions.get_mut(&action_name).unwrap().push(action);
 
         Ok(())
@@ -657,10 +945,10 @@ impl Thing for BaseThing {
         let action = self.get_action(action_name.clone(), action_id.clone());
         match action {
             Some(action) => {
-                action.write().unwrap().cancel();
+                write_lock(&action).cancel();
 
                 let actions = self.actions.get_mut(&action_name).unwrap();
-                actions.retain(|a| a.read().unwrap().get_id() != action_id);
+                actions.retain(|a| read_lock(a).get_id() != action_id);
 
                 true
             }
@@ -781,7 +1069,7 @@ impl Thing for BaseThing {
     /// Start the specified action.
     fn start_action(&mut self, name: String, id: String) {
         if let Some(action) = self.get_action(name, id) {
-            let mut a = action.write().unwrap();
+            let mut a = write_lock(&action);
             a.start();
             self.action_notify(a.as_action_description());
             a.perform_action();
@@ -791,7 +1079,7 @@ impl Thing for BaseThing {
     /// Cancel the specified action.
     fn cancel_action(&mut self, name: String, id: String) {
         if let Some(action) = self.get_action(name, id) {
-            let mut a = action.write().unwrap();
+            let mut a = write_lock(&action);
             a.cancel();
         }
     }
@@ -799,7 +1087,7 @@ impl Thing for BaseThing {
     /// Finish the specified action.
     fn finish_action(&mut self, name: String, id: String) {
         if let Some(action) = self.get_action(name, id) {
-            let mut a = action.write().unwrap();
+            let mut a = write_lock(&action);
             a.finish();
             self.action_notify(a.as_action_description());
         }
@@ -811,8 +1099,8 @@ impl Thing for BaseThing {
     /// # Arguments
     ///
     /// * `ws_id` - ID of the websocket
-    fn drain_queue(&mut self, ws_id: String) -> Vec<Drain<String>> {
-        let mut drains: Vec<Drain<String>> = Vec::new();
+    fn drain_queue(&mut self, ws_id: String) -> Vec<Drain<'_, String>> {
+        let mut drains: Vec<Drain<'_, String>> = Vec::new();
         if let Some(v) = self.subscribers.get_mut(&ws_id) {
             drains.push(v.drain(..));
         }
@@ -825,6 +1113,11 @@ impl Thing for BaseThing {
 
         drains
     }
+
+    /// Get the child things of this thing, with the path segments they are served under.
+    fn get_children(&self) -> Vec<(String, Arc<RwLock<Box<dyn Thing>>>)> {
+        self.children.clone()
+    }
 }
 
 /// Struct to describe an action available to be taken.
@@ -864,10 +1157,7 @@ impl AvailableAction {
                 }
             }
 
-            match scope.compile_and_return(json!(schema), true) {
-                Ok(s) => Some(s),
-                Err(_) => None,
-            }
+            scope.compile_and_return(json!(schema), true).ok()
         } else {
             None
         };
@@ -0,0 +1,517 @@
+use crate::utils::{read_lock, write_lock};
+use actix_web::dev::Payload;
+use actix_web::{error, FromRequest, HttpRequest};
+use log::{info, warn};
+use std::any::Any;
+use std::fmt;
+use std::fs;
+use std::future::{ready, Ready};
+use std::io;
+#[cfg(feature = "self-signed")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Certificate of the server, as swapped in on reload.
+#[cfg(feature = "ssl")]
+type Certificate = openssl::ssl::SslContext;
+#[cfg(all(feature = "rustls", not(feature = "ssl")))]
+type Certificate = Arc<rustls::sign::CertifiedKey>;
+
+/// Stream of a TLS connection, as passed to `HttpServer::on_connect()`.
+#[cfg(feature = "ssl")]
+type Stream = actix_tls::accept::openssl::TlsStream<actix_web::rt::net::TcpStream>;
+#[cfg(all(feature = "rustls", not(feature = "ssl")))]
+type Stream = actix_tls::accept::rustls_0_23::TlsStream<actix_web::rt::net::TcpStream>;
+
+/// Where the certificate chain and private key of the server come from.
+#[derive(Clone)]
+pub(crate) enum TlsSource {
+    /// PEM files, read when the server starts and on reload.
+    Files { key: PathBuf, cert: PathBuf },
+    /// PEM-encoded certificate chain and private key held in memory.
+    Pem { key: Vec<u8>, cert: Vec<u8> },
+}
+
+impl TlsSource {
+    /// Read the certificate chain and private key.
+    fn load(&self) -> io::Result<TlsIdentity> {
+        match self {
+            TlsSource::Files { key, cert } => {
+                let read = |kind: &str, path: &PathBuf| {
+                    fs::read(path).map_err(|err| {
+                        io::Error::new(
+                            err.kind(),
+                            format!("Cannot read TLS {} {}: {}", kind, path.display(), err),
+                        )
+                    })
+                };
+
+                Ok(TlsIdentity {
+                    key: read("private key", key)?,
+                    cert: read("certificate", cert)?,
+                })
+            }
+            TlsSource::Pem { key, cert } => Ok(TlsIdentity {
+                key: key.clone(),
+                cert: cert.clone(),
+            }),
+        }
+    }
+
+    /// Get when the files were last modified, if they are files.
+    fn modified(&self) -> Option<SystemTime> {
+        match self {
+            TlsSource::Files { key, cert } => {
+                let modified = |path: &PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+                modified(key).max(modified(cert))
+            }
+            TlsSource::Pem { .. } => None,
+        }
+    }
+}
+
+/// Generate a self-signed certificate for the given host names, persisted in a directory.
+///
+/// A certificate generated earlier is reused as long as it is valid and covers all the names.
+#[cfg(feature = "self-signed")]
+pub(crate) fn self_signed(dir: &Path, names: &[String]) -> io::Result<TlsSource> {
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+
+    // Host names enclose IPv6 addresses in brackets.
+    let mut unique: Vec<String> = Vec::new();
+    for name in names {
+        let name = name
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_owned();
+        if !unique.contains(&name) {
+            unique.push(name);
+        }
+    }
+    let names = unique;
+
+    let reusable = key.is_file()
+        && fs::read(&cert)
+            .map(|pem| covers(&pem, &names))
+            .unwrap_or(false);
+    if !reusable {
+        let certified = rcgen::generate_simple_self_signed(names.clone()).map_err(|err| {
+            io::Error::other(format!("Cannot generate a TLS certificate: {}", err))
+        })?;
+
+        fs::create_dir_all(dir)?;
+        write_private(&key, certified.key_pair.serialize_pem().as_bytes())?;
+        fs::write(&cert, certified.cert.pem())?;
+        info!(
+            "Generated a self-signed TLS certificate for {} in {}",
+            names.join(", "),
+            dir.display()
+        );
+    }
+
+    Ok(TlsSource::Files { key, cert })
+}
+
+/// Check whether a PEM-encoded certificate is valid and covers all the names.
+#[cfg(feature = "self-signed")]
+fn covers(pem: &[u8], names: &[String]) -> bool {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use x509_parser::extensions::GeneralName;
+
+    let pem = match x509_parser::pem::parse_x509_pem(pem) {
+        Ok((_, pem)) => pem,
+        Err(_) => return false,
+    };
+    let cert = match pem.parse_x509() {
+        Ok(cert) => cert,
+        Err(_) => return false,
+    };
+    if !cert.validity().is_valid() {
+        return false;
+    }
+
+    let covered: Vec<String> = match cert.subject_alternative_name() {
+        Ok(Some(extension)) => extension
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(name) => Some(name.to_string()),
+                GeneralName::IPAddress(octets) => match octets.len() {
+                    4 => <[u8; 4]>::try_from(*octets)
+                        .ok()
+                        .map(|octets| IpAddr::from(Ipv4Addr::from(octets)).to_string()),
+                    16 => <[u8; 16]>::try_from(*octets)
+                        .ok()
+                        .map(|octets| IpAddr::from(Ipv6Addr::from(octets)).to_string()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        _ => return false,
+    };
+
+    names.iter().all(|name| covered.contains(name))
+}
+
+/// Write a file only the current user can read, where supported.
+#[cfg(feature = "self-signed")]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(contents)
+}
+
+/// PEM-encoded certificate chain and private key, as loaded.
+struct TlsIdentity {
+    key: Vec<u8>,
+    cert: Vec<u8>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(feature = "ssl")]
+impl TlsIdentity {
+    /// Build an OpenSSL acceptor, checking that the key matches the certificate.
+    ///
+    /// With `client_ca`, clients must present a certificate issued by one of its certificates.
+    fn acceptor(&self, client_ca: Option<&[u8]>) -> io::Result<openssl::ssl::SslAcceptorBuilder> {
+        use openssl::pkey::PKey;
+        use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+        use openssl::x509::X509;
+
+        let key = PKey::private_key_from_pem(&self.key)
+            .map_err(|err| invalid(format!("Invalid TLS private key: {}", err)))?;
+        let mut chain = X509::stack_from_pem(&self.cert)
+            .map_err(|err| invalid(format!("Invalid TLS certificate: {}", err)))?
+            .into_iter();
+        let leaf = chain
+            .next()
+            .ok_or_else(|| invalid("Invalid TLS certificate: no certificate found".to_owned()))?;
+
+        let mut builder =
+            SslAcceptor::mozilla_intermediate(SslMethod::tls()).map_err(io::Error::other)?;
+        builder
+            .set_private_key(&key)
+            .map_err(|err| invalid(format!("Invalid TLS private key: {}", err)))?;
+        builder
+            .set_certificate(&leaf)
+            .map_err(|err| invalid(format!("Invalid TLS certificate: {}", err)))?;
+        for cert in chain {
+            builder
+                .add_extra_chain_cert(cert)
+                .map_err(|err| invalid(format!("Invalid TLS certificate: {}", err)))?;
+        }
+        builder
+            .check_private_key()
+            .map_err(|err| invalid(format!("Invalid TLS private key: {}", err)))?;
+
+        if let Some(client_ca) = client_ca {
+            let cas = X509::stack_from_pem(client_ca)
+                .map_err(|err| invalid(format!("Invalid TLS client CA: {}", err)))?;
+            if cas.is_empty() {
+                return Err(invalid(
+                    "Invalid TLS client CA: no certificate found".to_owned(),
+                ));
+            }
+
+            for ca in cas {
+                builder
+                    .add_client_ca(&ca)
+                    .and_then(|_| builder.cert_store_mut().add_cert(ca))
+                    .map_err(|err| invalid(format!("Invalid TLS client CA: {}", err)))?;
+            }
+            builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+
+        Ok(builder)
+    }
+
+    /// Build the certificate swapped in on reload.
+    fn certificate(&self, client_ca: Option<&[u8]>) -> io::Result<Certificate> {
+        Ok(self.acceptor(client_ca)?.build().into_context())
+    }
+}
+
+#[cfg(all(feature = "rustls", not(feature = "ssl")))]
+impl TlsIdentity {
+    /// Build the certificate swapped in on reload, checking that the key matches it.
+    fn certificate(&self, _client_ca: Option<&[u8]>) -> io::Result<Certificate> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+        let chain = CertificateDer::pem_slice_iter(&self.cert)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| invalid(format!("Invalid TLS certificate: {}", err)))?;
+        if chain.is_empty() {
+            return Err(invalid(
+                "Invalid TLS certificate: no certificate found".to_owned(),
+            ));
+        }
+
+        let key = PrivateKeyDer::from_pem_slice(&self.key)
+            .map_err(|err| invalid(format!("Invalid TLS private key: {}", err)))?;
+        let key = rustls::crypto::ring::sign::any_supported_type(&key)
+            .map_err(|err| invalid(format!("Invalid TLS private key: {}", err)))?;
+
+        let certified = rustls::sign::CertifiedKey::new(chain, key);
+        certified
+            .keys_match()
+            .map_err(|err| invalid(format!("Invalid TLS private key: {}", err)))?;
+        Ok(Arc::new(certified))
+    }
+}
+
+struct TlsState {
+    source: RwLock<Option<TlsSource>>,
+    client_ca: RwLock<Option<Vec<u8>>>,
+    modified: RwLock<Option<SystemTime>>,
+    certificate: RwLock<Option<Certificate>>,
+}
+
+/// Handle to replace the certificate of a server serving TLS, from any thread.
+///
+/// New connections use the new certificate; open connections are kept.
+#[derive(Clone)]
+pub struct TlsHandle(Arc<TlsState>);
+
+impl fmt::Debug for TlsHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Leave out the source, which may hold the private key.
+        f.debug_struct("TlsHandle")
+            .field("enabled", &self.is_enabled())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsHandle {
+    pub(crate) fn new(source: Option<TlsSource>) -> Self {
+        TlsHandle(Arc::new(TlsState {
+            source: RwLock::new(source),
+            client_ca: RwLock::new(None),
+            modified: RwLock::new(None),
+            certificate: RwLock::new(None),
+        }))
+    }
+
+    pub(crate) fn set_source(&self, source: TlsSource) {
+        *write_lock(&self.0.source) = Some(source);
+    }
+
+    pub(crate) fn set_client_ca(&self, client_ca: Vec<u8>) {
+        *write_lock(&self.0.client_ca) = Some(client_ca);
+    }
+
+    /// Whether the server is configured to serve TLS.
+    pub fn is_enabled(&self) -> bool {
+        read_lock(&self.0.source).is_some()
+    }
+
+    /// Read the certificate chain and private key again, i.e. after they were renewed.
+    ///
+    /// Fails if they cannot be read or do not match, keeping the current certificate.
+    pub fn reload(&self) -> io::Result<()> {
+        let source = read_lock(&self.0.source)
+            .clone()
+            .ok_or_else(|| invalid("TLS is not configured".to_owned()))?;
+        let modified = source.modified();
+        let certificate = source
+            .load()?
+            .certificate(read_lock(&self.0.client_ca).as_deref())?;
+
+        *write_lock(&self.0.certificate) = Some(certificate);
+        *write_lock(&self.0.modified) = modified;
+        Ok(())
+    }
+
+    /// Replace the certificate chain and private key with PEM-encoded ones held in memory.
+    ///
+    /// Fails if they do not match, keeping the current certificate.
+    pub fn set_pem(&self, cert_chain: Vec<u8>, private_key: Vec<u8>) -> io::Result<()> {
+        let source = TlsSource::Pem {
+            key: private_key,
+            cert: cert_chain,
+        };
+        let certificate = source
+            .load()?
+            .certificate(read_lock(&self.0.client_ca).as_deref())?;
+
+        *write_lock(&self.0.source) = Some(source);
+        *write_lock(&self.0.certificate) = Some(certificate);
+        *write_lock(&self.0.modified) = None;
+        Ok(())
+    }
+
+    /// Reload the certificate if its files changed since it was last loaded.
+    pub(crate) fn reload_if_modified(&self) {
+        let modified = match *read_lock(&self.0.source) {
+            Some(ref source) => source.modified(),
+            None => return,
+        };
+        if modified.is_none() || modified == *read_lock(&self.0.modified) {
+            return;
+        }
+
+        match self.reload() {
+            Ok(()) => info!("Reloaded the TLS certificate"),
+            Err(err) => warn!("Keeping the current TLS certificate: {}", err),
+        }
+    }
+
+    /// Build an OpenSSL acceptor serving the current certificate.
+    #[cfg(feature = "ssl")]
+    pub(crate) fn openssl_acceptor(&self) -> io::Result<openssl::ssl::SslAcceptorBuilder> {
+        use openssl::ssl::SniError;
+
+        let source = read_lock(&self.0.source)
+            .clone()
+            .ok_or_else(|| invalid("TLS is not configured".to_owned()))?;
+        let mut builder = source
+            .load()?
+            .acceptor(read_lock(&self.0.client_ca).as_deref())?;
+
+        // Swap in the current certificate for each connection, so reloads apply without
+        // restarting the listeners. OpenSSL calls this even if the client sends no server name.
+        let state = self.0.clone();
+        builder.set_servername_callback(move |ssl, _| {
+            if let Some(ref context) = *read_lock(&state.certificate) {
+                ssl.set_ssl_context(context)
+                    .map_err(|_| SniError::ALERT_FATAL)?;
+            }
+            Ok(())
+        });
+
+        Ok(builder)
+    }
+
+    /// Build a rustls configuration serving the current certificate.
+    #[cfg(all(feature = "rustls", not(feature = "ssl")))]
+    pub(crate) fn rustls_config(&self) -> io::Result<rustls::ServerConfig> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::CertificateDer;
+        use rustls::server::WebPkiClientVerifier;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| invalid(format!("Invalid TLS configuration: {}", err)))?;
+
+        let builder = match *read_lock(&self.0.client_ca) {
+            Some(ref client_ca) => {
+                let mut roots = rustls::RootCertStore::empty();
+                for ca in CertificateDer::pem_slice_iter(client_ca) {
+                    let ca =
+                        ca.map_err(|err| invalid(format!("Invalid TLS client CA: {}", err)))?;
+                    roots
+                        .add(ca)
+                        .map_err(|err| invalid(format!("Invalid TLS client CA: {}", err)))?;
+                }
+
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .map_err(|err| invalid(format!("Invalid TLS client CA: {}", err)))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(builder.with_cert_resolver(Arc::new(CertificateResolver(self.clone()))))
+    }
+}
+
+/// Resolves the certificate swapped in by the last reload.
+#[cfg(all(feature = "rustls", not(feature = "ssl")))]
+#[derive(Debug)]
+struct CertificateResolver(TlsHandle);
+
+#[cfg(all(feature = "rustls", not(feature = "ssl")))]
+impl rustls::server::ResolvesServerCert for CertificateResolver {
+    fn resolve(
+        &self,
+        _client_hello: rustls::server::ClientHello<'_>,
+    ) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        read_lock(&(self.0).0.certificate).clone()
+    }
+}
+
+/// Identity of a client, from the certificate it presented when mutual TLS is enabled.
+///
+/// Handlers can take it as an argument, which answers 401 Unauthorized to clients without a
+/// certificate, or look it up with `HttpRequest::conn_data()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientIdentity {
+    subject: String,
+    common_name: Option<String>,
+    certificate: Vec<u8>,
+}
+
+impl ClientIdentity {
+    /// Read the identity from a DER-encoded certificate.
+    pub fn from_der(certificate: &[u8]) -> Option<Self> {
+        let (_, parsed) = x509_parser::parse_x509_certificate(certificate).ok()?;
+        let subject = parsed.subject();
+        let common_name = subject
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(|cn| cn.to_owned());
+
+        Some(ClientIdentity {
+            subject: subject.to_string(),
+            common_name,
+            certificate: certificate.to_vec(),
+        })
+    }
+
+    /// Get the distinguished name of the subject, i.e. `CN=sensor-1, O=Example`.
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Get the common name of the subject, if any.
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    /// Get the DER-encoded certificate.
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    /// Read the identity of the client of a TLS connection, for `HttpServer::on_connect()`.
+    pub(crate) fn from_connection(connection: &dyn Any) -> Option<Self> {
+        let stream = connection.downcast_ref::<Stream>()?;
+
+        #[cfg(feature = "ssl")]
+        let certificate = stream.ssl().peer_certificate()?.to_der().ok()?;
+        #[cfg(all(feature = "rustls", not(feature = "ssl")))]
+        let certificate = stream.get_ref().1.peer_certificates()?.first()?.to_vec();
+
+        Self::from_der(&certificate)
+    }
+}
+
+impl FromRequest for ClientIdentity {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.conn_data::<ClientIdentity>()
+                .cloned()
+                .ok_or_else(|| error::ErrorUnauthorized("No client certificate")),
+        )
+    }
+}
@@ -1,14 +1,61 @@
-use chrono::Utc;
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 #[cfg(feature = "actix")]
 use std::{collections::HashSet, net::IpAddr};
 
+use super::clock::{Clock, SystemClock};
+
 /// Get the current time.
 ///
 /// Returns the current time in the form YYYY-mm-ddTHH:MM:SS+00:00
 pub fn timestamp() -> String {
-    let now = Utc::now();
-    now.format("%Y-%m-%dT%H:%M:%S+00:00").to_string()
+    SystemClock::new().timestamp()
+}
+
+/// Acquire a read lock.
+///
+/// If a previous holder of the lock panicked, the poison is cleared and the data is returned as
+/// is, so a single failing callback does not take the thing offline.
+pub fn read_lock<T: ?Sized>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| {
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Acquire a write lock.
+///
+/// If a previous holder of the lock panicked, the poison is cleared and the data is returned as
+/// is, so a single failing callback does not take the thing offline.
+pub fn write_lock<T: ?Sized>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|poisoned| {
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Acquire a mutex.
+///
+/// If a previous holder of the mutex panicked, the poison is cleared and the data is returned as
+/// is.
+pub fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Determine whether a path segment can be used in hrefs as is.
+///
+/// Only unreserved URL characters are allowed, and `.` and `..` are refused as they are
+/// resolved as relative paths.
+pub(crate) fn is_valid_path_segment(path: &str) -> bool {
+    !path.is_empty()
+        && path != "."
+        && path != ".."
+        && path
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b))
 }
 
 /// Get all IP addresses
@@ -19,13 +66,29 @@ pub fn get_addresses() -> Vec<String> {
     for iface in if_addrs::get_if_addrs().unwrap() {
         match iface.ip() {
             IpAddr::V4(addr) => addresses.insert(addr.to_string()),
-            IpAddr::V6(addr) => addresses.insert(format!("[{}]", addr.to_string())),
+            IpAddr::V6(addr) => addresses.insert(format!("[{}]", addr)),
         };
     }
 
     let mut results = Vec::with_capacity(addresses.len());
-    results.extend(addresses.into_iter());
+    results.extend(addresses);
     results.sort_unstable();
 
     results
 }
+
+/// Get the IP addresses of all network interfaces, along with the interface names.
+#[cfg(feature = "actix")]
+pub fn get_interface_addresses() -> Vec<(String, IpAddr)> {
+    if_addrs::get_if_addrs()
+        .map(|ifaces| {
+            ifaces
+                .into_iter()
+                .map(|iface| {
+                    let ip = iface.ip();
+                    (iface.name, ip)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,153 @@
+use serde_json::json;
+use webthing::capability::validate;
+
+const CONTEXT: &str = "https://webthings.io/schemas";
+
+/// Validate a description, returning the problems found as strings.
+fn problems(description: serde_json::Value) -> Vec<String> {
+    match validate(description.as_object().unwrap()) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+    }
+}
+
+fn light(brightness: serde_json::Value) -> serde_json::Value {
+    json!({
+        "@context": CONTEXT,
+        "@type": ["OnOffSwitch", "Light"],
+        "properties": {
+            "on": {"@type": "OnOffProperty", "type": "boolean"},
+            "brightness": brightness,
+        },
+    })
+}
+
+#[test]
+fn accepts_satisfied_capabilities() {
+    let brightness = json!({
+        "@type": "BrightnessProperty",
+        "type": "integer",
+        "minimum": 10,
+        "maximum": 90,
+    });
+    assert_eq!(problems(light(brightness)), Vec::<String>::new());
+}
+
+#[test]
+fn checks_fixed_ranges() {
+    for (brightness, expected) in [
+        (
+            json!({"@type": "BrightnessProperty", "type": "integer"}),
+            vec![
+                "/properties/brightness: BrightnessProperty must have a minimum of at least 0",
+                "/properties/brightness: BrightnessProperty must have a maximum of at most 100",
+            ],
+        ),
+        (
+            json!({"@type": "BrightnessProperty", "type": "integer", "minimum": 0}),
+            vec!["/properties/brightness: BrightnessProperty must have a maximum of at most 100"],
+        ),
+        (
+            json!({
+                "@type": "BrightnessProperty",
+                "type": "integer",
+                "minimum": -1,
+                "maximum": 255,
+            }),
+            vec![
+                "/properties/brightness: BrightnessProperty must have a minimum of at least 0",
+                "/properties/brightness: BrightnessProperty must have a maximum of at most 100",
+            ],
+        ),
+    ] {
+        assert_eq!(problems(light(brightness)), expected);
+    }
+}
+
+#[test]
+fn checks_property_types_and_values() {
+    let brightness = json!({
+        "@type": "BrightnessProperty",
+        "type": "number",
+        "minimum": 0,
+        "maximum": 100,
+    });
+    assert_eq!(
+        problems(light(brightness)),
+        ["/properties/brightness: BrightnessProperty must be of type integer"]
+    );
+
+    let description = json!({
+        "@context": CONTEXT,
+        "properties": {
+            "mode": {"@type": "ColorModeProperty", "type": "string", "enum": ["color", "rgb"]},
+        },
+    });
+    assert_eq!(
+        problems(description),
+        [concat!(
+            "/properties/mode: ColorModeProperty does not allow the value \"rgb\", ",
+            "expected one of color, temperature"
+        )]
+    );
+}
+
+#[test]
+fn checks_required_properties() {
+    let description = json!({
+        "@context": CONTEXT,
+        "@type": ["OnOffSwitch"],
+        "properties": {"on": {"type": "boolean"}},
+    });
+    assert_eq!(
+        problems(description),
+        ["/properties: OnOffSwitch requires a property with @type OnOffProperty"]
+    );
+}
+
+#[test]
+fn rejects_unknown_types() {
+    let description = json!({
+        "@context": CONTEXT,
+        "@type": ["Lightbulb"],
+        "properties": {"on": {"@type": "OnProperty", "type": "boolean"}},
+        "actions": {"fade": {"@type": "DimAction"}},
+        "events": {"hot": {"@type": "HotEvent"}},
+    });
+    assert_eq!(
+        problems(description),
+        [
+            "/properties/on/@type: Unknown property type OnProperty",
+            "/actions/fade/@type: Unknown action type DimAction",
+            "/events/hot/@type: Unknown event type HotEvent",
+            "/@type: Unknown capability Lightbulb",
+        ]
+    );
+}
+
+#[test]
+fn follows_the_context() {
+    // Prefixed terms are only checked when the prefix refers to the WebThings schemas.
+    let description = json!({
+        "@context": ["https://www.w3.org/2022/wot/td/v1.1", {"wt": CONTEXT}],
+        "@type": ["wt:Light", "saref:Lightbulb"],
+        "properties": {
+            "brightness": {"@type": "wt:BrightnessProperty", "type": "integer"},
+        },
+    });
+    assert_eq!(
+        problems(description),
+        [
+            "/properties/brightness: BrightnessProperty must have a minimum of at least 0",
+            "/properties/brightness: BrightnessProperty must have a maximum of at most 100",
+            "/properties: Light requires a property with @type OnOffProperty",
+        ]
+    );
+
+    let description = json!({
+        "@context": "https://example.com/vocabulary",
+        "@type": ["Lightbulb"],
+        "properties": {"brightness": {"@type": "BrightnessProperty", "type": "string"}},
+    });
+    assert_eq!(problems(description), Vec::<String>::new());
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::App;
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+use webthing::{BaseProperty, BaseThing, Thing, ThingsType, WebThingServer};
+
+/// A power strip with two outlets, the first of which has a USB port of its own.
+fn make_strip() -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut strip = BaseThing::new(
+        "urn:dev:ops:strip-1234".to_owned(),
+        "Power Strip".to_owned(),
+        None,
+        None,
+    );
+
+    for (n, path) in ["outlet-1", "outlet-2"].iter().enumerate() {
+        let mut outlet = BaseThing::new(
+            format!("urn:dev:ops:strip-1234:{}", path),
+            format!("Outlet {}", n + 1),
+            None,
+            None,
+        );
+        let on = json!({"type": "boolean"});
+        outlet.add_property(Box::new(BaseProperty::new(
+            "on".to_owned(),
+            json!(false),
+            None,
+            on.as_object().cloned(),
+        )));
+        if n == 0 {
+            outlet
+                .add_child("usb".to_owned(), common::make_lamp())
+                .unwrap();
+        }
+
+        strip
+            .add_child(path.to_string(), Arc::new(RwLock::new(Box::new(outlet))))
+            .unwrap();
+    }
+
+    Arc::new(RwLock::new(Box::new(strip)))
+}
+
+/// Get the hrefs of the links with a relation.
+fn links(thing: &serde_json::Value, rel: &str) -> Vec<String> {
+    thing["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|link| link["rel"] == rel)
+        .map(|link| link["href"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[actix_rt::test]
+async fn serves_nested_children() {
+    let mut server = common::single_server(make_strip());
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let req = TestRequest::get().uri("/").to_request();
+    let strip: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(
+        links(&strip, "item"),
+        ["/things/outlet-1", "/things/outlet-2"]
+    );
+    assert!(links(&strip, "collection").is_empty());
+
+    let req = TestRequest::get().uri("/things/outlet-1").to_request();
+    let outlet: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(outlet["title"], "Outlet 1");
+    assert_eq!(links(&outlet, "item"), ["/things/outlet-1/things/usb"]);
+    assert_eq!(links(&outlet, "collection"), ["/"]);
+
+    let req = TestRequest::get()
+        .uri("/things/outlet-1/things/usb")
+        .to_request();
+    let usb: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(usb["title"], "My Lamp");
+    assert_eq!(links(&usb, "collection"), ["/things/outlet-1"]);
+    assert_eq!(
+        links(&usb["properties"]["brightness"], "property"),
+        ["/things/outlet-1/things/usb/properties/brightness"]
+    );
+
+    let req = TestRequest::put()
+        .uri("/things/outlet-2/properties/on")
+        .set_json(json!({"on": true}))
+        .to_request();
+    assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+    let req = TestRequest::get()
+        .uri("/things/outlet-2/properties/on")
+        .to_request();
+    let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(body, json!({"on": true}));
+
+    let req = TestRequest::get()
+        .uri("/things/outlet-1/things/usb/properties/brightness")
+        .to_request();
+    let body: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(body, json!({"brightness": 50}));
+}
+
+#[test]
+fn rejects_unsafe_child_paths() {
+    let mut strip = BaseThing::new(
+        "urn:dev:ops:strip-1234".to_owned(),
+        "Power Strip".to_owned(),
+        None,
+        None,
+    );
+
+    for path in ["", ".", "..", "a/b", "outlet 1", "50%", "outlet?1"] {
+        assert_eq!(
+            strip.add_child(path.to_owned(), common::make_lamp()),
+            Err("Invalid path segment"),
+            "{:?}",
+            path
+        );
+    }
+    assert!(strip.get_children().is_empty());
+
+    strip
+        .add_child("outlet-1.a_b~c".to_owned(), common::make_lamp())
+        .unwrap();
+    assert_eq!(
+        strip.add_child("outlet-1.a_b~c".to_owned(), common::make_lamp()),
+        Err("Path segment already in use")
+    );
+}
+
+#[actix_rt::test]
+async fn unknown_children_are_not_found() {
+    let mut server = common::single_server(make_strip());
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    for uri in [
+        "/things/outlet-3",
+        "/things/outlet-2/things/usb",
+        "/things/outlet-1/things/usb/things/usb",
+        "/things/outlet-3/properties/on",
+        "/things/outlet-1/properties/missing",
+    ] {
+        let req = TestRequest::get().uri(uri).to_request();
+        assert_eq!(
+            call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND,
+            "{}",
+            uri
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn serves_children_of_multiple_things() {
+    let mut server = WebThingServer::new(
+        ThingsType::Multiple(vec![make_strip()], "Things".to_owned()),
+        None,
+        None,
+        None,
+        Box::new(common::Generator),
+        None,
+        Some(true),
+    );
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let req = TestRequest::get().uri("/strip-1234").to_request();
+    let strip: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(
+        links(&strip, "item"),
+        ["/strip-1234/things/outlet-1", "/strip-1234/things/outlet-2"]
+    );
+
+    let req = TestRequest::get()
+        .uri("/strip-1234/things/outlet-1/things/usb")
+        .to_request();
+    let usb: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(usb["title"], "My Lamp");
+    assert_eq!(links(&usb, "collection"), ["/strip-1234/things/outlet-1"]);
+
+    let req = TestRequest::get()
+        .uri("/strip-1234/things/outlet-1")
+        .to_request();
+    let outlet: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(links(&outlet, "collection"), ["/strip-1234"]);
+
+    for uri in ["/strip-1234/things/outlet-3", "/lamp/things/outlet-1"] {
+        let req = TestRequest::get().uri(uri).to_request();
+        assert_eq!(
+            call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND,
+            "{}",
+            uri
+        );
+    }
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use common::{make_lamp, serve, single_server, Generator};
+use futures::StreamExt;
+use serde_json::json;
+use std::time::Duration;
+use webthing::client::{ClientError, Notification, ThingClient};
+use webthing::{BaseEvent, DescriptionFormat, ThingsType, WebThingServer};
+
+async fn next(observation: &mut webthing::client::Observation) -> Notification {
+    tokio::time::timeout(Duration::from_secs(5), observation.next())
+        .await
+        .expect("timed out waiting for a notification")
+        .expect("observation ended")
+}
+
+#[actix_rt::test]
+async fn reads_and_writes_properties() {
+    for format in [DescriptionFormat::WebThings, DescriptionFormat::Td11] {
+        let mut server = single_server(make_lamp()).with_description_format(format);
+        let (url, handle) = serve(&mut server);
+
+        let client = ThingClient::connect(&url).await.unwrap();
+        assert_eq!(client.title(), Some("My Lamp"));
+        assert_eq!(client.read_property("on").await.unwrap(), json!(true));
+
+        assert_eq!(
+            client
+                .write_property("brightness", json!(20))
+                .await
+                .unwrap(),
+            json!(20)
+        );
+        let properties = client.read_properties().await.unwrap();
+        assert_eq!(properties.get("brightness"), Some(&json!(20)));
+
+        match client.write_property("brightness", json!(200)).await {
+            Err(ClientError::Status(400)) => (),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        match client.read_property("color").await {
+            Err(ClientError::Unknown(name)) => assert_eq!(name, "color"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        handle.stop(false).await;
+    }
+}
+
+#[actix_rt::test]
+async fn requests_and_cancels_actions() {
+    let mut server = single_server(make_lamp());
+    let (url, handle) = serve(&mut server);
+    let client = ThingClient::connect(&url).await.unwrap();
+
+    let request = client
+        .request_action("fade", Some(json!({"level": 10})))
+        .await
+        .unwrap();
+    assert_eq!(request.name, "fade");
+    assert_eq!(request.input, Some(json!({"level": 10})));
+
+    let fetched = client.get_action("fade", &request.id).await.unwrap();
+    assert_eq!(fetched.id, request.id);
+    assert_eq!(fetched.status, "pending");
+
+    let listed = client.list_actions(None).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(client.list_actions(Some("fade")).await.unwrap(), listed);
+
+    client.cancel_action("fade", &request.id).await.unwrap();
+    match client.get_action("fade", &request.id).await {
+        Err(ClientError::Status(404)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn observes_properties_and_events() {
+    let thing = make_lamp();
+    let mut server = single_server(thing.clone());
+    let (url, handle) = serve(&mut server);
+    let client = ThingClient::connect(&url).await.unwrap();
+
+    let mut observation = client.observe(&["overheated"]).unwrap();
+    assert_eq!(next(&mut observation).await, Notification::Connected);
+
+    client.write_property("on", json!(false)).await.unwrap();
+    let mut expected = serde_json::Map::new();
+    expected.insert("on".to_owned(), json!(false));
+    assert_eq!(
+        next(&mut observation).await,
+        Notification::PropertyStatus(expected)
+    );
+
+    thing.write().unwrap().add_event(Box::new(BaseEvent::new(
+        "overheated".to_owned(),
+        Some(json!(102)),
+    )));
+    match next(&mut observation).await {
+        Notification::Event(event) => {
+            assert_eq!(event.name, "overheated");
+            assert_eq!(event.data, Some(json!(102)));
+        }
+        other => panic!("unexpected notification: {:?}", other),
+    }
+
+    let events = client.list_events(Some("overheated")).await.unwrap();
+    assert_eq!(events.len(), 1);
+
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn reconnects_websocket() {
+    let thing = make_lamp();
+    let mut server = WebThingServer::new(
+        ThingsType::Multiple(vec![thing.clone()], "Lamps".to_owned()),
+        None,
+        None,
+        None,
+        Box::new(Generator),
+        None,
+        Some(true),
+    );
+    let things = server.things_handle().unwrap();
+    let (url, handle) = serve(&mut server);
+
+    let clients = ThingClient::connect_all(&url).await.unwrap();
+    assert_eq!(clients.len(), 1);
+    let client = clients[0]
+        .clone()
+        .with_reconnect_delay(Duration::from_millis(50));
+
+    let mut observation = client.observe(&[]).unwrap();
+    assert_eq!(next(&mut observation).await, Notification::Connected);
+
+    // Removing the thing closes its WebSockets; serving it again lets the client reconnect.
+    assert!(things.remove_thing("my-lamp-1234").is_some());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    things.add_thing_at("my-lamp-1234", thing).unwrap();
+
+    assert_eq!(next(&mut observation).await, Notification::Connected);
+    client.write_property("on", json!(false)).await.unwrap();
+    assert!(matches!(
+        next(&mut observation).await,
+        Notification::PropertyStatus(_)
+    ));
+
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn requests_the_subprotocol_named_in_forms() {
+    for format in [DescriptionFormat::WebThings, DescriptionFormat::Td11] {
+        let mut server = single_server(make_lamp()).with_description_format(format);
+        let (url, handle) = serve(&mut server);
+        let client = ThingClient::connect(&url).await.unwrap();
+
+        let mut observation = client.observe(&[]).unwrap();
+        assert_eq!(next(&mut observation).await, Notification::Connected);
+
+        handle.stop(false).await;
+    }
+}
+
+#[actix_rt::test]
+async fn rejected_handshakes_end_the_observation() {
+    let mut server = single_server(make_lamp()).with_description_format(DescriptionFormat::Td11);
+    let (url, handle) = serve(&mut server);
+
+    // The server won't agree to a subprotocol it doesn't speak, which retrying can't fix.
+    let mut description = ThingClient::connect(&url)
+        .await
+        .unwrap()
+        .description()
+        .clone();
+    for form in description["forms"].as_array_mut().unwrap() {
+        if form.get("subprotocol").is_some() {
+            form["subprotocol"] = json!("bogus");
+        }
+    }
+    let client = ThingClient::from_description(&url, description)
+        .unwrap()
+        .with_reconnect_delay(Duration::from_millis(50));
+
+    let mut observation = client.observe(&[]).unwrap();
+    assert!(matches!(
+        next(&mut observation).await,
+        Notification::Rejected(_)
+    ));
+    let end = tokio::time::timeout(Duration::from_secs(5), observation.next()).await;
+    assert_eq!(end.unwrap(), None);
+
+    handle.stop(false).await;
+}
@@ -0,0 +1,87 @@
+use chrono::{TimeZone, Utc};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use webthing::{
+    Action, BaseAction, BaseEvent, BaseThing, Clock, Event, ManualClock, SystemClock, Thing,
+    TimestampPrecision,
+};
+
+fn make_thing(clock: Arc<ManualClock>) -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:clock".to_owned(),
+        "Clock".to_owned(),
+        None,
+        None,
+    )
+    .with_clock(clock);
+    thing.add_available_action("tick".to_owned(), serde_json::Map::new());
+    thing.add_available_event("ticked".to_owned(), serde_json::Map::new());
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+#[test]
+fn timestamps_actions_and_events_with_the_things_clock() {
+    let start = Utc.with_ymd_and_hms(2022, 3, 7, 12, 0, 0).unwrap();
+    let clock = Arc::new(ManualClock::new(start));
+    let thing = make_thing(clock.clone());
+
+    let action: Arc<RwLock<Box<dyn Action>>> = Arc::new(RwLock::new(Box::new(BaseAction::new(
+        "1".to_owned(),
+        "tick".to_owned(),
+        None,
+        Arc::downgrade(&thing),
+    ))));
+    thing
+        .write()
+        .unwrap()
+        .add_action(action.clone(), None)
+        .unwrap();
+    assert_eq!(
+        action.read().unwrap().get_time_requested(),
+        "2022-03-07T12:00:00+00:00"
+    );
+
+    clock.advance(Duration::from_secs(90));
+    thing
+        .write()
+        .unwrap()
+        .finish_action("tick".to_owned(), "1".to_owned());
+    assert_eq!(
+        action.read().unwrap().get_time_completed().as_deref(),
+        Some("2022-03-07T12:01:30+00:00")
+    );
+
+    clock.set(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    thing
+        .write()
+        .unwrap()
+        .add_event(Box::new(BaseEvent::new("ticked".to_owned(), None)));
+    let events = thing.read().unwrap().get_event_descriptions(None);
+    assert_eq!(
+        events[0]["ticked"]["timestamp"],
+        "2023-01-01T00:00:00+00:00"
+    );
+}
+
+#[test]
+fn formats_milliseconds() {
+    let start = Utc.with_ymd_and_hms(2022, 3, 7, 12, 0, 0).unwrap();
+    let clock = ManualClock::new(start).with_precision(TimestampPrecision::Milliseconds);
+    clock.advance(Duration::from_millis(250));
+    assert_eq!(clock.timestamp(), "2022-03-07T12:00:00.250+00:00");
+
+    let mut event = BaseEvent::new("ticked".to_owned(), None);
+    event.set_clock(Arc::new(clock));
+    assert_eq!(event.get_time(), "2022-03-07T12:00:00.250+00:00");
+
+    let system = SystemClock::new().with_precision(TimestampPrecision::Milliseconds);
+    assert_eq!(
+        system.timestamp().len(),
+        "2022-03-07T12:00:00.250+00:00".len()
+    );
+    assert_eq!(
+        SystemClock::new().timestamp().len(),
+        "2022-03-07T12:00:00+00:00".len()
+    );
+}
@@ -0,0 +1,91 @@
+#![allow(dead_code)]
+
+use actix_web::dev::ServerHandle;
+use actix_web::{App, HttpServer};
+use serde_json::json;
+use std::sync::{Arc, RwLock, Weak};
+use uuid::Uuid;
+use webthing::server::ActionGenerator;
+use webthing::{Action, BaseAction, BaseProperty, BaseThing, Thing, ThingsType, WebThingServer};
+
+/// Generates actions which stay pending until they are finished by the test.
+pub struct Generator;
+
+impl ActionGenerator for Generator {
+    fn generate(
+        &self,
+        thing: Weak<RwLock<Box<dyn Thing>>>,
+        name: String,
+        input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+        let input = input.and_then(|i| i.as_object()).cloned();
+        Some(Box::new(BaseAction::new(
+            Uuid::new_v4().to_string(),
+            name,
+            input,
+            thing,
+        )))
+    }
+}
+
+pub fn make_lamp() -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:my-lamp-1234".to_owned(),
+        "My Lamp".to_owned(),
+        Some(vec!["OnOffSwitch".to_owned(), "Light".to_owned()]),
+        None,
+    );
+
+    let on = json!({"@type": "OnOffProperty", "type": "boolean"});
+    thing.add_property(Box::new(BaseProperty::new(
+        "on".to_owned(),
+        json!(true),
+        None,
+        on.as_object().cloned(),
+    )));
+
+    let brightness = json!({"type": "integer", "minimum": 0, "maximum": 100});
+    thing.add_property(Box::new(BaseProperty::new(
+        "brightness".to_owned(),
+        json!(50),
+        None,
+        brightness.as_object().cloned(),
+    )));
+
+    let fade = json!({"input": {"type": "object"}});
+    thing.add_available_action("fade".to_owned(), fade.as_object().cloned().unwrap());
+
+    let overheated = json!({"type": "number"});
+    thing.add_available_event(
+        "overheated".to_owned(),
+        overheated.as_object().cloned().unwrap(),
+    );
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+/// Serve things on an ephemeral port, returning the server's URL.
+pub fn serve(server: &mut WebThingServer) -> (String, ServerHandle) {
+    let config = server.make_config();
+    let http = HttpServer::new(move || App::new().configure(config.clone()))
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let url = format!("http://{}/", http.addrs()[0]);
+    let http = http.run();
+    let handle = http.handle();
+    actix_rt::spawn(http);
+    (url, handle)
+}
+
+pub fn single_server(thing: Arc<RwLock<Box<dyn Thing>>>) -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Single(thing),
+        None,
+        None,
+        None,
+        Box::new(Generator),
+        None,
+        Some(true),
+    )
+}
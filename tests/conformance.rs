@@ -0,0 +1,779 @@
+//! Conformance tests for the Web Thing REST and WebSocket APIs, covering the checks of the
+//! WebThings `webthing-tester` against both single and multiple-things servers.
+
+mod common;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, App};
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+use std::{thread, time};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+use webthing::description::WEBSOCKET_SUBPROTOCOL;
+use webthing::server::{ActionGenerator, HostValidator};
+use webthing::{
+    Action, BaseAction, BaseEvent, BaseProperty, BaseThing, Thing, ThingsType, WebThingServer,
+};
+
+const PORT: u16 = 8888;
+
+const HOST: &str = "localhost:8888";
+
+/// Fades the lamp to a brightness, then reports that it overheated.
+struct FadeAction(BaseAction);
+
+impl Action for FadeAction {
+    fn set_href_prefix(&mut self, prefix: String) {
+        self.0.set_href_prefix(prefix)
+    }
+
+    fn get_id(&self) -> String {
+        self.0.get_id()
+    }
+
+    fn get_name(&self) -> String {
+        self.0.get_name()
+    }
+
+    fn get_href(&self) -> String {
+        self.0.get_href()
+    }
+
+    fn get_status(&self) -> String {
+        self.0.get_status()
+    }
+
+    fn get_time_requested(&self) -> String {
+        self.0.get_time_requested()
+    }
+
+    fn get_time_completed(&self) -> Option<String> {
+        self.0.get_time_completed()
+    }
+
+    fn get_input(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        self.0.get_input()
+    }
+
+    fn get_thing(&self) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.0.get_thing()
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.0.set_status(status)
+    }
+
+    fn start(&mut self) {
+        self.0.start()
+    }
+
+    fn perform_action(&mut self) {
+        let thing = match self.get_thing() {
+            Some(thing) => thing,
+            None => return,
+        };
+        let input = self.get_input().unwrap();
+        let name = self.get_name();
+        let id = self.get_id();
+
+        thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(
+                input.get("duration").unwrap().as_u64().unwrap(),
+            ));
+
+            let mut thing = thing.write().unwrap();
+            let _ = thing.set_property(
+                "brightness".to_owned(),
+                input.get("brightness").unwrap().clone(),
+            );
+            thing.add_event(Box::new(BaseEvent::new(
+                "overheated".to_owned(),
+                Some(json!(102)),
+            )));
+            thing.finish_action(name, id);
+        });
+    }
+
+    fn cancel(&mut self) {
+        self.0.cancel()
+    }
+
+    fn finish(&mut self) {
+        self.0.finish()
+    }
+}
+
+struct Generator;
+
+impl ActionGenerator for Generator {
+    fn generate(
+        &self,
+        thing: Weak<RwLock<Box<dyn Thing>>>,
+        name: String,
+        input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+        let input = input.and_then(|i| i.as_object()).cloned();
+        match name.as_str() {
+            "fade" => Some(Box::new(FadeAction(BaseAction::new(
+                Uuid::new_v4().to_string(),
+                name,
+                input,
+                thing,
+            )))),
+            _ => None,
+        }
+    }
+}
+
+/// The lamp of the single-thing example.
+fn make_thing() -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:my-lamp-1234".to_owned(),
+        "My Lamp".to_owned(),
+        Some(vec!["OnOffSwitch".to_owned(), "Light".to_owned()]),
+        Some("A web connected lamp".to_owned()),
+    );
+
+    let on = json!({
+        "@type": "OnOffProperty",
+        "title": "On/Off",
+        "type": "boolean",
+        "description": "Whether the lamp is turned on"
+    });
+    thing.add_property(Box::new(BaseProperty::new(
+        "on".to_owned(),
+        json!(true),
+        None,
+        on.as_object().cloned(),
+    )));
+
+    let brightness = json!({
+        "@type": "BrightnessProperty",
+        "title": "Brightness",
+        "type": "integer",
+        "description": "The level of light from 0-100",
+        "minimum": 0,
+        "maximum": 100,
+        "unit": "percent"
+    });
+    thing.add_property(Box::new(BaseProperty::new(
+        "brightness".to_owned(),
+        json!(50),
+        None,
+        brightness.as_object().cloned(),
+    )));
+
+    let fade = json!({
+        "title": "Fade",
+        "description": "Fade the lamp to a given level",
+        "input": {
+            "type": "object",
+            "required": ["brightness", "duration"],
+            "properties": {
+                "brightness": {"type": "integer", "minimum": 0, "maximum": 100, "unit": "percent"},
+                "duration": {"type": "integer", "minimum": 1, "unit": "milliseconds"}
+            }
+        }
+    });
+    thing.add_available_action("fade".to_owned(), fade.as_object().cloned().unwrap());
+
+    let overheated = json!({
+        "description": "The lamp has exceeded its safe operating temperature",
+        "type": "number",
+        "unit": "degree celsius"
+    });
+    thing.add_available_event(
+        "overheated".to_owned(),
+        overheated.as_object().cloned().unwrap(),
+    );
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+/// How the lamp is served.
+#[derive(Clone, Copy, Debug)]
+enum Mode {
+    Single,
+    Multiple,
+}
+
+const MODES: [Mode; 2] = [Mode::Single, Mode::Multiple];
+
+impl Mode {
+    /// Path prefix of the lamp's resources.
+    fn prefix(self) -> &'static str {
+        match self {
+            Mode::Single => "",
+            Mode::Multiple => "/my-lamp-1234",
+        }
+    }
+
+    /// Path of the lamp's description.
+    fn root(self) -> &'static str {
+        match self {
+            Mode::Single => "/",
+            Mode::Multiple => "/my-lamp-1234",
+        }
+    }
+
+    fn server(self, disable_host_validation: bool) -> WebThingServer {
+        let thing = make_thing();
+        let things = match self {
+            Mode::Single => ThingsType::Single(thing),
+            Mode::Multiple => ThingsType::Multiple(vec![thing], "Lamps".to_owned()),
+        };
+
+        WebThingServer::new(
+            things,
+            Some(PORT),
+            None,
+            None,
+            Box::new(Generator),
+            None,
+            Some(disable_host_validation),
+        )
+    }
+}
+
+macro_rules! init_app {
+    ($server:expr) => {{
+        let config = $server.make_config();
+        test::init_service(App::new().wrap(HostValidator).configure(config)).await
+    }};
+}
+
+/// Send a request with a valid Host header, returning the status and JSON body, if any.
+async fn request<S, B>(
+    app: &S,
+    method: Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let mut req = test::TestRequest::default()
+        .method(method)
+        .uri(path)
+        .insert_header(("Host", HOST));
+    if let Some(body) = body {
+        req = req.set_json(body);
+    }
+
+    let response = test::call_service(app, req.to_request()).await;
+    let status = response.status();
+    let body = test::read_body(response).await;
+    let body = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(&body).unwrap()
+    };
+
+    (status, body)
+}
+
+async fn get<S, B>(app: &S, path: &str) -> (StatusCode, serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    request(app, Method::GET, path, None).await
+}
+
+fn is_timestamp(value: &serde_json::Value) -> bool {
+    value
+        .as_str()
+        .is_some_and(|t| chrono::DateTime::parse_from_rfc3339(t).is_ok())
+}
+
+#[actix_rt::test]
+async fn thing_description() {
+    for mode in MODES {
+        let prefix = mode.prefix();
+        let app = init_app!(mode.server(false));
+
+        let (status, description) = get(&app, mode.root()).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", mode);
+        assert_eq!(description["id"], "urn:dev:ops:my-lamp-1234");
+        assert_eq!(description["title"], "My Lamp");
+        assert_eq!(description["description"], "A web connected lamp");
+        assert_eq!(description["@context"], "https://webthings.io/schemas");
+        assert_eq!(description["@type"], json!(["OnOffSwitch", "Light"]));
+        assert_eq!(
+            description["base"],
+            format!("http://{}{}", HOST, mode.root())
+        );
+        assert_eq!(description["security"], "nosec_sc");
+        assert_eq!(
+            description["securityDefinitions"],
+            json!({"nosec_sc": {"scheme": "nosec"}})
+        );
+
+        assert_eq!(
+            description["properties"]["brightness"],
+            json!({
+                "@type": "BrightnessProperty",
+                "title": "Brightness",
+                "type": "integer",
+                "description": "The level of light from 0-100",
+                "minimum": 0,
+                "maximum": 100,
+                "unit": "percent",
+                "links": [{"rel": "property", "href": format!("{}/properties/brightness", prefix)}]
+            })
+        );
+        assert_eq!(
+            description["actions"]["fade"]["links"],
+            json!([{"rel": "action", "href": format!("{}/actions/fade", prefix)}])
+        );
+        assert_eq!(
+            description["events"]["overheated"]["links"],
+            json!([{"rel": "event", "href": format!("{}/events/overheated", prefix)}])
+        );
+
+        let links = description["links"].as_array().unwrap();
+        for rel in ["properties", "actions", "events"] {
+            assert!(
+                links.contains(&json!({"rel": rel, "href": format!("{}/{}", prefix, rel)})),
+                "missing {} link: {:?}",
+                rel,
+                links
+            );
+        }
+        assert!(links.contains(&json!({
+            "rel": "alternate",
+            "href": format!("ws://{}{}", HOST, mode.root()),
+        })));
+    }
+}
+
+#[actix_rt::test]
+async fn things_list() {
+    let app = init_app!(Mode::Multiple.server(false));
+
+    let (status, things) = get(&app, "/").await;
+    assert_eq!(status, StatusCode::OK);
+    let things = things.as_array().unwrap();
+    assert_eq!(things.len(), 1);
+    assert_eq!(things[0]["id"], "urn:dev:ops:my-lamp-1234");
+    assert_eq!(things[0]["href"], "/my-lamp-1234");
+
+    let (status, _) = get(&app, "/my-lamp-5678").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get(&app, "/my-lamp-5678/properties").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn properties() {
+    for mode in MODES {
+        let prefix = mode.prefix();
+        let app = init_app!(mode.server(false));
+        let path = |p: &str| format!("{}{}", prefix, p);
+
+        let (status, properties) = get(&app, &path("/properties")).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", mode);
+        assert_eq!(properties, json!({"on": true, "brightness": 50}));
+
+        let (status, body) = get(&app, &path("/properties/brightness")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"brightness": 50}));
+
+        let (status, body) = request(
+            &app,
+            Method::PUT,
+            &path("/properties/brightness"),
+            Some(json!({"brightness": 25})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"brightness": 25}));
+
+        let (_, body) = get(&app, &path("/properties/brightness")).await;
+        assert_eq!(body, json!({"brightness": 25}));
+
+        let (status, _) = get(&app, &path("/properties/color")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(
+            &app,
+            Method::PUT,
+            &path("/properties/color"),
+            Some(json!({"color": "#ffffff"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+#[actix_rt::test]
+async fn property_validation() {
+    for mode in MODES {
+        let app = init_app!(mode.server(false));
+        let path = format!("{}/properties/brightness", mode.prefix());
+
+        for value in [json!(101), json!(-1), json!("50"), json!(2.5), json!(null)] {
+            let (status, body) = request(
+                &app,
+                Method::PUT,
+                &path,
+                Some(json!({ "brightness": value })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{:?} {}", mode, value);
+            assert_eq!(body["messageType"], "error");
+            assert_eq!(body["data"]["status"], "400 Bad Request");
+        }
+
+        let (status, body) = request(&app, Method::PUT, &path, Some(json!({"on": false}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["data"]["message"],
+            "Request does not contain property key"
+        );
+
+        let (status, _) = request(&app, Method::PUT, &path, Some(json!([50]))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, body) = get(&app, &path).await;
+        assert_eq!(body, json!({"brightness": 50}));
+    }
+}
+
+#[actix_rt::test]
+async fn action_lifecycle() {
+    for mode in MODES {
+        let prefix = mode.prefix();
+        let app = init_app!(mode.server(false));
+        let path = |p: &str| format!("{}{}", prefix, p);
+
+        let (status, actions) = get(&app, &path("/actions")).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", mode);
+        assert_eq!(actions, json!([]));
+
+        let input = json!({"brightness": 75, "duration": 100});
+        let (status, body) = request(
+            &app,
+            Method::POST,
+            &path("/actions"),
+            Some(json!({"fade": {"input": input}})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let fade = &body["fade"];
+        assert_eq!(fade["input"], input);
+        assert_eq!(fade["status"], "created");
+        assert!(is_timestamp(&fade["timeRequested"]));
+        let href = fade["href"].as_str().unwrap().to_owned();
+        assert!(href.starts_with(&path("/actions/fade/")), "{}", href);
+
+        let (status, actions) = get(&app, &path("/actions")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(actions.as_array().unwrap().len(), 1);
+        assert_eq!(actions[0]["fade"]["href"], href);
+        assert_eq!(actions[0]["fade"]["status"], "pending");
+
+        let (_, actions) = get(&app, &path("/actions/fade")).await;
+        assert_eq!(actions.as_array().unwrap().len(), 1);
+
+        let mut action = serde_json::Value::Null;
+        for _ in 0..50 {
+            action = get(&app, &href).await.1;
+            if action["fade"]["status"] == "completed" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(action["fade"]["status"], "completed");
+        assert!(is_timestamp(&action["fade"]["timeCompleted"]));
+
+        let (_, body) = get(&app, &path("/properties/brightness")).await;
+        assert_eq!(body, json!({"brightness": 75}));
+
+        let (status, events) = get(&app, &path("/events")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(events.as_array().unwrap().len(), 1);
+        assert_eq!(events[0]["overheated"]["data"], 102);
+        assert!(is_timestamp(&events[0]["overheated"]["timestamp"]));
+
+        let (status, events) = get(&app, &path("/events/overheated")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(events.as_array().unwrap().len(), 1);
+
+        let (status, _) = request(&app, Method::DELETE, &href, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = get(&app, &href).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(&app, Method::DELETE, &href, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Actions can also be requested on their own resource.
+        let (status, body) = request(
+            &app,
+            Method::POST,
+            &path("/actions/fade"),
+            Some(json!({"fade": {"input": {"brightness": 10, "duration": 1}}})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["fade"]["status"], "created");
+    }
+}
+
+#[actix_rt::test]
+async fn action_validation() {
+    for mode in MODES {
+        let app = init_app!(mode.server(false));
+        let path = |p: &str| format!("{}{}", mode.prefix(), p);
+
+        let invalid = [
+            (
+                path("/actions"),
+                json!({"fade": {"input": {"brightness": 50}}}),
+            ),
+            (
+                path("/actions"),
+                json!({"fade": {"input": {"brightness": 500, "duration": 1}}}),
+            ),
+            (path("/actions"), json!({"dim": {}})),
+            (path("/actions"), json!({"fade": {}, "dim": {}})),
+            (path("/actions"), json!("fade")),
+            (path("/actions/fade"), json!({"dim": {}})),
+            (
+                path("/actions/fade"),
+                json!({"fade": {"input": {"duration": 0, "brightness": 1}}}),
+            ),
+        ];
+        for (url, body) in invalid {
+            let (status, _) = request(&app, Method::POST, &url, Some(body.clone())).await;
+            assert_eq!(
+                status,
+                StatusCode::BAD_REQUEST,
+                "{:?} {} {}",
+                mode,
+                url,
+                body
+            );
+        }
+
+        let (_, actions) = get(&app, &path("/actions")).await;
+        assert_eq!(actions, json!([]));
+
+        let (status, _) = get(&app, &path("/actions/fade/1234")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(&app, Method::DELETE, &path("/actions/fade/1234"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+#[actix_rt::test]
+async fn host_validation() {
+    for mode in MODES {
+        let app = init_app!(mode.server(false));
+
+        for host in [
+            "localhost",
+            "localhost:8888",
+            "127.0.0.1:8888",
+            "LOCALHOST:8888",
+        ] {
+            let req = test::TestRequest::get()
+                .uri(mode.root())
+                .insert_header(("Host", host))
+                .to_request();
+            let status = test::call_service(&app, req).await.status();
+            assert_eq!(status, StatusCode::OK, "{:?} {}", mode, host);
+        }
+
+        for host in ["evil.com", "localhost:8889", "127.0.0.1:80"] {
+            let req = test::TestRequest::get()
+                .uri(mode.root())
+                .insert_header(("Host", host))
+                .to_request();
+            let status = test::call_service(&app, req).await.status();
+            assert_eq!(status, StatusCode::FORBIDDEN, "{:?} {}", mode, host);
+        }
+
+        let req = test::TestRequest::get().uri(mode.root()).to_request();
+        let status = test::call_service(&app, req).await.status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let app = init_app!(mode.server(true));
+        let req = test::TestRequest::get()
+            .uri(mode.root())
+            .insert_header(("Host", "evil.com"))
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Serve a lamp and open a WebSocket to it.
+async fn websocket(mode: Mode) -> (WebSocket, actix_web::dev::ServerHandle) {
+    let (url, handle) = common::serve(&mut mode.server(true));
+    let url = format!(
+        "{}{}",
+        url.replacen("http", "ws", 1).trim_end_matches('/'),
+        mode.root()
+    );
+
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        HeaderValue::from_static(WEBSOCKET_SUBPROTOCOL),
+    );
+    let (socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+        WEBSOCKET_SUBPROTOCOL
+    );
+
+    (socket, handle)
+}
+
+async fn send(socket: &mut WebSocket, message: serde_json::Value) {
+    socket
+        .send(Message::text(message.to_string()))
+        .await
+        .unwrap();
+}
+
+async fn receive(socket: &mut WebSocket) -> serde_json::Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for a message")
+            .expect("WebSocket closed")
+            .unwrap();
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn websocket_properties() {
+    for mode in MODES {
+        let (mut socket, handle) = websocket(mode).await;
+
+        send(
+            &mut socket,
+            json!({"messageType": "setProperty", "data": {"brightness": 10}}),
+        )
+        .await;
+        assert_eq!(
+            receive(&mut socket).await,
+            json!({"messageType": "propertyStatus", "data": {"brightness": 10}})
+        );
+
+        send(
+            &mut socket,
+            json!({"messageType": "setProperty", "data": {"brightness": 1000}}),
+        )
+        .await;
+        let error = receive(&mut socket).await;
+        assert_eq!(error["messageType"], "error", "{:?}", mode);
+        assert_eq!(error["data"]["status"], "400 Bad Request");
+        assert_eq!(
+            error["data"]["request"]["data"],
+            json!({"brightness": 1000})
+        );
+
+        handle.stop(false).await;
+    }
+}
+
+#[actix_rt::test]
+async fn websocket_actions_and_events() {
+    for mode in MODES {
+        let (mut socket, handle) = websocket(mode).await;
+
+        send(
+            &mut socket,
+            json!({"messageType": "addEventSubscription", "data": {"overheated": {}}}),
+        )
+        .await;
+        send(
+            &mut socket,
+            json!({
+                "messageType": "requestAction",
+                "data": {"fade": {"input": {"brightness": 90, "duration": 50}}}
+            }),
+        )
+        .await;
+
+        let mut statuses = Vec::new();
+        let mut brightness = None;
+        let mut event = None;
+        while statuses.last().map(String::as_str) != Some("completed") || event.is_none() {
+            let message = receive(&mut socket).await;
+            match message["messageType"].as_str().unwrap() {
+                "actionStatus" => {
+                    let fade = &message["data"]["fade"];
+                    assert!(fade["href"]
+                        .as_str()
+                        .unwrap()
+                        .starts_with(&format!("{}/actions/fade/", mode.prefix())));
+                    statuses.push(fade["status"].as_str().unwrap().to_owned());
+                }
+                "propertyStatus" => brightness = Some(message["data"]["brightness"].clone()),
+                "event" => event = Some(message["data"]["overheated"].clone()),
+                other => panic!("unexpected message type {}", other),
+            }
+        }
+        assert_eq!(statuses, ["created", "pending", "completed"], "{:?}", mode);
+        assert_eq!(brightness, Some(json!(90)));
+        let event = event.unwrap();
+        assert_eq!(event["data"], 102);
+        assert!(is_timestamp(&event["timestamp"]));
+
+        send(
+            &mut socket,
+            json!({"messageType": "requestAction", "data": {"fade": {"input": {}}}}),
+        )
+        .await;
+        let error = receive(&mut socket).await;
+        assert_eq!(error["messageType"], "error");
+        assert_eq!(error["data"]["status"], "400 Bad Request");
+
+        handle.stop(false).await;
+    }
+}
+
+#[actix_rt::test]
+async fn websocket_invalid_messages() {
+    let (mut socket, handle) = websocket(Mode::Single).await;
+
+    let invalid = [
+        ("not json".to_owned(), "Parsing request failed"),
+        ("[1, 2]".to_owned(), "Parsing request failed"),
+        (json!({"data": {}}).to_string(), "Invalid message"),
+        (
+            json!({"messageType": "setProperty", "data": 1}).to_string(),
+            "Invalid message",
+        ),
+        (
+            json!({"messageType": "getProperty", "data": {}}).to_string(),
+            "Unknown messageType: getProperty",
+        ),
+    ];
+    for (message, expected) in invalid {
+        socket.send(Message::text(message.clone())).await.unwrap();
+        let error = receive(&mut socket).await;
+        assert_eq!(error["messageType"], "error", "{}", message);
+        assert_eq!(error["data"]["status"], "400 Bad Request");
+        assert_eq!(error["data"]["message"], expected);
+    }
+
+    handle.stop(false).await;
+}
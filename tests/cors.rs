@@ -0,0 +1,181 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, TestRequest};
+use actix_web::App;
+use std::io;
+use std::time::Duration;
+use webthing::server::HostValidator;
+use webthing::{BaseActionGenerator, CorsPolicy, MdnsMode, ThingsType, WebThingServer};
+
+const HOST: &str = "localhost:8888";
+
+fn server() -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(8888),
+        None,
+        None,
+        Box::new(BaseActionGenerator),
+        None,
+        None,
+    )
+    .with_mdns(MdnsMode::Disabled)
+}
+
+fn preflight(origin: &str, method: &str, headers: &str) -> TestRequest {
+    TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/properties/on")
+        .insert_header(("Host", HOST))
+        .insert_header(("Origin", origin))
+        .insert_header(("Access-Control-Request-Method", method))
+        .insert_header(("Access-Control-Request-Headers", headers))
+}
+
+#[actix_rt::test]
+async fn default_policy_allows_any_origin() {
+    let policy = CorsPolicy::default();
+    let config = server().make_config();
+    let app = init_service(
+        App::new()
+            .wrap(HostValidator)
+            .wrap(policy.middleware())
+            .configure(config),
+    )
+    .await;
+
+    let req = TestRequest::get()
+        .uri("/")
+        .insert_header(("Host", HOST))
+        .insert_header(("Origin", "https://app.example.com"))
+        .to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("Access-Control-Allow-Origin").unwrap(),
+        "*"
+    );
+
+    let req = preflight(
+        "https://app.example.com",
+        "PUT",
+        "authorization, content-type",
+    )
+    .to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("Access-Control-Allow-Origin").unwrap(),
+        "*"
+    );
+    let methods = res
+        .headers()
+        .get("Access-Control-Allow-Methods")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(methods.contains("PUT"), "{}", methods);
+    let headers = res
+        .headers()
+        .get("Access-Control-Allow-Headers")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_lowercase();
+    assert!(headers.contains("authorization"), "{}", headers);
+    assert!(!res
+        .headers()
+        .contains_key("Access-Control-Allow-Credentials"));
+}
+
+#[actix_rt::test]
+async fn restricted_policy_checks_origins() {
+    let policy = CorsPolicy::with_origins(vec![
+        "https://app.example.com".to_owned(),
+        "https://*.lan.example.com:8443".to_owned(),
+    ])
+    .with_credentials(true)
+    .with_allowed_header("X-Api-Key")
+    .with_exposed_headers(vec!["Location".to_owned()])
+    .with_max_age(Duration::from_secs(600));
+    let config = server().make_config();
+    let app = init_service(
+        App::new()
+            .wrap(HostValidator)
+            .wrap(policy.middleware())
+            .configure(config),
+    )
+    .await;
+
+    for origin in [
+        "https://app.example.com",
+        "https://hub.lan.example.com:8443",
+        "https://a.b.lan.example.com:8443",
+    ] {
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header(("Host", HOST))
+            .insert_header(("Origin", origin))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK, "{}", origin);
+        assert_eq!(
+            res.headers().get("Access-Control-Allow-Origin").unwrap(),
+            origin
+        );
+        assert_eq!(
+            res.headers()
+                .get("Access-Control-Allow-Credentials")
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(
+            res.headers().get("Access-Control-Expose-Headers").unwrap(),
+            "location"
+        );
+    }
+
+    // Requests from other origins are served without CORS headers, so browsers block them.
+    for origin in [
+        "http://app.example.com",
+        "https://lan.example.com:8443",
+        "https://hub.lan.example.com",
+        "https://evil.com/.lan.example.com:8443",
+    ] {
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header(("Host", HOST))
+            .insert_header(("Origin", origin))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert!(
+            !res.headers().contains_key("Access-Control-Allow-Origin"),
+            "{}",
+            origin
+        );
+    }
+
+    let req = preflight("https://app.example.com", "PUT", "x-api-key").to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("Access-Control-Max-Age").unwrap(), "600");
+
+    let req = preflight("https://evil.com", "PUT", "content-type").to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn start_rejects_invalid_policies() {
+    for policy in [
+        CorsPolicy::default().with_credentials(true),
+        CorsPolicy::with_origins(vec!["app.example.com".to_owned()]),
+        CorsPolicy::with_origins(vec!["https://*.*.example.com".to_owned()]),
+    ] {
+        match server().with_cors(policy.clone()).start(None) {
+            Ok(_) => panic!("{:?} accepted", policy),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err),
+        }
+    }
+}
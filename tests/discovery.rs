@@ -0,0 +1,106 @@
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use webthing::discovery::{
+    BrowseEvent, DiscoveredThing, Discovery, DiscoveryEvent, ResolvedService,
+};
+
+fn service(name: &str, addresses: &[&str], txt: &[(&str, &str)]) -> ResolvedService {
+    ResolvedService {
+        name: format!("{}._webthing._tcp.local.", name),
+        hostname: "lamps.local.".to_owned(),
+        addresses: addresses
+            .iter()
+            .map(|a| a.parse::<IpAddr>().unwrap())
+            .collect(),
+        port: 8888,
+        txt: txt
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>(),
+    }
+}
+
+fn url(event: Option<DiscoveryEvent>) -> (bool, String) {
+    match event {
+        Some(DiscoveryEvent::Appeared(thing)) => (true, thing.url),
+        Some(DiscoveryEvent::Disappeared(thing)) => (false, thing.url),
+        None => panic!("discovery ended"),
+    }
+}
+
+#[test]
+fn resolves_urls_from_txt_records() {
+    let resolve = |addresses: &[&str], txt: &[(&str, &str)]| {
+        DiscoveredThing::from_service(service("Lamp", addresses, txt)).map(|thing| thing.url)
+    };
+
+    assert_eq!(
+        resolve(&["192.168.1.10"], &[]).as_deref(),
+        Some("http://192.168.1.10:8888/")
+    );
+    assert_eq!(
+        resolve(
+            &["fd00::10", "192.168.1.10"],
+            &[("path", "/things"), ("tls", "1")]
+        )
+        .as_deref(),
+        Some("https://192.168.1.10:8888/things")
+    );
+    assert_eq!(
+        resolve(&["fd00::10"], &[("path", "/things/lamp")]).as_deref(),
+        Some("http://[fd00::10]:8888/things/lamp")
+    );
+    assert_eq!(resolve(&[], &[]), None);
+}
+
+#[actix_rt::test]
+async fn reports_appearing_and_disappearing_things() {
+    let (responder, events) = mpsc::unbounded();
+    let mut discovery = Discovery::from_events(events);
+
+    let lamp = service("Lamp", &["192.168.1.10"], &[("path", "/")]);
+    responder
+        .unbounded_send(BrowseEvent::Resolved(lamp.clone()))
+        .unwrap();
+    assert_eq!(
+        url(discovery.next().await),
+        (true, "http://192.168.1.10:8888/".to_owned())
+    );
+
+    // Repeated announcements are not reported, but changes are.
+    responder
+        .unbounded_send(BrowseEvent::Resolved(lamp.clone()))
+        .unwrap();
+    let moved = service("Lamp", &["192.168.1.11"], &[("path", "/")]);
+    responder
+        .unbounded_send(BrowseEvent::Resolved(moved))
+        .unwrap();
+    assert_eq!(
+        url(discovery.next().await),
+        (false, "http://192.168.1.10:8888/".to_owned())
+    );
+    assert_eq!(
+        url(discovery.next().await),
+        (true, "http://192.168.1.11:8888/".to_owned())
+    );
+    assert_eq!(discovery.things().len(), 1);
+
+    responder
+        .unbounded_send(BrowseEvent::Removed(
+            "Unknown._webthing._tcp.local.".to_owned(),
+        ))
+        .unwrap();
+    responder
+        .unbounded_send(BrowseEvent::Removed(lamp.name.clone()))
+        .unwrap();
+    assert_eq!(
+        url(discovery.next().await),
+        (false, "http://192.168.1.11:8888/".to_owned())
+    );
+    assert!(discovery.things().is_empty());
+
+    drop(responder);
+    assert!(discovery.next().await.is_none());
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::App;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use webthing::property::{AsyncValueForwarder, ForwarderFuture};
+use webthing::{BaseProperty, BaseThing, Thing};
+
+/// A device that takes a while to apply values and rounds them to multiples of ten.
+struct Dimmer {
+    delay: Duration,
+    failure: Option<&'static str>,
+}
+
+impl AsyncValueForwarder for Dimmer {
+    fn set_value(&self, value: serde_json::Value) -> ForwarderFuture {
+        let delay = self.delay;
+        let failure = self.failure;
+        Box::pin(async move {
+            actix_rt::time::sleep(delay).await;
+            match failure {
+                Some(failure) => Err(failure),
+                None => Ok(json!(value.as_i64().unwrap() / 10 * 10)),
+            }
+        })
+    }
+}
+
+fn make_dimmer(delay: Duration, failure: Option<&'static str>) -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:dimmer-1234".to_owned(),
+        "Dimmer".to_owned(),
+        None,
+        None,
+    );
+
+    let on = json!({"type": "boolean"});
+    thing.add_property(Box::new(BaseProperty::new(
+        "on".to_owned(),
+        json!(true),
+        None,
+        on.as_object().cloned(),
+    )));
+
+    let level = json!({"type": "integer", "minimum": 0, "maximum": 100});
+    thing.add_property(Box::new(
+        BaseProperty::new(
+            "level".to_owned(),
+            json!(50),
+            None,
+            level.as_object().cloned(),
+        )
+        .with_async_value_forwarder(Arc::new(Dimmer { delay, failure })),
+    ));
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+fn put_level(level: serde_json::Value) -> actix_http::Request {
+    TestRequest::put()
+        .uri("/properties/level")
+        .set_json(json!({ "level": level }))
+        .to_request()
+}
+
+#[actix_rt::test]
+async fn caches_the_value_applied_by_the_device() {
+    let thing = make_dimmer(Duration::ZERO, None);
+    let mut server = common::single_server(thing.clone());
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let res = call_service(&app, put_level(json!(47))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = read_body_json(res).await;
+    assert_eq!(body, json!({"level": 40}));
+    assert_eq!(thing.read().unwrap().get_property("level"), Some(json!(40)));
+
+    // Invalid values are rejected before reaching the device.
+    let res = call_service(&app, put_level(json!(101))).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(thing.read().unwrap().get_property("level"), Some(json!(40)));
+}
+
+#[actix_rt::test]
+async fn reports_failures_and_timeouts() {
+    let mut server = common::single_server(make_dimmer(Duration::ZERO, Some("Device offline")));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let res = call_service(&app, put_level(json!(20))).await;
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    let body: serde_json::Value = read_body_json(res).await;
+    assert_eq!(body["data"]["status"], "502 Bad Gateway");
+    assert_eq!(body["data"]["message"], "Device offline");
+
+    let thing = make_dimmer(Duration::from_secs(5), None);
+    let mut server =
+        common::single_server(thing.clone()).with_forwarder_timeout(Duration::from_millis(50));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let res = call_service(&app, put_level(json!(20))).await;
+    assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(thing.read().unwrap().get_property("level"), Some(json!(50)));
+}
+
+#[actix_rt::test]
+async fn does_not_lock_the_thing_while_waiting() {
+    let mut server = common::single_server(make_dimmer(Duration::from_millis(500), None));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let write = call_service(&app, put_level(json!(30)));
+    let read = async {
+        // Let the write reach the forwarder first.
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        let started = Instant::now();
+        let req = TestRequest::get().uri("/properties/on").to_request();
+        let res = call_service(&app, req).await;
+        (res.status(), started.elapsed())
+    };
+
+    let (write, (status, elapsed)) = futures::join!(write, read);
+    assert_eq!(write.status(), StatusCode::OK);
+    assert_eq!(status, StatusCode::OK);
+    assert!(elapsed < Duration::from_millis(250), "{:?}", elapsed);
+}
+
+#[actix_rt::test]
+async fn reports_websocket_errors() {
+    let mut server = common::single_server(make_dimmer(Duration::ZERO, Some("Device offline")));
+    let (url, handle) = common::serve(&mut server);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.replacen("http", "ws", 1).as_str())
+        .await
+        .unwrap();
+
+    for (data, status) in [
+        (json!({"level": 20}), "502 Bad Gateway"),
+        (json!({"missing": 20}), "400 Bad Request"),
+    ] {
+        let request = json!({"messageType": "setProperty", "data": data});
+        socket
+            .send(Message::text(request.to_string()))
+            .await
+            .unwrap();
+
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for a message")
+            .expect("WebSocket closed")
+            .unwrap();
+        let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(message["messageType"], "error");
+        assert_eq!(message["data"]["status"], status);
+        assert_eq!(message["data"]["request"], request);
+    }
+
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn applies_websocket_messages_in_order() {
+    let mut server = common::single_server(make_dimmer(Duration::from_millis(200), None));
+    let (url, handle) = common::serve(&mut server);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.replacen("http", "ws", 1).as_str())
+        .await
+        .unwrap();
+
+    // The slow write is applied before the quick one sent after it.
+    for data in [json!({"level": 30}), json!({"on": false})] {
+        let request = json!({"messageType": "setProperty", "data": data});
+        socket
+            .send(Message::text(request.to_string()))
+            .await
+            .unwrap();
+    }
+
+    let mut changes = Vec::new();
+    while changes.len() < 2 {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for a message")
+            .expect("WebSocket closed")
+            .unwrap();
+        let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(message["messageType"], "propertyStatus");
+        changes.push(message["data"].clone());
+    }
+    assert_eq!(changes, [json!({"level": 30}), json!({"on": false})]);
+
+    handle.stop(false).await;
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::App;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use webthing::{RateLimit, RequestLimits};
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+fn put_on(peer: &str) -> TestRequest {
+    TestRequest::put()
+        .uri("/properties/on")
+        .peer_addr(peer.parse::<SocketAddr>().unwrap())
+        .set_json(json!({"on": false}))
+}
+
+fn post_fade(peer: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/actions/fade")
+        .peer_addr(peer.parse::<SocketAddr>().unwrap())
+        .set_json(json!({"fade": {"input": {}}}))
+}
+
+async fn call(
+    app: &impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error>,
+    req: TestRequest,
+) -> StatusCode {
+    call_service(app, req.to_request()).await.status()
+}
+
+/// Wait for an error message, skipping property and action notifications.
+async fn receive_error(socket: &mut WebSocket) -> serde_json::Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for a message")
+            .expect("WebSocket closed")
+            .unwrap();
+        let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        if message["messageType"] == "error" {
+            return message["data"].clone();
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn limits_clients() {
+    let mut server = common::single_server(common::make_lamp())
+        .with_request_limits(RequestLimits::default().with_per_client(RateLimit::per_minute(2)));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    assert_eq!(call(&app, put_on("192.0.2.1:1000")).await, StatusCode::OK);
+    assert_eq!(
+        call(&app, post_fade("192.0.2.1:1001")).await,
+        StatusCode::CREATED
+    );
+
+    let res = call_service(&app, put_on("192.0.2.1:1002").to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = res
+        .headers()
+        .get("Retry-After")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=30).contains(&retry_after), "{}", retry_after);
+    let body: serde_json::Value = read_body_json(res).await;
+    assert_eq!(body["data"]["status"], "429 Too Many Requests");
+
+    // Reads aren't limited, and other clients have their own limit.
+    let req = TestRequest::get()
+        .uri("/properties/on")
+        .peer_addr("192.0.2.1:1003".parse().unwrap());
+    assert_eq!(call(&app, req).await, StatusCode::OK);
+    assert_eq!(call(&app, put_on("192.0.2.2:1000")).await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn limits_things_and_interactions() {
+    let limits = RequestLimits::default().with_per_interaction(RateLimit::per_minute(1));
+    let mut server = common::single_server(common::make_lamp()).with_request_limits(limits);
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    assert_eq!(call(&app, put_on("192.0.2.1:1000")).await, StatusCode::OK);
+    assert_eq!(
+        call(&app, put_on("192.0.2.2:1000")).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        call(&app, post_fade("192.0.2.1:1000")).await,
+        StatusCode::CREATED
+    );
+    let req = TestRequest::post()
+        .uri("/actions")
+        .peer_addr("192.0.2.1:1000".parse().unwrap())
+        .set_json(json!({"fade": {"input": {}}}));
+    assert_eq!(call(&app, req).await, StatusCode::TOO_MANY_REQUESTS);
+
+    let limits = RequestLimits::default().with_per_thing(RateLimit::per_minute(2));
+    let mut server = common::single_server(common::make_lamp()).with_request_limits(limits);
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    assert_eq!(call(&app, put_on("192.0.2.1:1000")).await, StatusCode::OK);
+    assert_eq!(
+        call(&app, post_fade("192.0.2.2:1000")).await,
+        StatusCode::CREATED
+    );
+    assert_eq!(
+        call(&app, post_fade("192.0.2.3:1000")).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[actix_rt::test]
+async fn limits_forwarded_clients() {
+    let mut server = common::single_server(common::make_lamp())
+        .with_trusted_proxies(vec!["10.0.0.0/8".parse().unwrap()])
+        .with_request_limits(RequestLimits::default().with_per_client(RateLimit::per_minute(1)));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let forwarded = |forwarded_for: &str| {
+        put_on("10.0.0.1:1000").insert_header(("X-Forwarded-For", forwarded_for.to_owned()))
+    };
+    assert_eq!(
+        call(&app, forwarded("192.0.2.1, 10.0.0.2")).await,
+        StatusCode::OK
+    );
+    // The client can't pose as another one by adding its own entries.
+    assert_eq!(
+        call(&app, forwarded("198.51.100.7, 192.0.2.1")).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(call(&app, forwarded("192.0.2.2")).await, StatusCode::OK);
+
+    let req = put_on("10.0.0.1:1000").insert_header(("Forwarded", "for=\"[2001:db8::1]:4711\""));
+    assert_eq!(call(&app, req).await, StatusCode::OK);
+    let req = put_on("10.0.0.3:1000").insert_header(("X-Forwarded-For", "2001:db8::1"));
+    assert_eq!(call(&app, req).await, StatusCode::TOO_MANY_REQUESTS);
+
+    // Untrusted peers are limited by their own address.
+    let req = put_on("192.0.2.9:1000").insert_header(("X-Forwarded-For", "192.0.2.3"));
+    assert_eq!(call(&app, req).await, StatusCode::OK);
+    assert_eq!(call(&app, put_on("192.0.2.3:1000")).await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn limits_body_size() {
+    let mut server = common::single_server(common::make_lamp())
+        .with_request_limits(RequestLimits::default().with_max_body_size(64));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    assert_eq!(
+        call(&app, post_fade("192.0.2.1:1000")).await,
+        StatusCode::CREATED
+    );
+    let req = TestRequest::post()
+        .uri("/actions/fade")
+        .set_json(json!({"fade": {"input": {"padding": "x".repeat(64)}}}));
+    assert_eq!(call(&app, req).await, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[actix_rt::test]
+async fn limits_websockets() {
+    let limits = RequestLimits::default()
+        .with_per_client(RateLimit::per_minute(2))
+        .with_per_interaction(RateLimit::per_minute(1));
+    let mut server = common::single_server(common::make_lamp()).with_request_limits(limits);
+    let (url, handle) = common::serve(&mut server);
+    let ws_url = url.replacen("http", "ws", 1);
+    let (mut socket, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+        .await
+        .unwrap();
+
+    let set_on = json!({"messageType": "setProperty", "data": {"on": false}});
+    for _ in 0..2 {
+        socket
+            .send(Message::text(set_on.to_string()))
+            .await
+            .unwrap();
+    }
+    let error = receive_error(&mut socket).await;
+    assert_eq!(error["status"], "429 Too Many Requests");
+    assert_eq!(error["request"], set_on);
+
+    socket
+        .send(Message::text(set_on.to_string()))
+        .await
+        .unwrap();
+    let error = receive_error(&mut socket).await;
+    assert_eq!(error["status"], "429 Too Many Requests");
+    assert_eq!(error["request"], json!(null));
+
+    handle.stop(false).await;
+
+    let limits = RequestLimits::default().with_max_frame_size(256);
+    let mut server = common::single_server(common::make_lamp()).with_request_limits(limits);
+    let (url, handle) = common::serve(&mut server);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.replacen("http", "ws", 1).as_str())
+        .await
+        .unwrap();
+    let large = json!({"messageType": "addEventSubscription", "data": {"x".repeat(256): {}}});
+    socket.send(Message::text(large.to_string())).await.unwrap();
+    let error = receive_error(&mut socket).await;
+    assert_eq!(error["status"], "413 Payload Too Large");
+
+    let mut close = None;
+    while let Ok(Some(Ok(message))) =
+        tokio::time::timeout(Duration::from_secs(5), socket.next()).await
+    {
+        if let Message::Close(frame) = message {
+            close = frame;
+        }
+    }
+    assert_eq!(close.unwrap().code, CloseCode::Size);
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn start_rejects_invalid_limits() {
+    for limit in [RateLimit::per_second(0), RateLimit::new(10, Duration::ZERO)] {
+        let limits = RequestLimits::default().with_per_thing(limit);
+        match common::single_server(common::make_lamp())
+            .with_request_limits(limits)
+            .start(None)
+        {
+            Ok(_) => panic!("{} accepted", limit),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err),
+        }
+    }
+}
@@ -0,0 +1,279 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+use actix_web::App;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use webthing::server::{HostValidator, IpNet};
+use webthing::{BaseActionGenerator, HostPattern, ListenAddress, ThingsType, WebThingServer};
+
+const PORT: u16 = 8888;
+
+fn server(addresses: Vec<ListenAddress>, hostname: Option<&str>) -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(PORT),
+        hostname.map(|h| h.to_owned()),
+        None,
+        Box::new(BaseActionGenerator),
+        None,
+        None,
+    )
+    .with_listen_addresses(addresses)
+}
+
+#[test]
+fn parses_listen_addresses() {
+    for (input, expected) in [
+        ("0.0.0.0", ListenAddress::Ip(Ipv4Addr::UNSPECIFIED.into())),
+        ("[::]", ListenAddress::Ip(Ipv6Addr::UNSPECIFIED.into())),
+        ("::1", ListenAddress::Ip(Ipv6Addr::LOCALHOST.into())),
+        ("eth0", ListenAddress::Interface("eth0".to_owned())),
+        (
+            "unix:/run/webthing.sock",
+            ListenAddress::Unix(PathBuf::from("/run/webthing.sock")),
+        ),
+    ] {
+        assert_eq!(input.parse::<ListenAddress>(), Ok(expected));
+    }
+
+    assert_eq!(
+        ListenAddress::Ip(Ipv6Addr::LOCALHOST.into()).to_string(),
+        "[::1]"
+    );
+    assert!("unix:".parse::<ListenAddress>().is_err());
+    assert!("127.0.0.1:80".parse::<ListenAddress>().is_err());
+    assert!("".parse::<ListenAddress>().is_err());
+}
+
+#[actix_rt::test]
+async fn host_validation_follows_bound_addresses() {
+    let loopback_v6 = vec![ListenAddress::Ip(IpAddr::V6(Ipv6Addr::LOCALHOST))];
+    let config = server(loopback_v6, Some("thing.example")).make_config();
+    let app = init_service(App::new().wrap(HostValidator).configure(config)).await;
+
+    for (host, expected) in [
+        ("[::1]:8888", StatusCode::OK),
+        ("localhost:8888", StatusCode::OK),
+        ("thing.example:8888", StatusCode::OK),
+        ("127.0.0.1:8888", StatusCode::FORBIDDEN),
+    ] {
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header(("Host", host))
+            .to_request();
+        let status = call_service(&app, req).await.status();
+        assert_eq!(status, expected, "{}", host);
+    }
+}
+
+#[actix_rt::test]
+async fn host_validation_follows_ephemeral_ports() {
+    let loopback = vec![ListenAddress::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST))];
+    let mut server = WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(0),
+        None,
+        None,
+        Box::new(BaseActionGenerator),
+        None,
+        None,
+    )
+    .with_listen_addresses(loopback);
+    let running = server.start(None).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+
+    let address = server.addrs()[0];
+    assert_ne!(address.port(), 0);
+    for (host, expected) in [
+        (address.to_string(), "HTTP/1.1 200 OK"),
+        ("127.0.0.1:80".to_owned(), "HTTP/1.1 403 Forbidden"),
+    ] {
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            host
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with(expected), "{}: {}", host, response);
+    }
+
+    handle.stop(false).await;
+}
+
+#[test]
+fn parses_host_patterns() {
+    for (input, expected) in [
+        (
+            "Thing.Example",
+            HostPattern::Exact("thing.example".to_owned(), None),
+        ),
+        (
+            "thing.example:8443",
+            HostPattern::Exact("thing.example".to_owned(), Some(8443)),
+        ),
+        ("::1", HostPattern::Exact("::1".to_owned(), None)),
+        ("[::1]:80", HostPattern::Exact("::1".to_owned(), Some(80))),
+        (
+            "*.example.com",
+            HostPattern::Wildcard("example.com".to_owned(), None),
+        ),
+        (
+            "10.0.0.0/8",
+            HostPattern::Network("10.0.0.0/8".parse().unwrap()),
+        ),
+    ] {
+        assert_eq!(input.parse::<HostPattern>(), Ok(expected.clone()));
+        assert_eq!(
+            expected.to_string().parse::<HostPattern>(),
+            Ok(expected),
+            "{}",
+            input
+        );
+    }
+
+    for invalid in ["", "*", "a.*.com", "thing:port", "10.0.0.0/33", "fd00::1:"] {
+        assert!(invalid.parse::<HostPattern>().is_err(), "{}", invalid);
+    }
+}
+
+#[actix_rt::test]
+async fn host_validation_accepts_allowed_patterns() {
+    let loopback = vec![ListenAddress::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST))];
+    let patterns = ["*.example.com", "thing.example:8443", "192.168.0.0/16"]
+        .iter()
+        .map(|p| p.parse().unwrap())
+        .collect();
+    let config = server(loopback, None)
+        .with_allowed_hosts(patterns)
+        .make_config();
+    let app = init_service(App::new().wrap(HostValidator).configure(config)).await;
+
+    for (host, expected) in [
+        ("localhost:8888", StatusCode::OK),
+        ("lamp.example.com", StatusCode::OK),
+        ("Lamp.Example.com:443", StatusCode::OK),
+        ("example.com", StatusCode::FORBIDDEN),
+        ("evil-example.com", StatusCode::FORBIDDEN),
+        ("thing.example:8443", StatusCode::OK),
+        ("thing.example", StatusCode::FORBIDDEN),
+        ("192.168.4.20:8888", StatusCode::OK),
+        ("192.169.4.20", StatusCode::FORBIDDEN),
+    ] {
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header(("Host", host))
+            .to_request();
+        let status = call_service(&app, req).await.status();
+        assert_eq!(status, expected, "{}", host);
+    }
+}
+
+#[actix_rt::test]
+async fn trusted_proxies_forward_host_and_scheme() {
+    let loopback = vec![ListenAddress::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST))];
+    let config = server(loopback, None)
+        .with_allowed_hosts(vec!["things.example.com".parse().unwrap()])
+        .with_trusted_proxies(vec!["10.0.0.0/8".parse::<IpNet>().unwrap()])
+        .make_config();
+    let app = init_service(App::new().wrap(HostValidator).configure(config)).await;
+
+    let proxy: SocketAddr = "10.1.2.3:50000".parse().unwrap();
+    let stranger: SocketAddr = "172.16.0.1:50000".parse().unwrap();
+    let get = |peer: SocketAddr, headers: &[(&'static str, &'static str)]| {
+        let mut req = TestRequest::get()
+            .uri("/")
+            .peer_addr(peer)
+            .insert_header(("Host", "localhost:8888"));
+        for header in headers {
+            req = req.insert_header(*header);
+        }
+        req.to_request()
+    };
+
+    for headers in [
+        &[
+            ("X-Forwarded-Host", "things.example.com"),
+            ("X-Forwarded-Proto", "https"),
+        ][..],
+        &[(
+            "Forwarded",
+            "for=192.0.2.60, for=192.0.2.61;proto=https;host=\"things.example.com\"",
+        )][..],
+    ] {
+        let description: serde_json::Value =
+            call_and_read_body_json(&app, get(proxy, headers)).await;
+        assert_eq!(description["base"], "https://things.example.com/");
+        let websocket = description["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|link| link["rel"] == "alternate")
+            .unwrap();
+        assert_eq!(websocket["href"], "wss://things.example.com/");
+
+        // Other peers cannot spoof the host.
+        let description: serde_json::Value =
+            call_and_read_body_json(&app, get(stranger, headers)).await;
+        assert_eq!(description["base"], "http://localhost:8888/");
+    }
+
+    // Forwarded hosts are validated like the Host header.
+    let req = get(proxy, &[("X-Forwarded-Host", "evil.com")]);
+    assert_eq!(
+        call_service(&app, req).await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    // Clients can send their own elements, but only those appended by the proxy are used.
+    for headers in [
+        &[(
+            "Forwarded",
+            "host=things.example.com;proto=https, for=192.0.2.60;host=evil.com",
+        )][..],
+        &[("X-Forwarded-Host", "things.example.com, evil.com")][..],
+    ] {
+        let req = get(proxy, headers);
+        assert_eq!(
+            call_service(&app, req).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+    let headers = [(
+        "Forwarded",
+        "host=evil.com;proto=http, for=192.0.2.60;host=things.example.com;proto=https",
+    )];
+    let description: serde_json::Value = call_and_read_body_json(&app, get(proxy, &headers)).await;
+    assert_eq!(description["base"], "https://things.example.com/");
+}
+
+#[cfg(unix)]
+#[actix_rt::test]
+async fn serves_on_unix_socket() {
+    let path = std::env::temp_dir().join(format!("webthing-{}.sock", std::process::id()));
+    let mut server = server(
+        vec![ListenAddress::Unix(path.clone())],
+        Some("thing.example"),
+    );
+    let running = server.start(None).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+
+    let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: thing.example\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("\"title\":\"My Lamp\""), "{}", response);
+
+    handle.stop(false).await;
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,253 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::App;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use webthing::property::ValueForwarder;
+use webthing::server::ActionGenerator;
+use webthing::{Action, BaseAction, BaseProperty, BaseThing, Thing, ThingsType, WebThingServer};
+
+/// A device that blocks while applying values, or panics.
+struct Device {
+    delay: Duration,
+    panics: bool,
+}
+
+impl ValueForwarder for Device {
+    fn set_value(&mut self, value: serde_json::Value) -> Result<serde_json::Value, &'static str> {
+        thread::sleep(self.delay);
+        if self.panics {
+            panic!("device driver bug");
+        }
+        Ok(value)
+    }
+}
+
+fn make_thing(level: Device) -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:device-1234".to_owned(),
+        "Device".to_owned(),
+        None,
+        None,
+    );
+
+    let on = json!({"type": "boolean"});
+    thing.add_property(Box::new(BaseProperty::new(
+        "on".to_owned(),
+        json!(true),
+        Some(Box::new(Device {
+            delay: Duration::ZERO,
+            panics: false,
+        })),
+        on.as_object().cloned(),
+    )));
+
+    let level_description = json!({"type": "integer"});
+    thing.add_property(Box::new(BaseProperty::new(
+        "level".to_owned(),
+        json!(50),
+        Some(Box::new(level)),
+        level_description.as_object().cloned(),
+    )));
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+fn put(property: &str, value: serde_json::Value) -> actix_http::Request {
+    TestRequest::put()
+        .uri(&format!("/properties/{}", property))
+        .set_json(json!({ property: value }))
+        .to_request()
+}
+
+#[actix_rt::test]
+async fn panicking_forwarder_is_isolated() {
+    let thing = make_thing(Device {
+        delay: Duration::ZERO,
+        panics: true,
+    });
+    let mut server = common::single_server(thing.clone());
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    for _ in 0..2 {
+        let res = call_service(&app, put("level", json!(10))).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let req = TestRequest::get().uri("/properties").to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = read_body_json(res).await;
+    assert_eq!(body, json!({"on": true, "level": 50}));
+
+    let res = call_service(&app, put("on", json!(false))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!thing.is_poisoned());
+}
+
+#[actix_rt::test]
+async fn slow_forwarder_does_not_block_other_properties() {
+    let thing = make_thing(Device {
+        delay: Duration::from_millis(500),
+        panics: false,
+    });
+    let mut server = common::single_server(thing.clone());
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let write = call_service(&app, put("level", json!(10)));
+    let others = async {
+        // Let the slow write reach the device first.
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        let started = Instant::now();
+        let read = call_service(&app, TestRequest::get().uri("/properties/on").to_request())
+            .await
+            .status();
+        let write = call_service(&app, put("on", json!(false))).await.status();
+        (read, write, started.elapsed())
+    };
+
+    let (write, (read, other_write, elapsed)) = futures::join!(write, others);
+    assert_eq!(write.status(), StatusCode::OK);
+    assert_eq!(read, StatusCode::OK);
+    assert_eq!(other_write, StatusCode::OK);
+    assert!(elapsed < Duration::from_millis(250), "{:?}", elapsed);
+    assert_eq!(
+        thing.read().unwrap().get_properties(),
+        *json!({"on": false, "level": 10}).as_object().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn slow_forwarder_times_out() {
+    let thing = make_thing(Device {
+        delay: Duration::from_millis(1000),
+        panics: false,
+    });
+    let mut server =
+        common::single_server(thing.clone()).with_forwarder_timeout(Duration::from_millis(50));
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let started = Instant::now();
+    let res = call_service(&app, put("level", json!(10))).await;
+    assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(thing.read().unwrap().get_property("level"), Some(json!(50)));
+}
+
+/// Records whether the thing could be read while the action was performed.
+struct ProbeAction {
+    action: BaseAction,
+    thing_free: Arc<AtomicBool>,
+}
+
+impl Action for ProbeAction {
+    fn set_href_prefix(&mut self, prefix: String) {
+        self.action.set_href_prefix(prefix)
+    }
+
+    fn get_id(&self) -> String {
+        self.action.get_id()
+    }
+
+    fn get_name(&self) -> String {
+        self.action.get_name()
+    }
+
+    fn get_href(&self) -> String {
+        self.action.get_href()
+    }
+
+    fn get_status(&self) -> String {
+        self.action.get_status()
+    }
+
+    fn get_time_requested(&self) -> String {
+        self.action.get_time_requested()
+    }
+
+    fn get_time_completed(&self) -> Option<String> {
+        self.action.get_time_completed()
+    }
+
+    fn get_input(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        self.action.get_input()
+    }
+
+    fn get_thing(&self) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.action.get_thing()
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.action.set_status(status)
+    }
+
+    fn start(&mut self) {
+        self.action.start()
+    }
+
+    fn perform_action(&mut self) {
+        if let Some(thing) = self.get_thing() {
+            self.thing_free
+                .store(thing.try_read().is_ok(), Ordering::SeqCst);
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.action.cancel()
+    }
+
+    fn finish(&mut self) {
+        self.action.finish()
+    }
+}
+
+struct ProbeGenerator(Arc<AtomicBool>);
+
+impl ActionGenerator for ProbeGenerator {
+    fn generate(
+        &self,
+        thing: Weak<RwLock<Box<dyn Thing>>>,
+        name: String,
+        input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+        let input = input.and_then(|i| i.as_object()).cloned();
+        Some(Box::new(ProbeAction {
+            action: BaseAction::new(Uuid::new_v4().to_string(), name, input, thing),
+            thing_free: self.0.clone(),
+        }))
+    }
+}
+
+#[actix_rt::test]
+async fn actions_are_performed_without_locking_the_thing() {
+    let thing_free = Arc::new(AtomicBool::new(false));
+    let mut server = WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        None,
+        None,
+        None,
+        Box::new(ProbeGenerator(thing_free.clone())),
+        None,
+        Some(true),
+    );
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    let req = TestRequest::post()
+        .uri("/actions")
+        .set_json(json!({"fade": {"input": {}}}))
+        .to_request();
+    let res = call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    assert!(thing_free.load(Ordering::SeqCst));
+
+    // Subscribers were still told the action started.
+    let req = TestRequest::get().uri("/actions/fade").to_request();
+    let actions: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(actions[0]["fade"]["status"], "pending");
+}
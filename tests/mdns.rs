@@ -0,0 +1,105 @@
+mod common;
+
+use webthing::server::MdnsMode;
+use webthing::{AdvertisedService, BaseActionGenerator, ThingsType, WebThingServer};
+
+fn lamps_server(mode: MdnsMode) -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Multiple(vec![common::make_lamp()], "Lamps".to_owned()),
+        Some(0),
+        None,
+        None,
+        Box::new(BaseActionGenerator),
+        Some("/things".to_owned()),
+        None,
+    )
+    .with_mdns(mode)
+    .with_mdns_name("Living Room".to_owned())
+    .with_mdns_txt_record("model", "lamp-2")
+}
+
+fn service(name: &str, path: &str) -> AdvertisedService {
+    AdvertisedService {
+        name: name.to_owned(),
+        txt: vec![format!("path={}", path), "model=lamp-2".to_owned()],
+    }
+}
+
+#[actix_rt::test]
+async fn advertises_things_as_they_change() {
+    let mut server = lamps_server(MdnsMode::PerThing);
+    let handle = server.things_handle().unwrap();
+    actix_rt::spawn(server.start(None).unwrap());
+    assert_eq!(
+        server.advertised_services(),
+        [service("My Lamp", "/things/my-lamp-1234")]
+    );
+
+    // Things with the same title are advertised under distinct instance names, and
+    // replacing or removing them withdraws their previous service.
+    let path = handle.add_thing(common::make_lamp());
+    let second = service(&format!("My Lamp ({})", path), &format!("/things/{}", path));
+    assert_eq!(
+        server.advertised_services(),
+        [service("My Lamp", "/things/my-lamp-1234"), second.clone()]
+    );
+    assert!(handle.replace_thing(&path, common::make_lamp()).is_some());
+    assert_eq!(
+        server.advertised_services(),
+        [service("My Lamp", "/things/my-lamp-1234"), second]
+    );
+    assert!(handle.remove_thing(&path).is_some());
+    assert_eq!(
+        server.advertised_services(),
+        [service("My Lamp", "/things/my-lamp-1234")]
+    );
+
+    server.shutdown().await;
+    assert!(server.advertised_services().is_empty());
+}
+
+#[actix_rt::test]
+async fn advertises_the_path_things_are_listed_at() {
+    let mut server = lamps_server(MdnsMode::Server);
+    actix_rt::spawn(server.start(None).unwrap());
+    assert_eq!(
+        server.advertised_services(),
+        [service("Living Room", "/things/")]
+    );
+
+    let url = format!("http://127.0.0.1:{}/things/", server.addrs()[0].port());
+    let things: serde_json::Value = reqwest::get(&url).await.unwrap().json().await.unwrap();
+    assert_eq!(things[0]["href"], "/things/my-lamp-1234");
+
+    server.shutdown().await;
+}
+
+#[actix_rt::test]
+async fn advertises_the_path_a_single_thing_is_served_at() {
+    let mut server = WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(0),
+        None,
+        None,
+        Box::new(BaseActionGenerator),
+        Some("/lamp".to_owned()),
+        None,
+    );
+    actix_rt::spawn(server.start(None).unwrap());
+    assert_eq!(server.advertised_services()[0].txt, ["path=/lamp"]);
+
+    let url = format!("http://127.0.0.1:{}/lamp", server.addrs()[0].port());
+    let thing: serde_json::Value = reqwest::get(&url).await.unwrap().json().await.unwrap();
+    assert_eq!(thing["id"], "urn:dev:ops:my-lamp-1234");
+
+    server.shutdown().await;
+}
+
+#[actix_rt::test]
+async fn is_not_advertised_when_disabled() {
+    let mut server = lamps_server(MdnsMode::Disabled);
+    actix_rt::spawn(server.start(None).unwrap());
+    assert!(server.advertised_services().is_empty());
+
+    server.shutdown().await;
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::App;
+use std::sync::{Arc, RwLock};
+use webthing::{BaseThing, Thing, ThingsType, WebThingServer};
+
+fn make_thing(id: &str) -> Arc<RwLock<Box<dyn Thing>>> {
+    let thing = BaseThing::new(id.to_owned(), id.to_owned(), None, None);
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+fn multiple_server(things: Vec<Arc<RwLock<Box<dyn Thing>>>>) -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Multiple(things, "Things".to_owned()),
+        None,
+        None,
+        None,
+        Box::new(common::Generator),
+        None,
+        Some(true),
+    )
+}
+
+#[test]
+fn derives_safe_paths_from_ids() {
+    let server = multiple_server(vec![]);
+    let things = server.things_handle().unwrap();
+
+    for (id, path) in [
+        ("urn:dev:ops:my-lamp-1234", "my-lamp-1234"),
+        ("urn:dev:..", "urn-dev"),
+        ("urn:dev:.", "urn-dev-2"),
+        ("urn:dev:v1.2", "v1-2"),
+        ("urn:dev:a?b#c%20", "a-b-c-20"),
+    ] {
+        assert_eq!(things.add_thing(make_thing(id)), path);
+    }
+}
+
+#[test]
+fn rejects_unsafe_path_segments() {
+    let server = multiple_server(vec![]);
+    let things = server.things_handle().unwrap();
+
+    for path in ["", ".", "..", "a/b", "a?b", "a#b", "a%20b", "a b", "ä"] {
+        assert_eq!(
+            things.add_thing_at(path, common::make_lamp()),
+            Err("Invalid path segment"),
+            "{:?}",
+            path
+        );
+    }
+
+    assert_eq!(
+        things.add_thing_at("v1.2~beta_3", common::make_lamp()),
+        Ok("v1.2~beta_3".to_owned())
+    );
+    assert_eq!(
+        things.add_thing_at("42", common::make_lamp()),
+        Ok("42".to_owned())
+    );
+}
+
+#[actix_rt::test]
+async fn serves_index_aliases_when_enabled() {
+    let ids = ["urn:dev:ops:lamp", "urn:dev:ops:sensor"];
+
+    let mut server = multiple_server(ids.iter().map(|id| make_thing(id)).collect());
+    let app = init_service(App::new().configure(server.make_config())).await;
+    let req = TestRequest::get().uri("/0").to_request();
+    assert_eq!(
+        call_service(&app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let mut server =
+        multiple_server(ids.iter().map(|id| make_thing(id)).collect()).with_index_aliases(true);
+    let things = server.things_handle().unwrap();
+    let app = init_service(App::new().configure(server.make_config())).await;
+
+    for (index, id) in ids.iter().enumerate() {
+        let req = TestRequest::get().uri(&format!("/{}", index)).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let thing: serde_json::Value = read_body_json(res).await;
+        assert_eq!(thing["id"], *id);
+    }
+
+    // Aliases follow the order things were added in, not their current position.
+    assert!(things.remove_thing("lamp").is_some());
+    let req = TestRequest::get().uri("/0").to_request();
+    assert_eq!(
+        call_service(&app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    let req = TestRequest::get().uri("/1").to_request();
+    let thing: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    assert_eq!(thing["id"], "urn:dev:ops:sensor");
+}
+
+#[actix_rt::test]
+async fn reserves_numeric_paths_for_index_aliases() {
+    let mut server = multiple_server(vec![
+        make_thing("urn:dev:ops:1"),
+        make_thing("urn:dev:ops:0"),
+    ])
+    .with_index_aliases(true);
+    let things = server.things_handle().unwrap();
+
+    assert_eq!(
+        things.add_thing_at("7", common::make_lamp()),
+        Err("Path segment reserved for index aliases")
+    );
+    assert_eq!(
+        things.add_thing(make_thing("urn:dev:ops:3")),
+        "urn-dev-ops-3"
+    );
+
+    // Things served under a numeric path before the aliases were enabled are moved.
+    let app = init_service(App::new().configure(server.make_config())).await;
+    let req = TestRequest::get().to_request();
+    let list: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+    let hrefs: Vec<&str> = list
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|thing| thing["href"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        hrefs,
+        ["/urn-dev-ops-1", "/urn-dev-ops-0", "/urn-dev-ops-3"]
+    );
+
+    for (index, id) in ["urn:dev:ops:1", "urn:dev:ops:0"].iter().enumerate() {
+        let req = TestRequest::get().uri(&format!("/{}", index)).to_request();
+        let thing: serde_json::Value = read_body_json(call_service(&app, req).await).await;
+        assert_eq!(thing["id"], *id);
+    }
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use actix_web::dev::ServerHandle;
+use common::{make_lamp, serve, single_server, Generator};
+use futures::StreamExt;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use webthing::client::{Notification, ThingClient};
+use webthing::server::ActionGenerator;
+use webthing::{
+    BaseActionGenerator, BaseEvent, ProxyActionGenerator, ProxyThing, ThingsType, WebThingServer,
+};
+
+/// Poll until a condition holds, failing after a few seconds.
+async fn eventually(mut condition: impl FnMut() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("condition not met in time");
+}
+
+#[actix_rt::test]
+async fn mirrors_remote_thing() {
+    let remote = make_lamp();
+    let mut remote_server = single_server(remote.clone());
+    let (remote_url, remote_handle) = serve(&mut remote_server);
+
+    let proxy = ProxyThing::connect(&remote_url).await.unwrap();
+    assert_eq!(proxy.read().unwrap().get_id(), "urn:dev:ops:my-lamp-1234");
+    assert_eq!(proxy.read().unwrap().get_property("on"), Some(json!(true)));
+
+    let mut local_server = WebThingServer::new(
+        ThingsType::Single(proxy.clone()),
+        None,
+        None,
+        None,
+        Box::new(ProxyActionGenerator::new(Box::new(BaseActionGenerator))),
+        None,
+        Some(true),
+    );
+    let (local_url, local_handle) = serve(&mut local_server);
+    let local = ThingClient::connect(&local_url).await.unwrap();
+    let mut observation = local.observe(&["overheated"]).unwrap();
+    assert_eq!(observation.next().await, Some(Notification::Connected));
+
+    // Writes are forwarded to the remote thing.
+    local.write_property("brightness", json!(30)).await.unwrap();
+    assert_eq!(
+        remote.read().unwrap().get_property("brightness"),
+        Some(json!(30))
+    );
+
+    // Changes on the remote thing are mirrored.
+    remote
+        .write()
+        .unwrap()
+        .set_property("on".to_owned(), json!(false))
+        .ok();
+    let proxied = proxy.clone();
+    eventually(|| proxied.read().unwrap().get_property("on") == Some(json!(false))).await;
+
+    // Events are relayed.
+    remote.write().unwrap().add_event(Box::new(BaseEvent::new(
+        "overheated".to_owned(),
+        Some(json!(102)),
+    )));
+    loop {
+        match observation.next().await {
+            Some(Notification::Event(event)) => {
+                assert_eq!(event.data, Some(json!(102)));
+                break;
+            }
+            Some(_) => (),
+            None => panic!("observation ended"),
+        }
+    }
+
+    // Actions are requested from the remote thing and finish with it.
+    let request = local.request_action("fade", Some(json!({}))).await.unwrap();
+    let remote_client = ThingClient::connect(&remote_url).await.unwrap();
+    let mut remote_requests = Vec::new();
+    for _ in 0..100 {
+        remote_requests = remote_client.list_actions(Some("fade")).await.unwrap();
+        if !remote_requests.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(remote_requests.len(), 1);
+    remote
+        .write()
+        .unwrap()
+        .finish_action("fade".to_owned(), remote_requests[0].id.clone());
+    let mut status = String::new();
+    for _ in 0..100 {
+        status = local.get_action("fade", &request.id).await.unwrap().status;
+        if status == "completed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status, "completed");
+
+    local_handle.stop(false).await;
+    remote_handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn generates_local_actions_for_other_things() {
+    let generator = ProxyActionGenerator::new(Box::new(Generator));
+    let thing = make_lamp();
+    let action = generator.generate(Arc::downgrade(&thing), "fade".to_owned(), None);
+    assert!(action.is_some());
+}
+
+/// Serve a proxy for a lamp and request its fade action, returning the local and remote IDs.
+async fn request_proxied_fade(remote_url: &str) -> (ThingClient, String, String, ServerHandle) {
+    let proxy = ProxyThing::connect(remote_url).await.unwrap();
+    let mut local_server = WebThingServer::new(
+        ThingsType::Single(proxy),
+        None,
+        None,
+        None,
+        Box::new(ProxyActionGenerator::new(Box::new(BaseActionGenerator))),
+        None,
+        Some(true),
+    );
+    let (local_url, local_handle) = serve(&mut local_server);
+    let local = ThingClient::connect(&local_url).await.unwrap();
+    let request = local.request_action("fade", Some(json!({}))).await.unwrap();
+
+    let remote_client = ThingClient::connect(remote_url).await.unwrap();
+    let mut remote_requests = Vec::new();
+    for _ in 0..100 {
+        remote_requests = remote_client.list_actions(Some("fade")).await.unwrap();
+        if !remote_requests.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(remote_requests.len(), 1);
+
+    (
+        local,
+        request.id,
+        remote_requests[0].id.clone(),
+        local_handle,
+    )
+}
+
+#[actix_rt::test]
+async fn mirrors_cancelled_remote_actions() {
+    let remote = make_lamp();
+    let mut remote_server = single_server(remote.clone());
+    let (remote_url, remote_handle) = serve(&mut remote_server);
+    let (local, local_id, remote_id, local_handle) = request_proxied_fade(&remote_url).await;
+
+    {
+        let mut remote = remote.write().unwrap();
+        let action = remote.get_action("fade".to_owned(), remote_id).unwrap();
+        let description = {
+            let mut action = action.write().unwrap();
+            action.set_status("cancelled".to_owned());
+            action.as_action_description()
+        };
+        remote.action_notify(description);
+    }
+
+    let mut status = String::new();
+    for _ in 0..100 {
+        status = local.get_action("fade", &local_id).await.unwrap().status;
+        if status == "cancelled" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status, "cancelled");
+
+    local_handle.stop(false).await;
+    remote_handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn dropping_the_proxy_closes_its_websocket() {
+    let mut remote_server = single_server(make_lamp());
+    let (remote_url, remote_handle) = serve(&mut remote_server);
+
+    // Observe the proxy's WebSocket from a bare endpoint which never sends anything.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut description = ThingClient::connect(&remote_url)
+        .await
+        .unwrap()
+        .description()
+        .clone();
+    let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+    description.insert("forms".to_owned(), json!([{ "href": endpoint }]));
+    let client = ThingClient::from_description(&remote_url, description).unwrap();
+
+    let proxy = ProxyThing::from_client(client).await.unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+    // Let the proxy take note of the connection, after which it receives nothing.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    drop(proxy);
+    let closed = tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(Ok(message)) = socket.next().await {
+            if message.is_close() {
+                break;
+            }
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "WebSocket still open");
+
+    remote_handle.stop(false).await;
+}
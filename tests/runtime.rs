@@ -0,0 +1,134 @@
+mod common;
+
+use futures::StreamExt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use webthing::{BaseThing, Thing, ThingsType, WebThingServer};
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+fn multiple_server() -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Multiple(vec![common::make_lamp()], "Things".to_owned()),
+        None,
+        None,
+        None,
+        Box::new(common::Generator),
+        None,
+        Some(true),
+    )
+}
+
+async fn get(url: &str) -> (u16, serde_json::Value) {
+    let res = reqwest::get(url).await.unwrap();
+    let status = res.status().as_u16();
+    (status, res.json().await.unwrap_or_default())
+}
+
+/// Wait for the WebSocket to be closed, returning the close frame's code and reason.
+async fn closed(socket: &mut WebSocket) -> (CloseCode, String) {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("timed out waiting for the WebSocket to close")
+            .expect("WebSocket closed without a close frame")
+            .unwrap();
+        if let Message::Close(Some(frame)) = message {
+            return (frame.code, frame.reason.to_string());
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn adds_and_removes_things_while_running() {
+    let mut server = multiple_server();
+    let things = server.things_handle().unwrap();
+    let (url, handle) = common::serve(&mut server);
+
+    let (_, list) = get(&url).await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+
+    let sensor = BaseThing::new(
+        "urn:dev:ops:sensor-42".to_owned(),
+        "Sensor".to_owned(),
+        None,
+        None,
+    );
+    let path = things.add_thing(Arc::new(RwLock::new(Box::new(sensor))));
+    assert_eq!(path, "sensor-42");
+
+    let (_, list) = get(&url).await;
+    let hrefs: Vec<&str> = list
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|thing| thing["href"].as_str().unwrap())
+        .collect();
+    assert_eq!(hrefs, ["/my-lamp-1234", "/sensor-42"]);
+    let (status, sensor) = get(&format!("{}sensor-42", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(sensor["title"], "Sensor");
+
+    let ws_url = format!("{}sensor-42", url.replacen("http", "ws", 1));
+    let (mut socket, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+        .await
+        .unwrap();
+    assert!(things.remove_thing("sensor-42").is_some());
+    assert_eq!(
+        closed(&mut socket).await,
+        (CloseCode::Away, "Thing removed".to_owned())
+    );
+
+    let (status, _) = get(&format!("{}sensor-42", url)).await;
+    assert_eq!(status, 404);
+    let (_, list) = get(&url).await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    assert!(things.remove_thing("sensor-42").is_none());
+
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn replaces_things_while_running() {
+    let mut server = multiple_server();
+    let things = server.things_handle().unwrap();
+    let (url, handle) = common::serve(&mut server);
+
+    let ws_url = format!("{}my-lamp-1234", url.replacen("http", "ws", 1));
+    let (mut socket, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+        .await
+        .unwrap();
+
+    let lamp = BaseThing::new(
+        "urn:dev:ops:my-lamp-1234".to_owned(),
+        "New Lamp".to_owned(),
+        None,
+        None,
+    );
+    let lamp: Arc<RwLock<Box<dyn Thing>>> = Arc::new(RwLock::new(Box::new(lamp)));
+    assert!(things.add_thing_at("my-lamp-1234", lamp.clone()).is_err());
+    assert!(things.replace_thing("my-lamp-1234", lamp).is_some());
+    assert_eq!(closed(&mut socket).await.0, CloseCode::Away);
+
+    let (status, thing) = get(&format!("{}my-lamp-1234", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(thing["title"], "New Lamp");
+
+    // Sockets connecting to the new thing stay open.
+    let (mut socket, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+        .await
+        .unwrap();
+    assert!(things
+        .replace_thing("unknown", common::make_lamp())
+        .is_none());
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), socket.next())
+            .await
+            .is_err()
+    );
+
+    handle.stop(false).await;
+}
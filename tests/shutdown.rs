@@ -0,0 +1,117 @@
+mod common;
+
+use actix_web::web;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::net::{IpAddr, Ipv4Addr, TcpStream};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use webthing::{BaseActionGenerator, ListenAddress, ThingsType, WebThingServer};
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn receive_status(socket: &mut WebSocket) -> serde_json::Value {
+    let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+        .await
+        .expect("timed out waiting for a message")
+        .expect("WebSocket closed")
+        .unwrap();
+    let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(message["messageType"], "actionStatus");
+    message["data"]["fade"]["status"].clone()
+}
+
+#[actix_rt::test]
+async fn closes_websockets_and_cancels_actions() {
+    let mut server = common::single_server(common::make_lamp());
+    let shutdown = server.shutdown_handle();
+    let (url, handle) = common::serve(&mut server);
+    shutdown.set_server(handle);
+
+    let ws_url = url.replacen("http", "ws", 1);
+    let (mut socket, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+        .await
+        .unwrap();
+    let request = json!({"messageType": "requestAction", "data": {"fade": {"input": {}}}});
+    socket
+        .send(Message::text(request.to_string()))
+        .await
+        .unwrap();
+
+    let mut statuses = Vec::new();
+    while statuses.last() != Some(&json!("pending")) {
+        statuses.push(receive_status(&mut socket).await);
+    }
+
+    let mut close = None;
+    let shutting_down = actix_rt::spawn(async move { shutdown.shutdown().await });
+    while let Ok(Some(Ok(message))) =
+        tokio::time::timeout(Duration::from_secs(5), socket.next()).await
+    {
+        match message {
+            Message::Text(text) => {
+                let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+                statuses.push(message["data"]["fade"]["status"].clone());
+            }
+            Message::Close(frame) => close = frame,
+            _ => (),
+        }
+    }
+
+    assert_eq!(statuses, ["created", "pending", "cancelled"]);
+    assert_eq!(close.unwrap().code, CloseCode::Away);
+
+    shutting_down.await.unwrap();
+    let address = url.trim_start_matches("http://").trim_end_matches('/');
+    assert!(TcpStream::connect(address).is_err());
+    assert!(server.shutdown_handle().is_shutting_down());
+}
+
+fn slow(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/slow",
+        web::get().to(|| async {
+            actix_rt::time::sleep(Duration::from_millis(1500)).await;
+            "done"
+        }),
+    );
+}
+
+#[actix_rt::test]
+async fn rounds_shutdown_timeouts_up() {
+    let mut server = WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(0),
+        None,
+        None,
+        Box::new(BaseActionGenerator),
+        None,
+        Some(true),
+    )
+    .with_listen_addresses(vec![ListenAddress::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST))])
+    .with_shutdown_timeout(Duration::from_millis(1500));
+    actix_rt::spawn(server.start(Some(&slow)).unwrap());
+
+    let mut stream = tokio::net::TcpStream::connect(server.addrs()[0])
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    actix_rt::time::sleep(Duration::from_millis(50)).await;
+
+    // The request is in flight when shutting down, and finishes within the timeout, which the
+    // HTTP server would force after a second if it were cut to whole seconds.
+    let shutdown = server.shutdown_handle();
+    let shutting_down = actix_rt::spawn(async move { shutdown.shutdown().await });
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("done"), "{}", response);
+
+    shutting_down.await.unwrap();
+}
@@ -0,0 +1,136 @@
+use serde_json::json;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A simulated dimmer whose `fade` action sets its level.
+fn description() -> serde_json::Value {
+    json!({
+        "title": "Dimmer",
+        "properties": {
+            "level": {"type": "integer", "minimum": 0, "maximum": 10},
+        },
+        "actions": {
+            "fade": {"input": {"type": "object", "properties": {"level": {"type": "integer"}}}},
+        },
+    })
+}
+
+/// Write a JSON file to the temporary directory.
+fn write_json(name: &str, value: &serde_json::Value) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("webthing-sim-{}-{}", std::process::id(), name));
+    std::fs::write(&path, value.to_string()).unwrap();
+    path
+}
+
+/// Run the simulator with a configuration on a port.
+fn spawn(name: &str, config: serde_json::Value, port: u16) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_webthing-sim"))
+        .arg(write_json(&format!("{}-td.json", name), &description()))
+        .arg("--config")
+        .arg(write_json(&format!("{}-config.json", name), &config))
+        .arg("--port")
+        .arg(port.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+/// Wait for the simulator to exit, killing it if it keeps running.
+fn wait(child: &mut Child) -> Option<ExitStatus> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait().unwrap() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    child.kill().unwrap();
+    None
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn rejects_invalid_random_walks() {
+    for (config, message) in [
+        (
+            json!({"step": -1}),
+            "Invalid random walk step for level: -1",
+        ),
+        (
+            json!({"minimum": 5, "maximum": 1}),
+            "Invalid random walk bounds for level: minimum 5 is above maximum 1",
+        ),
+    ] {
+        let config = json!({"properties": {"level": {"randomWalk": config}}});
+        let mut child = spawn("walk", config, free_port());
+        let status = wait(&mut child).expect("simulator kept running");
+        let output = child.wait_with_output().unwrap();
+
+        assert_eq!(status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{}", stderr);
+    }
+}
+
+#[actix_rt::test]
+async fn cancelled_actions_leave_the_thing_alone() {
+    let port = free_port();
+    let config = json!({
+        "actions": {"fade": {"delay": 500, "set": {"level": {"input": "/level"}}}},
+    });
+    let mut child = spawn("actions", config, port);
+    let url = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::new();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while client.get(&url).send().await.is_err() {
+        assert!(Instant::now() < deadline, "simulator did not start");
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let fade = |level: i64| {
+        client
+            .post(format!("{}/actions", url))
+            .json(&json!({"fade": {"input": {"level": level}}}))
+            .send()
+    };
+    let level = || async {
+        let res = client
+            .get(format!("{}/properties/level", url))
+            .send()
+            .await
+            .unwrap();
+        res.json::<serde_json::Value>().await.unwrap()["level"].clone()
+    };
+
+    let initial = level().await;
+    let action: serde_json::Value = fade(3).await.unwrap().json().await.unwrap();
+    let href = action["fade"]["href"].as_str().unwrap();
+    let res = client
+        .delete(format!("{}{}", url, href))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 204);
+    actix_rt::time::sleep(Duration::from_millis(1000)).await;
+    assert_eq!(level().await, initial);
+
+    fade(7).await.unwrap();
+    actix_rt::time::sleep(Duration::from_millis(1000)).await;
+    assert_eq!(level().await, 7);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
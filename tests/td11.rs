@@ -0,0 +1,223 @@
+mod common;
+
+use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+use actix_web::App;
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use webthing::description::{to_td11, TD_11_CONTEXT, WEBSOCKET_SUBPROTOCOL};
+use webthing::{BaseThing, DescriptionFormat, Thing, WebThingServer};
+
+fn td11_server() -> WebThingServer {
+    common::single_server(common::make_lamp()).with_description_format(DescriptionFormat::Td11)
+}
+
+async fn get_description(server: &mut WebThingServer) -> serde_json::Value {
+    let app = init_service(App::new().configure(server.make_config())).await;
+    let req = TestRequest::get()
+        .uri("/")
+        .insert_header(("Host", "lamp.local:8888"))
+        .to_request();
+    read_body_json(call_service(&app, req).await).await
+}
+
+#[actix_rt::test]
+async fn describes_interactions_with_forms() {
+    let td = get_description(&mut td11_server()).await;
+    let ws = "ws://lamp.local:8888/";
+
+    assert_eq!(td["@context"][0], TD_11_CONTEXT);
+    assert_eq!(td["base"], "http://lamp.local:8888/");
+
+    let on = &td["properties"]["on"];
+    assert!(on.get("links").is_none());
+    assert_eq!(
+        on["forms"],
+        json!([
+            {
+                "href": "/properties/on",
+                "op": ["readproperty", "writeproperty"],
+                "contentType": "application/json",
+            },
+            {"href": ws, "op": ["observeproperty"], "subprotocol": "webthing"},
+        ])
+    );
+
+    assert_eq!(
+        td["actions"]["fade"]["forms"],
+        json!([
+            {
+                "href": "/actions/fade",
+                "op": "invokeaction",
+                "contentType": "application/json",
+            },
+            {"href": ws, "op": "invokeaction", "subprotocol": "webthing"},
+        ])
+    );
+
+    let overheated = &td["events"]["overheated"];
+    assert_eq!(overheated["data"], json!({"type": "number"}));
+    assert_eq!(
+        overheated["forms"],
+        json!([{"href": ws, "op": "subscribeevent", "subprotocol": "webthing"}])
+    );
+
+    assert_eq!(
+        td["forms"],
+        json!([
+            {
+                "href": "/properties",
+                "op": "readallproperties",
+                "contentType": "application/json",
+            },
+            {
+                "href": "/actions",
+                "op": "queryallactions",
+                "contentType": "application/json",
+            },
+            {
+                "href": ws,
+                "op": ["writemultipleproperties", "observeallproperties", "subscribeallevents"],
+                "subprotocol": "webthing",
+            },
+        ])
+    );
+
+    let rels: Vec<&str> = td["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|link| link["rel"].as_str().unwrap())
+        .collect();
+    assert_eq!(rels, ["alternate"]);
+    assert_eq!(td["links"][0]["href"], ws);
+}
+
+#[actix_rt::test]
+async fn declares_security() {
+    let td = get_description(&mut td11_server()).await;
+    assert_eq!(
+        td["securityDefinitions"],
+        json!({"nosec_sc": {"scheme": "nosec"}})
+    );
+    assert_eq!(td["security"], "nosec_sc");
+
+    // Security declared by the thing itself is kept.
+    let description = json!({
+        "title": "Lamp",
+        "securityDefinitions": {"basic_sc": {"scheme": "basic", "in": "header"}},
+        "security": ["basic_sc"],
+        "links": [],
+    });
+    let td = to_td11(
+        description.as_object().cloned().unwrap(),
+        "ws://lamp.local/",
+    );
+    assert_eq!(
+        td["securityDefinitions"],
+        json!({"basic_sc": {"scheme": "basic", "in": "header"}})
+    );
+    assert_eq!(td["security"], json!(["basic_sc"]));
+}
+
+#[test]
+fn read_only_properties_are_not_writable() {
+    let description = json!({
+        "title": "Sensor",
+        "properties": {
+            "temperature": {
+                "type": "number",
+                "readOnly": true,
+                "links": [{"rel": "property", "href": "/properties/temperature"}],
+            },
+        },
+        "links": [],
+    });
+    let td = to_td11(
+        description.as_object().cloned().unwrap(),
+        "ws://sensor.local/",
+    );
+    assert_eq!(
+        td["properties"]["temperature"]["forms"][0]["op"],
+        json!(["readproperty"])
+    );
+}
+
+#[actix_rt::test]
+async fn negotiates_the_subprotocol_named_in_forms() {
+    let (url, handle) = common::serve(&mut td11_server());
+    let mut request = url.replacen("http", "ws", 1).into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        HeaderValue::from_static(WEBSOCKET_SUBPROTOCOL),
+    );
+
+    let (_, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+        "webthing"
+    );
+
+    handle.stop(false).await;
+}
+
+#[test]
+fn imports_td11_descriptions() {
+    let td = json!({
+        "@context": [TD_11_CONTEXT, "https://webthings.io/schemas"],
+        "@type": ["OnOffSwitch"],
+        "id": "urn:dev:ops:switch",
+        "title": "Switch",
+        "securityDefinitions": {"nosec_sc": {"scheme": "nosec"}},
+        "security": "nosec_sc",
+        "properties": {
+            "on": {
+                "@type": "OnOffProperty",
+                "type": "boolean",
+                "uriVariables": {"delay": {"type": "integer"}},
+                "forms": [{"href": "/on"}],
+            },
+        },
+        "actions": {
+            "toggle": {
+                "@type": "ToggleAction",
+                "safe": false,
+                "idempotent": false,
+                "synchronous": true,
+                "forms": [{"href": "/toggle"}],
+            },
+        },
+        "events": {
+            "toggled": {
+                "data": {"type": "boolean"},
+                "subscription": {"type": "string"},
+                "forms": [{"href": "/toggled"}],
+            },
+        },
+    });
+
+    let thing = BaseThing::from_description(&td).unwrap();
+    assert_eq!(thing.validate_description(), Ok(()));
+
+    let description = thing.as_thing_description();
+    let toggle = description["actions"]["toggle"].as_object().unwrap();
+    for key in ["safe", "idempotent", "synchronous", "forms"] {
+        assert!(!toggle.contains_key(key), "{}", key);
+    }
+    assert!(description["properties"]["on"]
+        .get("uriVariables")
+        .is_none());
+    assert_eq!(description["events"]["toggled"]["type"], "boolean");
+}
+
+#[actix_rt::test]
+async fn round_trips_served_descriptions() {
+    let td = get_description(&mut td11_server()).await;
+    let thing = BaseThing::from_description(&td).unwrap();
+    assert_eq!(thing.validate_description(), Ok(()));
+
+    let mut server = common::single_server(Arc::new(RwLock::new(Box::new(thing))))
+        .with_description_format(DescriptionFormat::Td11);
+    assert_eq!(get_description(&mut server).await, td);
+}
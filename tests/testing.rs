@@ -0,0 +1,200 @@
+use serde_json::json;
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use webthing::client::ClientError;
+use webthing::server::ActionGenerator;
+use webthing::testing::{MockForwarder, TestServer};
+use webthing::{Action, BaseAction, BaseEvent, BaseProperty, BaseThing, Thing, ThingsType};
+
+const WITHIN: Duration = Duration::from_secs(2);
+
+/// Finishes actions right away, raising an event.
+struct ResetAction(BaseAction);
+
+impl Action for ResetAction {
+    fn set_href_prefix(&mut self, prefix: String) {
+        self.0.set_href_prefix(prefix)
+    }
+
+    fn get_id(&self) -> String {
+        self.0.get_id()
+    }
+
+    fn get_name(&self) -> String {
+        self.0.get_name()
+    }
+
+    fn get_href(&self) -> String {
+        self.0.get_href()
+    }
+
+    fn get_status(&self) -> String {
+        self.0.get_status()
+    }
+
+    fn get_time_requested(&self) -> String {
+        self.0.get_time_requested()
+    }
+
+    fn get_time_completed(&self) -> Option<String> {
+        self.0.get_time_completed()
+    }
+
+    fn get_input(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        self.0.get_input()
+    }
+
+    fn get_thing(&self) -> Option<Arc<RwLock<Box<dyn Thing>>>> {
+        self.0.get_thing()
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.0.set_status(status)
+    }
+
+    fn start(&mut self) {
+        self.0.start()
+    }
+
+    fn perform_action(&mut self) {
+        if let Some(thing) = self.get_thing() {
+            let (name, id) = (self.get_name(), self.get_id());
+            thread::spawn(move || {
+                let mut thing = thing.write().unwrap();
+                thing.add_event(Box::new(BaseEvent::new("reset".to_owned(), None)));
+                thing.finish_action(name, id);
+            });
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.0.cancel()
+    }
+
+    fn finish(&mut self) {
+        self.0.finish()
+    }
+}
+
+struct Generator;
+
+impl ActionGenerator for Generator {
+    fn generate(
+        &self,
+        thing: Weak<RwLock<Box<dyn Thing>>>,
+        name: String,
+        _input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+        let id = Uuid::new_v4().to_string();
+        Some(Box::new(ResetAction(BaseAction::new(
+            id, name, None, thing,
+        ))))
+    }
+}
+
+fn make_counter(forwarder: &MockForwarder) -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:counter".to_owned(),
+        "Counter".to_owned(),
+        None,
+        None,
+    );
+
+    let count = json!({"type": "integer", "minimum": 0});
+    thing.add_property(Box::new(BaseProperty::new(
+        "count".to_owned(),
+        json!(0),
+        Some(Box::new(forwarder.clone())),
+        count.as_object().cloned(),
+    )));
+
+    let level = json!({"type": "number"});
+    thing.add_property(Box::new(
+        BaseProperty::new(
+            "level".to_owned(),
+            json!(0),
+            None,
+            level.as_object().cloned(),
+        )
+        .with_async_value_forwarder(Arc::new(forwarder.clone())),
+    ));
+
+    thing.add_available_action("reset".to_owned(), serde_json::Map::new());
+    thing.add_available_event("reset".to_owned(), serde_json::Map::new());
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+#[actix_rt::test]
+async fn expects_notifications_and_action_statuses() {
+    let forwarder = MockForwarder::new();
+    let server = TestServer::builder(ThingsType::Single(make_counter(&forwarder)))
+        .with_action_generator(Box::new(Generator))
+        .start()
+        .unwrap();
+    let mut client = server.client().await.unwrap();
+
+    client.write_property("count", json!(3)).await.unwrap();
+    assert_eq!(
+        client.expect_property_status("count", WITHIN).await,
+        json!(3)
+    );
+    assert_eq!(forwarder.values(), [json!(3)]);
+
+    client.write_property("level", json!(0.5)).await.unwrap();
+    client
+        .expect_property_value("level", json!(0.5), WITHIN)
+        .await;
+    assert_eq!(forwarder.last_value(), Some(json!(0.5)));
+
+    let request = client.request_action("reset", None).await.unwrap();
+    let completed = client
+        .expect_action_status(&request, "completed", WITHIN)
+        .await;
+    assert!(completed.time_completed.is_some());
+    client.expect_event("reset", WITHIN).await;
+
+    server.stop().await;
+}
+
+#[actix_rt::test]
+async fn mock_forwarder_failures_and_delays() {
+    let forwarder = MockForwarder::new();
+    let server = TestServer::builder(ThingsType::Single(make_counter(&forwarder)))
+        .with_forwarder_timeout(Duration::from_millis(100))
+        .start()
+        .unwrap();
+    let mut client = server.client().await.unwrap();
+
+    forwarder.set_failure(Some("Device unreachable"));
+    match client.write_property("level", json!(1)).await {
+        Err(ClientError::Status(502)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    forwarder.set_failure(None);
+    forwarder.set_delay(Duration::from_millis(500));
+    match client.write_property("level", json!(2)).await {
+        Err(ClientError::Status(504)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert_eq!(forwarder.values(), [json!(1), json!(2)]);
+    client.expect_quiet(Duration::from_millis(100)).await;
+
+    server.stop().await;
+}
+
+#[actix_rt::test]
+#[should_panic(expected = "expected propertyStatus for count")]
+async fn reports_unmet_expectations() {
+    let forwarder = MockForwarder::new();
+    let server = TestServer::start(ThingsType::Single(make_counter(&forwarder))).unwrap();
+    let mut client = server.client().await.unwrap();
+
+    client
+        .expect_property_status("count", Duration::from_millis(50))
+        .await;
+}
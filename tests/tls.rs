@@ -0,0 +1,331 @@
+mod common;
+
+use actix_web::web;
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedKey, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use webthing::{
+    BaseActionGenerator, ClientIdentity, ListenAddress, MdnsMode, ThingsType, WebThingServer,
+};
+
+fn server(port: u16, ssl_options: Option<(String, String)>) -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(port),
+        None,
+        ssl_options,
+        Box::new(BaseActionGenerator),
+        None,
+        None,
+    )
+    .with_listen_addresses(vec![ListenAddress::Ip(Ipv4Addr::LOCALHOST.into())])
+    .with_mdns(MdnsMode::Disabled)
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn start_error(mut server: WebThingServer) -> io::Error {
+    match server.start(None) {
+        Ok(_) => panic!("server started"),
+        Err(err) => err,
+    }
+}
+
+fn self_signed() -> CertifiedKey {
+    rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap()
+}
+
+/// Write a certificate and its key to files named after the test.
+fn write_files(name: &str, certified: &CertifiedKey) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir();
+    let cert = dir.join(format!("webthing-{}-{}-cert.pem", std::process::id(), name));
+    let key = dir.join(format!("webthing-{}-{}-key.pem", std::process::id(), name));
+    std::fs::write(&cert, certified.cert.pem()).unwrap();
+    std::fs::write(&key, certified.key_pair.serialize_pem()).unwrap();
+    (cert, key)
+}
+
+fn whoami(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/whoami",
+        web::get().to(|identity: ClientIdentity| async move {
+            identity.common_name().unwrap_or_default().to_owned()
+        }),
+    );
+}
+
+/// GET a path over TLS, trusting only the given certificate and optionally presenting a client
+/// certificate and key.
+fn get(port: u16, cert: &str, client: Option<(&str, &str)>, path: &str) -> String {
+    let mut roots = rustls::RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_slice(cert.as_bytes()).unwrap())
+        .unwrap();
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots);
+    let config = match client {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(
+                vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+                PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+
+    let connection =
+        rustls::ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap())
+            .unwrap();
+    let tcp = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    let mut stream = rustls::StreamOwned::new(connection, tcp);
+
+    // A failed handshake surfaces here, leaving the response empty.
+    let _ = write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost:{}\r\nConnection: close\r\n\r\n",
+        path, port
+    );
+
+    // The server may close without a close_notify, which only ends the response early.
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    String::from_utf8(response).unwrap()
+}
+
+async fn get_async(port: u16, cert: &str, client: Option<(&str, &str)>, path: &str) -> String {
+    let cert = cert.to_owned();
+    let client = client.map(|(cert, key)| (cert.to_owned(), key.to_owned()));
+    let path = path.to_owned();
+    actix_rt::task::spawn_blocking(move || {
+        let client = client.as_ref().map(|(c, k)| (c.as_str(), k.as_str()));
+        get(port, &cert, client, &path)
+    })
+    .await
+    .unwrap()
+}
+
+#[actix_rt::test]
+async fn serves_in_memory_certificate() {
+    let certified = self_signed();
+    let cert = certified.cert.pem();
+    let port = free_port();
+
+    let mut server = server(port, None).with_tls_pem(
+        cert.clone().into_bytes(),
+        certified.key_pair.serialize_pem().into_bytes(),
+    );
+    let running = server.start(None).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+
+    let response = get_async(port, &cert, None, "/").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("\"title\":\"My Lamp\""), "{}", response);
+
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn reports_invalid_keys() {
+    let certified = self_signed();
+    let other = self_signed();
+    let cert = certified.cert.pem().into_bytes();
+
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!("webthing-{}-cert.pem", std::process::id()));
+    let key_path = dir.join(format!("webthing-{}-key.pem", std::process::id()));
+    std::fs::write(&cert_path, &cert).unwrap();
+    std::fs::write(&key_path, b"not a key").unwrap();
+
+    let files = Some((
+        key_path.to_string_lossy().into_owned(),
+        cert_path.to_string_lossy().into_owned(),
+    ));
+    let err = start_error(server(free_port(), files));
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err);
+
+    let missing = Some((
+        dir.join("webthing-missing-key.pem")
+            .to_string_lossy()
+            .into_owned(),
+        cert_path.to_string_lossy().into_owned(),
+    ));
+    let err = start_error(server(free_port(), missing));
+    assert_eq!(err.kind(), io::ErrorKind::NotFound, "{}", err);
+
+    let err = start_error(
+        server(free_port(), None).with_tls_pem(cert, other.key_pair.serialize_pem().into_bytes()),
+    );
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err);
+
+    let _ = std::fs::remove_file(cert_path);
+    let _ = std::fs::remove_file(key_path);
+}
+
+#[actix_rt::test]
+async fn reloads_renewed_certificate() {
+    let old = self_signed();
+    let new = self_signed();
+    let (cert_path, key_path) = write_files("reload", &old);
+    let port = free_port();
+
+    let files = Some((
+        key_path.to_string_lossy().into_owned(),
+        cert_path.to_string_lossy().into_owned(),
+    ));
+    let mut server = server(port, files).with_tls_reload_interval(Duration::from_millis(50));
+    let tls = server.tls_handle();
+    let running = server.start(None).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+
+    let response = get_async(port, &old.cert.pem(), None, "/").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    // A mismatched key is refused, keeping the current certificate.
+    std::fs::write(&key_path, new.key_pair.serialize_pem()).unwrap();
+    let err = tls.reload().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", err);
+    let response = get_async(port, &old.cert.pem(), None, "/").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    // Once both files are renewed, the watcher picks them up.
+    std::fs::write(&cert_path, new.cert.pem()).unwrap();
+    let mut response = String::new();
+    for _ in 0..50 {
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        response = get_async(port, &new.cert.pem(), None, "/").await;
+        if !response.is_empty() {
+            break;
+        }
+    }
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(get_async(port, &old.cert.pem(), None, "/").await.is_empty());
+
+    // Certificates held in memory can be swapped in as well.
+    tls.set_pem(
+        old.cert.pem().into_bytes(),
+        old.key_pair.serialize_pem().into_bytes(),
+    )
+    .unwrap();
+    let response = get_async(port, &old.cert.pem(), None, "/").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    handle.stop(false).await;
+    let _ = std::fs::remove_file(cert_path);
+    let _ = std::fs::remove_file(key_path);
+}
+
+#[actix_rt::test]
+async fn requires_client_certificates() {
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "Test CA");
+    let ca_key = KeyPair::generate().unwrap();
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+
+    let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "sensor-1");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_key = KeyPair::generate().unwrap();
+    let client_cert = client_params
+        .signed_by(&client_key, &ca, &ca_key)
+        .unwrap()
+        .pem();
+    let client_key = client_key.serialize_pem();
+
+    let certified = self_signed();
+    let cert = certified.cert.pem();
+    let port = free_port();
+
+    let mut server = server(port, None)
+        .with_tls_pem(
+            cert.clone().into_bytes(),
+            certified.key_pair.serialize_pem().into_bytes(),
+        )
+        .with_tls_client_ca(ca.pem().into_bytes());
+    let running = server.start(Some(&whoami)).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+
+    let response = get_async(port, &cert, Some((&client_cert, &client_key)), "/whoami").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("sensor-1"), "{}", response);
+
+    // Clients without a certificate, or with one from another CA, fail the handshake.
+    assert!(get_async(port, &cert, None, "/").await.is_empty());
+    let stranger = self_signed();
+    let stranger_key = stranger.key_pair.serialize_pem();
+    let response = get_async(
+        port,
+        &cert,
+        Some((&stranger.cert.pem(), &stranger_key)),
+        "/",
+    )
+    .await;
+    assert!(response.is_empty(), "{}", response);
+
+    handle.stop(false).await;
+}
+
+#[cfg(feature = "self-signed")]
+#[actix_rt::test]
+async fn generates_self_signed_certificate() {
+    let dir = std::env::temp_dir().join(format!("webthing-{}-self-signed", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let port = free_port();
+    let mut first = server(port, None).with_self_signed_certificate(dir.clone());
+    let running = first.start(None).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+
+    let cert = std::fs::read_to_string(dir.join("cert.pem")).unwrap();
+    let response = get_async(port, &cert, None, "/").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    handle.stop(false).await;
+
+    // The certificate is kept while it covers the same names, and renewed when they change.
+    let mut restarted = server(free_port(), None).with_self_signed_certificate(dir.clone());
+    drop(restarted.start(None).unwrap());
+    assert_eq!(std::fs::read_to_string(dir.join("cert.pem")).unwrap(), cert);
+
+    let mut renamed = WebThingServer::new(
+        ThingsType::Single(common::make_lamp()),
+        Some(free_port()),
+        Some("thing.example".to_owned()),
+        None,
+        Box::new(BaseActionGenerator),
+        None,
+        None,
+    )
+    .with_listen_addresses(vec![ListenAddress::Ip(Ipv4Addr::LOCALHOST.into())])
+    .with_mdns(MdnsMode::Disabled)
+    .with_self_signed_certificate(dir.clone());
+    drop(renamed.start(None).unwrap());
+    assert_ne!(std::fs::read_to_string(dir.join("cert.pem")).unwrap(), cert);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use serde_json::json;
+use std::io;
+use std::sync::{Arc, RwLock};
+use webthing::{
+    BaseProperty, BaseThing, DescriptionValidation, ListenAddress, Thing, ThingsType,
+    WebThingServer,
+};
+
+/// A lamp whose brightness has a misspelled `minimum`.
+fn make_broken_lamp() -> Arc<RwLock<Box<dyn Thing>>> {
+    let mut thing = BaseThing::new(
+        "urn:dev:ops:broken-lamp".to_owned(),
+        "Broken Lamp".to_owned(),
+        None,
+        None,
+    );
+
+    let brightness = json!({"type": "integer", "minimun": 0, "maximum": 100});
+    thing.add_property(Box::new(BaseProperty::new(
+        "brightness".to_owned(),
+        json!(50),
+        None,
+        brightness.as_object().cloned(),
+    )));
+
+    Arc::new(RwLock::new(Box::new(thing)))
+}
+
+fn make_server(
+    things: Vec<Arc<RwLock<Box<dyn Thing>>>>,
+    validation: DescriptionValidation,
+) -> WebThingServer {
+    WebThingServer::new(
+        ThingsType::Multiple(things, "Things".to_owned()),
+        Some(0),
+        None,
+        None,
+        Box::new(common::Generator),
+        None,
+        Some(true),
+    )
+    .with_listen_addresses(vec![ListenAddress::Ip([127, 0, 0, 1].into())])
+    .with_description_validation(validation)
+}
+
+#[test]
+fn reports_problems_with_the_thing_id() {
+    let server = make_server(
+        vec![common::make_lamp(), make_broken_lamp()],
+        DescriptionValidation::Disabled,
+    );
+
+    let problems = server.validate_descriptions();
+    assert!(!problems.is_empty());
+    assert!(
+        problems
+            .iter()
+            .all(|problem| problem.starts_with("urn:dev:ops:broken-lamp: ")),
+        "{:?}",
+        problems
+    );
+    assert!(
+        problems
+            .iter()
+            .any(|problem| problem.contains("/properties/brightness")),
+        "{:?}",
+        problems
+    );
+}
+
+#[actix_rt::test]
+async fn strict_validation_refuses_to_start() {
+    let mut server = make_server(
+        vec![common::make_lamp(), make_broken_lamp()],
+        DescriptionValidation::Strict,
+    );
+    let err = server
+        .start(None)
+        .err()
+        .expect("started with an invalid description");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(
+        err.to_string().contains("urn:dev:ops:broken-lamp"),
+        "{}",
+        err
+    );
+
+    let mut server = make_server(vec![common::make_lamp()], DescriptionValidation::Strict);
+    let running = server.start(None).unwrap();
+    let handle = running.handle();
+    actix_rt::spawn(running);
+    handle.stop(false).await;
+}
+
+#[actix_rt::test]
+async fn warn_and_disabled_validation_start_anyway() {
+    for validation in [DescriptionValidation::Warn, DescriptionValidation::Disabled] {
+        let mut server = make_server(vec![make_broken_lamp()], validation);
+        let running = server.start(None).unwrap();
+        let handle = running.handle();
+        actix_rt::spawn(running);
+        handle.stop(false).await;
+    }
+}